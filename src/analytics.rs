@@ -0,0 +1,183 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use serde::Serialize;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static COUNTERS: Mutex<Counters> = Mutex::new(Counters::new());
+
+#[derive(Debug, Clone, PartialEq)]
+struct Counters {
+    turns: u32,
+    latency_total_ms: u64,
+    latency_samples: u32,
+    max_latency_ms: u32,
+    interrupts: u32,
+    errors: u32,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            turns: 0,
+            latency_total_ms: 0,
+            latency_samples: 0,
+            max_latency_ms: 0,
+            interrupts: 0,
+            errors: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::new()
+    }
+
+    fn merge(&mut self, other: &Counters) {
+        self.turns += other.turns;
+        self.latency_total_ms += other.latency_total_ms;
+        self.latency_samples += other.latency_samples;
+        self.max_latency_ms = self.max_latency_ms.max(other.max_latency_ms);
+        self.interrupts += other.interrupts;
+        self.errors += other.errors;
+    }
+}
+
+/// One batch as POSTed (JSON) to `{server}/api/analytics`, where `{server}` is
+/// the HTTP origin of the websocket server URL (see `network::convert_ws_to_http`).
+///
+/// Only counts and timings are sent, never audio, ASR text or replies.
+#[derive(Debug, Serialize)]
+pub struct AnalyticsReport {
+    #[serde(flatten)]
+    pub ident: crate::network::DeviceIdent,
+    /// Device uptime when the batch was sent.
+    pub uptime_secs: u64,
+    /// Time since the previous batch was taken.
+    pub period_secs: u64,
+    /// Completed turns (`EndResponse` received).
+    pub turns: u32,
+    /// Mean time from the server's `EndVad` to `StartAudio`, `null` without samples.
+    pub avg_latency_ms: Option<u32>,
+    pub max_latency_ms: u32,
+    /// Times the user talked over a reply (voice interrupt).
+    pub interrupts: u32,
+    /// Failed reconnect cycles and playback errors.
+    pub errors: u32,
+}
+
+fn record(f: impl FnOnce(&mut Counters)) {
+    if ENABLED.load(Ordering::Relaxed) {
+        if let Ok(mut counters) = COUNTERS.lock() {
+            f(&mut counters);
+        }
+    }
+}
+
+pub fn record_turn() {
+    record(|c| c.turns += 1);
+}
+
+pub fn record_latency(latency: std::time::Duration) {
+    let ms = latency.as_millis().min(u32::MAX as u128) as u32;
+    record(|c| {
+        c.latency_total_ms += ms as u64;
+        c.latency_samples += 1;
+        c.max_latency_ms = c.max_latency_ms.max(ms);
+    });
+}
+
+pub fn record_interrupt() {
+    record(|c| c.interrupts += 1);
+}
+
+pub fn record_error() {
+    record(|c| c.errors += 1);
+}
+
+/// Enables collection and starts the thread that sends a batch every `interval`.
+///
+/// Empty batches are skipped. A batch that fails to send is merged back and
+/// retried with the next one; a batch the server rejects is dropped.
+pub fn start(
+    device_id: String,
+    server_url: &str,
+    interval: std::time::Duration,
+) -> anyhow::Result<()> {
+    let base = crate::network::convert_ws_to_http(server_url)
+        .ok_or_else(|| anyhow::anyhow!("Invalid server URL for analytics: {}", server_url))?;
+    let url = format!("{}/api/analytics", base);
+    let ident = crate::network::DeviceIdent::new(device_id);
+
+    ENABLED.store(true, Ordering::Relaxed);
+
+    let boot = std::time::Instant::now();
+    let mut period_start = std::time::Instant::now();
+    // the batch of the previous post and when it was taken; merged back if it
+    // failed to send
+    let mut sent = Counters::new();
+    let mut sent_at = std::time::Instant::now();
+
+    crate::network::spawn_periodic_post(
+        "analytics",
+        8 * 1024,
+        move || interval,
+        url,
+        move |last| {
+            match last {
+                // accepted, or rejected: the server doesn't want it, retrying won't change that
+                Some(Ok(_)) => period_start = sent_at,
+                Some(Err(_)) => COUNTERS.lock().unwrap().merge(&sent),
+                None => {}
+            }
+
+            let counters = std::mem::replace(&mut *COUNTERS.lock().unwrap(), Counters::new());
+            if counters.is_empty() {
+                return None;
+            }
+
+            let report = AnalyticsReport {
+                ident: ident.clone(),
+                uptime_secs: boot.elapsed().as_secs(),
+                period_secs: period_start.elapsed().as_secs(),
+                turns: counters.turns,
+                avg_latency_ms: (counters.latency_samples > 0)
+                    .then(|| (counters.latency_total_ms / counters.latency_samples as u64) as u32),
+                max_latency_ms: counters.max_latency_ms,
+                interrupts: counters.interrupts,
+                errors: counters.errors,
+            };
+            sent = counters;
+            sent_at = std::time::Instant::now();
+            Some(report)
+        },
+    )
+}
+
+#[test]
+fn test_analytics_report_schema() {
+    let report = AnalyticsReport {
+        ident: crate::network::DeviceIdent::new("a1b2c3d4e5f6".to_string()),
+        uptime_secs: 3600,
+        period_secs: 600,
+        turns: 12,
+        avg_latency_ms: Some(850),
+        max_latency_ms: 1900,
+        interrupts: 2,
+        errors: 1,
+    };
+
+    assert_eq!(
+        crate::network::report_fields(&report),
+        serde_json::json!({
+            "uptime_secs": 3600,
+            "period_secs": 600,
+            "turns": 12,
+            "avg_latency_ms": 850,
+            "max_latency_ms": 1900,
+            "interrupts": 2,
+            "errors": 1,
+        })
+    );
+}
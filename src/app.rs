@@ -3,8 +3,9 @@ use std::sync::Arc;
 use tokio::sync::mpsc;
 
 use crate::{
+    analytics,
     audio::{self, AudioEvent, EventRx},
-    protocol::{self, ServerEvent},
+    protocol::{self, ServerEvent, TextArea},
     ui::DisplayTargetDrive,
     ws::Server,
 };
@@ -30,14 +31,27 @@ impl Event {
     pub const UNKNOWN: &'static str = "unknown";
     pub const K0: &'static str = "k0";
     pub const K0_: &'static str = "k0_";
+    /// Two short K0 presses within the configured window, replays the last response.
+    pub const K0_DOUBLE: &'static str = "k0_double";
+    /// K0 pressed and released in push-to-talk mode, see `AppConfig::push_to_talk`.
+    pub const K0_DOWN: &'static str = "k0_down";
+    pub const K0_UP: &'static str = "k0_up";
+    /// WakeNet heard the wake phrase (`wakeword` feature), starts a turn like K0.
+    pub const WAKE_WORD: &'static str = "wake_word";
 
     pub const K1: &'static str = "k1";
     pub const K2: &'static str = "k2";
     pub const VOL_UP: &'static str = "vol_up";
     pub const VOL_DOWN: &'static str = "vol_down";
     pub const VOL_SWITCH: &'static str = "vol_switch";
+    /// Toggles mute, also sent for VOL_UP and VOL_DOWN pressed together.
+    pub const MUTE: &'static str = "mute";
 
     pub const NOTIFY: &'static str = "notify";
+    /// The websocket ended without the device closing it, see `Server::recv`.
+    pub const SERVER_CLOSED: &'static str = "server_closed";
+    /// The NFC tag last read left the reader (`mfrc522` feature).
+    pub const CARD_REMOVED: &'static str = "card_removed";
 }
 
 async fn select_evt(
@@ -114,6 +128,11 @@ struct DownloadMetrics {
     start_time: std::time::Instant,
     data_size: usize,
     timeout_sec: u64,
+    /// Per turn, for `ClientCommand::Metrics`: when speech ended, when the
+    /// first audio chunk arrived after it and how many audio bytes arrived.
+    submit_time: Option<std::time::Instant>,
+    first_chunk_time: Option<std::time::Instant>,
+    total_bytes: u64,
 }
 
 impl DownloadMetrics {
@@ -122,9 +141,30 @@ impl DownloadMetrics {
             start_time: std::time::Instant::now() - std::time::Duration::from_secs(300),
             data_size: 0,
             timeout_sec: 15,
+            submit_time: None,
+            first_chunk_time: None,
+            total_bytes: 0,
         }
     }
 
+    /// Starts the per-turn counters when speech ended at `now`.
+    fn start_turn(&mut self, now: std::time::Instant) {
+        self.submit_time = Some(now);
+        self.first_chunk_time = None;
+        self.total_bytes = 0;
+    }
+
+    fn record_chunk(&mut self, now: std::time::Instant, size: usize) {
+        self.first_chunk_time.get_or_insert(now);
+        self.total_bytes += size as u64;
+    }
+
+    /// Milliseconds from `start_turn` to the first chunk.
+    fn first_audio_ms(&self) -> Option<u64> {
+        let (submit, first) = (self.submit_time?, self.first_chunk_time?);
+        Some(first.saturating_duration_since(submit).as_millis() as u64)
+    }
+
     fn is_timeout(&self) -> bool {
         self.start_time.elapsed().as_secs() > self.timeout_sec
     }
@@ -142,13 +182,90 @@ impl DownloadMetrics {
         self.start_time.elapsed()
     }
 
-    fn speed(&self) -> f64 {
-        self.elapsed().as_secs_f64() / ((self.data_size as f64) / 32000.0)
+    /// Whether enough audio has arrived for `speed` to be meaningful.
+    fn has_enough_data(&self) -> bool {
+        self.data_size >= MIN_SPEED_DATA_SIZE
+    }
+
+    /// Download time relative to playback time of the received audio (16kHz,
+    /// 16-bit), `None` when too little audio arrived to measure, e.g. an
+    /// action-only turn.
+    fn speed(&self) -> Option<f64> {
+        if !self.has_enough_data() {
+            return None;
+        }
+        Some(self.elapsed().as_secs_f64() / ((self.data_size as f64) / 32000.0))
+    }
+}
+
+#[test]
+fn test_download_metrics_speed_without_data() {
+    let mut metrics = DownloadMetrics::new();
+    assert_eq!(metrics.speed(), None);
+
+    metrics.reset();
+    metrics.add_data(MIN_SPEED_DATA_SIZE - 1);
+    assert!(!metrics.has_enough_data());
+    assert_eq!(metrics.speed(), None);
+
+    metrics.add_data(1);
+    assert!(metrics.has_enough_data());
+    assert!(metrics.speed().unwrap().is_finite());
+}
+
+#[test]
+fn test_download_metrics_first_audio() {
+    let t0 = std::time::Instant::now();
+    let at = |ms: u64| t0 + std::time::Duration::from_millis(ms);
+    let mut metrics = DownloadMetrics::new();
+    assert_eq!(metrics.first_audio_ms(), None);
+
+    metrics.start_turn(at(0));
+    assert_eq!(metrics.first_audio_ms(), None);
+    metrics.record_chunk(at(850), 1000);
+    metrics.record_chunk(at(900), 2000);
+    assert_eq!(metrics.first_audio_ms(), Some(850));
+    assert_eq!(metrics.total_bytes, 3000);
+
+    metrics.start_turn(at(2000));
+    assert_eq!(metrics.first_audio_ms(), None);
+    assert_eq!(metrics.total_bytes, 0);
+}
+
+/// State text while Idle, with the local time once SNTP has synced.
+fn idle_state(config: &AppConfig) -> String {
+    format!(
+        "Idle {}",
+        crate::sntp::format_clock(std::time::SystemTime::now(), config.utc_offset_minutes)
+    )
+}
+
+/// Bundled avatar for a `ServerEvent::Emotion` name, `None` for the default.
+fn emotion_image(name: &str) -> Option<&'static [u8]> {
+    match name {
+        "happy" => Some(include_bytes!("../assets/emotion_happy.png")),
+        "thinking" => Some(include_bytes!("../assets/emotion_thinking.png")),
+        "listening" => Some(include_bytes!("../assets/emotion_listening.png")),
+        _ => None,
     }
 }
 
 const SPEED_LIMIT: f64 = 1.0;
+/// 0.5s of 16kHz 16-bit audio; less than this is too short to time reliably.
+const MIN_SPEED_DATA_SIZE: usize = 16000;
+/// Entries of the turn log K1 shows on screen; all of them go to the log.
+const TURN_LOG_SHOWN: usize = 6;
+/// How often the WiFi signal in the state area is refreshed.
+const WIFI_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
 const NORMAL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60 * 5);
+/// Longest response kept for `K0_DOUBLE` replay, 20s at 16kHz. Longer responses
+/// are not kept at all rather than replayed truncated.
+const MAX_REPLAY_SAMPLES: usize = 16000 * 20;
+/// No chunk is sent before this much of the turn is buffered, whatever
+/// `AppConfig::submit_chunk_secs` says.
+const MIN_CHUNK_STREAM_SECS: f32 = 0.3;
+/// A turn without an ASR result after this much audio is ended.
+const MAX_SECS_WITHOUT_ASR: f32 = 10.0;
 
 struct SubmitState {
     submit_audio: f32,
@@ -166,14 +283,285 @@ impl SubmitState {
     }
 }
 
+/// Behaviour switches for `main_work`, loaded from NVS at boot.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// Keep the websocket open when going Idle instead of closing it.
+    ///
+    /// An open connection costs a little power and holds a server slot, but the
+    /// next turn skips the reconnect handshake. While idle, `ws_manager` keeps
+    /// polling the socket so server pings are still answered; if the connection
+    /// drops anyway, the next K0 reconnects as usual.
+    pub keep_connection_on_idle: bool,
+
+    /// Give up (and let `main` reboot) after this many consecutive failed
+    /// reconnect cycles, see `ReconnectGuard`. 1 gives up on the first failure.
+    pub max_reconnect_failures: u32,
+    /// Failed cycles further apart than this don't count as consecutive.
+    pub reconnect_failure_window: std::time::Duration,
+
+    /// Once a turn is submitted (Waiting), give up on it when no `ServerEvent`
+    /// arrives for this long: the turn is cancelled, the connection closed and
+    /// the device goes Idle with an error. Unrelated to `NORMAL_TIMEOUT`, which
+    /// only ends Listening.
+    pub response_timeout: std::time::Duration,
+
+    /// A turn is only started (`StartChat`) once this much mic audio is buffered.
+    /// Shorter triggers that end before it are dropped without contacting the
+    /// server. The count includes the pre-roll `afe_worker` replays from its
+    /// cache when speech starts (up to ~0.5s).
+    pub min_submit_secs: f32,
+    /// Minimum `audio::mean_amplitude` of the buffered audio, pre-roll included,
+    /// at the point `min_submit_secs` is reached. Quieter triggers are dropped.
+    /// 0 disables the check.
+    pub min_submit_amplitude: u16,
+    /// Once a turn has started, mic audio is sent in chunks of this much.
+    /// Shorter chunks reach ASR sooner at the cost of more, smaller frames.
+    pub submit_chunk_secs: f32,
+
+    /// With voice interrupt on, speech during playback interrupts it once this
+    /// much has been buffered.
+    pub min_interrupt_secs: f32,
+    /// Minimum `audio::mean_amplitude` of that speech, so playback leaking past
+    /// AEC doesn't interrupt itself. 0 disables the check.
+    pub min_interrupt_amplitude: u16,
+
+    /// Local time offset from UTC for the clock shown while Idle, in minutes.
+    pub utc_offset_minutes: i32,
+    /// Quiet hours as local `(start, end)` hours, see `sntp::in_hours`. Audio
+    /// the server starts while Idle is not played then, only its text shown.
+    /// Replies to the user's own turns are played as usual.
+    pub quiet_hours: Option<(u8, u8)>,
+
+    /// Backlight level (0..=100) while in use.
+    pub backlight_level: u8,
+    /// Backlight level after `backlight_dim_after` Idle with no events.
+    pub backlight_dim_level: u8,
+    /// `None` never dims.
+    pub backlight_dim_after: Option<std::time::Duration>,
+
+    /// Push-to-talk: `main` sends `K0_DOWN`/`K0_UP` instead of the tap and hold
+    /// events. Mic audio is only streamed while K0 is held, whatever the VAD
+    /// says, and releasing it submits the turn. Off keeps the VAD behaviour.
+    pub push_to_talk: bool,
+
+    /// `main` set a stored wake sound with `AudioEvent::SetHello`, so the
+    /// server's hello audio doesn't replace it.
+    pub custom_hello: bool,
+
+    /// Button events (`Event::K1`, `Event::VOL_UP`, ...) also sent to the
+    /// server as `ClientCommand::Button` while connected, for server-side
+    /// actions. They are still handled locally as well.
+    pub forward_buttons: Vec<String>,
+
+    /// After this long Idle with no events (including from the server), pause
+    /// the audio worker and let `power` clock the CPU down and light sleep, until
+    /// the next event. `None` never does.
+    pub power_save_after: Option<std::time::Duration>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            keep_connection_on_idle: false,
+            max_reconnect_failures: 3,
+            reconnect_failure_window: std::time::Duration::from_secs(5 * 60),
+            response_timeout: std::time::Duration::from_secs(30),
+            min_submit_secs: 0.5,
+            min_submit_amplitude: 0,
+            submit_chunk_secs: 0.512,
+            min_interrupt_secs: 0.6,
+            min_interrupt_amplitude: 0,
+            utc_offset_minutes: 8 * 60,
+            quiet_hours: None,
+            backlight_level: 70,
+            backlight_dim_level: 10,
+            backlight_dim_after: Some(std::time::Duration::from_secs(60)),
+            push_to_talk: false,
+            custom_hello: false,
+            forward_buttons: Vec::new(),
+            power_save_after: None,
+        }
+    }
+}
+
+/// Bounds how long `main_work` keeps retrying a server that won't come back.
+///
+/// A reconnect cycle is one `reconnect_with_retry` call that ran out of attempts.
+/// Failed cycles are consecutive when no turn completed (`EndResponse`) between
+/// them and they all fall within `window` of the first failure of the streak; a
+/// failure after the window has passed starts a new streak.
+struct ReconnectGuard {
+    max_failures: u32,
+    window: std::time::Duration,
+    failures: u32,
+    first_failure: Option<std::time::Instant>,
+}
+
+impl ReconnectGuard {
+    fn new(max_failures: u32, window: std::time::Duration) -> Self {
+        Self {
+            max_failures: max_failures.max(1),
+            window,
+            failures: 0,
+            first_failure: None,
+        }
+    }
+
+    /// Records a failed cycle, returns true once the limit is reached.
+    fn record_failure(&mut self) -> bool {
+        let now = std::time::Instant::now();
+        match self.first_failure {
+            Some(first) if now.duration_since(first) <= self.window => {
+                self.failures += 1;
+            }
+            _ => {
+                self.first_failure = Some(now);
+                self.failures = 1;
+            }
+        }
+        self.failures >= self.max_failures
+    }
+
+    fn reset(&mut self) {
+        self.failures = 0;
+        self.first_failure = None;
+    }
+}
+
+/// VOL_UP and VOL_DOWN within this of each other count as pressed together.
+const MUTE_CHORD_WINDOW: std::time::Duration = std::time::Duration::from_millis(400);
+
+/// Turns VOL_UP and VOL_DOWN pressed together into a mute toggle. The first
+/// press of the pair has already stepped the volume by the time the second
+/// arrives, so the level from before it is handed back to be restored.
+struct VolumeChord {
+    last: Option<(&'static str, std::time::Instant, u8)>,
+}
+
+impl VolumeChord {
+    fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Records `button` pressed at `now` while the level was `vol`. Returns the
+    /// level to restore when this press completes a chord.
+    fn press(&mut self, button: &'static str, now: std::time::Instant, vol: u8) -> Option<u8> {
+        match self.last.take() {
+            Some((other, at, before))
+                if other != button && now.duration_since(at) < MUTE_CHORD_WINDOW =>
+            {
+                Some(before)
+            }
+            _ => {
+                self.last = Some((button, now, vol));
+                None
+            }
+        }
+    }
+}
+
+#[test]
+fn test_volume_chord() {
+    let t0 = std::time::Instant::now();
+    let at = |ms: u64| t0 + std::time::Duration::from_millis(ms);
+    let mut chord = VolumeChord::new();
+
+    assert_eq!(chord.press(Event::VOL_UP, at(0), 3), None);
+    assert_eq!(chord.press(Event::VOL_DOWN, at(100), 4), Some(3));
+    // a chord is consumed, the next press starts over
+    assert_eq!(chord.press(Event::VOL_UP, at(200), 3), None);
+
+    // same button twice is just two steps
+    assert_eq!(chord.press(Event::VOL_UP, at(300), 4), None);
+    // too late
+    assert_eq!(chord.press(Event::VOL_DOWN, at(800), 5), None);
+    assert_eq!(chord.press(Event::VOL_UP, at(900), 4), Some(5));
+}
+
+/// Decides when the backlight dims: after `dim_after` without activity. Also
+/// times `AppConfig::power_save_after`.
+struct IdleDimmer {
+    dim_after: Option<std::time::Duration>,
+    last_activity: std::time::Instant,
+    dimmed: bool,
+}
+
+impl IdleDimmer {
+    fn new(dim_after: Option<std::time::Duration>, now: std::time::Instant) -> Self {
+        Self {
+            dim_after,
+            last_activity: now,
+            dimmed: false,
+        }
+    }
+
+    /// Records activity at `now`. Returns whether the backlight was dimmed and
+    /// has to be restored.
+    fn wake(&mut self, now: std::time::Instant) -> bool {
+        self.last_activity = now;
+        std::mem::take(&mut self.dimmed)
+    }
+
+    /// How long until the backlight should dim, `None` if it already is or
+    /// dimming is off.
+    fn until_dim(&self, now: std::time::Instant) -> Option<std::time::Duration> {
+        if self.dimmed {
+            return None;
+        }
+        self.dim_after
+            .map(|d| d.saturating_sub(now.duration_since(self.last_activity)))
+    }
+
+    /// Returns true once per inactive period, when the backlight should dim.
+    fn poll(&mut self, now: std::time::Instant) -> bool {
+        if self.until_dim(now) == Some(std::time::Duration::ZERO) {
+            self.dimmed = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[test]
+fn test_idle_dimmer() {
+    let t0 = std::time::Instant::now();
+    let at = |secs: u64| t0 + std::time::Duration::from_secs(secs);
+    let mut dimmer = IdleDimmer::new(Some(std::time::Duration::from_secs(60)), t0);
+
+    assert_eq!(
+        dimmer.until_dim(at(20)),
+        Some(std::time::Duration::from_secs(40))
+    );
+    assert!(!dimmer.poll(at(59)));
+    assert!(dimmer.poll(at(60)));
+    // only once
+    assert!(!dimmer.poll(at(61)));
+    assert_eq!(dimmer.until_dim(at(61)), None);
+
+    assert!(dimmer.wake(at(70)));
+    assert!(!dimmer.wake(at(80)));
+    assert!(!dimmer.poll(at(139)));
+    assert!(dimmer.poll(at(140)));
+
+    let mut never = IdleDimmer::new(None, t0);
+    assert_eq!(never.until_dim(at(1000)), None);
+    assert!(!never.poll(at(1000)));
+    assert!(!never.wake(at(1000)));
+}
+
 pub async fn main_work<'d, const N: usize>(
+    config: AppConfig,
     mut server: Server,
     player_tx: audio::PlayerTx,
     mut evt_rx: EventRx,
     framebuffer: &mut crate::boards::ui::DisplayBuffer,
     gui: &mut crate::boards::ui::ChatUI<N>,
+    prefs: tokio::sync::watch::Sender<crate::config::DeviceConfig>,
+    mut idle_gif: Option<crate::ui::GifLoop>,
 ) -> anyhow::Result<()> {
-    #[derive(PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     enum State {
         Listening,
         Waiting,
@@ -181,17 +569,22 @@ pub async fn main_work<'d, const N: usize>(
         Idle,
     }
 
-    gui.set_state("Idle".to_string());
+    let mut allow_interrupt = cfg!(feature = "voice_interrupt") && prefs.borrow().allow_interrupt;
+
+    gui.set_state(idle_state(&config));
     gui.set_text("".to_string());
+    gui.set_interrupt_indicator(allow_interrupt);
     gui.render_to_target(framebuffer)?;
     framebuffer.flush()?;
 
     let mut state = State::Idle;
 
+    let submit_chunk_samples =
+        ((config.submit_chunk_secs * audio::SAMPLE_RATE as f32) as usize).max(1);
     let mut submit_state = SubmitState {
         submit_audio: 0.0,
         start_submit: false,
-        audio_buffer: Vec::with_capacity(8192),
+        audio_buffer: Vec::with_capacity(submit_chunk_samples),
         got_asr_result: false,
     };
 
@@ -201,36 +594,352 @@ pub async fn main_work<'d, const N: usize>(
     let mut need_compute = true;
     let mut start_audio = false;
     let mut speed = 0.5;
-    let mut vol = 3u8;
-
+    // saved levels outside the 1..=5 button range (corrupt or hand-edited) are clamped
+    let mut vol = prefs.borrow().volume.clamp(1, 5);
+    let mut muted = false;
+    let mut volume_chord = VolumeChord::new();
+    player_tx
+        .send(AudioEvent::VolSet(vol))
+        .map_err(|e| anyhow::anyhow!("Error sending volume set: {e:?}"))?;
+
+    // Hello audio is only accepted between a HelloStart and a HelloEnd on the same
+    // connection. Anything buffered when the connection is replaced (reconnect or
+    // ServerUrl change) is discarded, so a HelloEnd that never arrived can't leave
+    // stale data behind, and a HelloEnd without a HelloStart is ignored.
     let mut hello_wav = Vec::with_capacity(1024 * 30);
+    let mut hello_receiving = false;
 
     let notify: Arc<tokio::sync::Notify> = Arc::new(tokio::sync::Notify::new());
     let mut wait_notify = false;
-    let mut init_hello = false;
-    let mut allow_interrupt = false;
+    let mut init_hello = config.custom_hello;
     let timeout = NORMAL_TIMEOUT;
+    let mut vad_end_at: Option<std::time::Instant> = None;
+    // PCM of the last complete response, `None` until one fits in MAX_REPLAY_SAMPLES
+    let mut last_response: Option<Vec<i16>> = None;
+    let mut response_audio: Vec<i16> = Vec::new();
+    let mut replaying = false;
+    let mut reconnect_guard = ReconnectGuard::new(
+        config.max_reconnect_failures,
+        config.reconnect_failure_window,
+    );
+    let mut dimmer = IdleDimmer::new(config.backlight_dim_after, std::time::Instant::now());
+    let mut power_saver = IdleDimmer::new(config.power_save_after, std::time::Instant::now());
+    let mut power_save: Option<crate::power::PowerSave> = None;
+    // K0 is held in push-to-talk mode
+    let mut ptt_held = false;
+    // a frame of `idle_gif` is on screen in place of the chat background
+    let mut idle_gif_shown = false;
+    // between `StartVideo` and `EndVideo`, frames are drawn instead of the chat
+    let mut video_shown = false;
+    // a server-initiated response is being shown silently during quiet hours
+    let mut quiet_response = false;
+    let mut wifi_polled_at: Option<std::time::Instant> = None;
+    // while Waiting, when to give up on the server, pushed back by each `ServerEvent`
+    let mut response_deadline: Option<std::time::Instant> = None;
+    // last state written to the turn log
+    let mut logged_state: Option<State> = None;
+    // when the select would have timed out, after it ended early for a scroll tick
+    let mut scroll_tick_deadline: Option<std::time::Instant> = None;
+
+    loop {
+        if state != State::Waiting {
+            response_deadline = None;
+        } else if response_deadline.is_none() {
+            response_deadline = Some(std::time::Instant::now() + config.response_timeout);
+        }
+
+        if logged_state != Some(state) {
+            logged_state = Some(state);
+            crate::telemetry::log_turn(format_args!("state {:?}", state));
+        }
+        crate::telemetry::set_state(match state {
+            State::Idle => crate::telemetry::IDLE,
+            State::Listening => crate::telemetry::LISTENING,
+            State::Waiting => crate::telemetry::WAITING,
+            State::Speaking => crate::telemetry::SPEAKING,
+        });
+        #[cfg(feature = "exio")]
+        crate::boards::set_status_led(match state {
+            State::Idle => crate::boards::StatusLed::Off,
+            State::Listening | State::Waiting => crate::boards::StatusLed::Pulse,
+            State::Speaking => crate::boards::StatusLed::On,
+        });
+
+        // an idle GIF frame redraws the state area anyway
+        if gui.set_battery(crate::boards::battery_percent()) && !idle_gif_shown && !video_shown {
+            gui.render_to_target(framebuffer)?;
+            framebuffer.flush()?;
+        }
+        if !wifi_polled_at.is_some_and(|at| at.elapsed() < WIFI_POLL_INTERVAL) {
+            wifi_polled_at = Some(std::time::Instant::now());
+            if gui.set_wifi(crate::network::rssi()) && !idle_gif_shown && !video_shown {
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
+        }
+
+        // while Idle, wake up on the minute to refresh the clock, often enough to
+        // refresh the WiFi signal, and in time to dim the backlight or save power.
+        // Saving power, only the clock is kept up.
+        let timeout = if state == State::Idle {
+            if dimmer.poll(std::time::Instant::now()) {
+                log::info!("Dimming backlight after inactivity");
+                crate::boards::set_backlight_level(config.backlight_dim_level);
+            }
+            if power_saver.poll(std::time::Instant::now()) {
+                if let Err(e) = player_tx.send(AudioEvent::Pause(true)) {
+                    log::error!("Error pausing audio: {:?}", e);
+                }
+                power_save = Some(crate::power::enter());
+            }
+            let until_minute = crate::sntp::until_next_minute(std::time::SystemTime::now());
+            let until_refresh = if power_save.is_some() {
+                until_minute
+            } else {
+                until_minute.min(WIFI_POLL_INTERVAL)
+            };
+            let until_frame = match &idle_gif {
+                Some(gif) if !replaying && !video_shown && power_save.is_none() => {
+                    gif.until_next(std::time::Instant::now())
+                }
+                _ => until_refresh,
+            };
+            let now = std::time::Instant::now();
+            until_refresh
+                .min(until_frame)
+                .min(dimmer.until_dim(now).unwrap_or(std::time::Duration::MAX))
+                .min(
+                    power_saver
+                        .until_dim(now)
+                        .unwrap_or(std::time::Duration::MAX),
+                )
+        } else if let Some(deadline) = response_deadline {
+            deadline
+                .saturating_duration_since(std::time::Instant::now())
+                .min(timeout)
+        } else {
+            timeout
+        };
+        // a scroll tick doesn't restart the wait for a real event
+        let timeout = match scroll_tick_deadline.take() {
+            Some(at) => timeout.min(at.saturating_duration_since(std::time::Instant::now())),
+            None => timeout,
+        };
+        // overflowing content scrolls on its own clock, whatever else arrives
+        let scrolling = !idle_gif_shown && !video_shown;
+        let select_timeout = match gui.until_scroll() {
+            Some(wait) if scrolling => wait.min(timeout),
+            _ => timeout,
+        };
+        let selected_at = std::time::Instant::now();
+        let Some(evt) = select_evt(
+            &mut evt_rx,
+            &mut server,
+            &notify,
+            wait_notify,
+            select_timeout,
+        )
+        .await
+        else {
+            break;
+        };
+
+        if scrolling && gui.scroll_content() {
+            gui.render_to_target(framebuffer)?;
+            framebuffer.flush()?;
+        }
+        if select_timeout < timeout && matches!(evt, Event::Event(Event::IDLE)) {
+            scroll_tick_deadline = Some(selected_at + timeout);
+            continue;
+        }
+
+        if response_deadline.is_some() && matches!(evt, Event::ServerEvent(_)) {
+            response_deadline = Some(std::time::Instant::now() + config.response_timeout);
+        }
+
+        // checked whatever the event, mic chunks keep the select from timing out
+        if response_deadline.is_some_and(|at| std::time::Instant::now() >= at) {
+            log::warn!(
+                "No response from the server within {:?}, giving up on the turn",
+                config.response_timeout
+            );
+            analytics::record_error();
+            crate::telemetry::log_turn("error: response timeout");
+            server.cancel();
+            crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+            submit_state.clear();
+            vad_end_at = None;
+            metrics.submit_time = None;
+            // a hung server shouldn't be reused for the next turn
+            server.close().await?;
+            state = State::Idle;
+            gui.set_status_icon(Some(crate::ui::StatusIcon::Error));
+            gui.set_state("No response".to_string());
+            gui.set_text("Press K0 to retry".to_string());
+            gui.render_to_target(framebuffer)?;
+            framebuffer.flush()?;
+            continue;
+        }
+
+        // select timeouts only count as activity outside Idle
+        if state != State::Idle || !matches!(evt, Event::Event(Event::IDLE)) {
+            if dimmer.wake(std::time::Instant::now()) {
+                crate::boards::set_backlight_level(config.backlight_level);
+            }
+            power_saver.wake(std::time::Instant::now());
+            // resumed before the event is handled, it may play audio
+            if let Some(power_save) = power_save.take() {
+                power_save.leave();
+                if let Err(e) = player_tx.send(AudioEvent::Pause(false)) {
+                    log::error!("Error resuming audio: {:?}", e);
+                }
+            }
+        }
+
+        let evt = match evt {
+            Event::Event(button @ (Event::VOL_UP | Event::VOL_DOWN)) => {
+                match volume_chord.press(button, std::time::Instant::now(), vol) {
+                    Some(before) => {
+                        vol = before;
+                        Event::Event(Event::MUTE)
+                    }
+                    None => evt,
+                }
+            }
+            evt => evt,
+        };
+
+        // anything but a timeout stops the idle GIF and brings the chat screen
+        // back before the event is handled; it resumes on the next Idle timeout
+        if idle_gif_shown && !matches!(evt, Event::Event(Event::IDLE)) {
+            idle_gif_shown = false;
+            gui.invalidate();
+            gui.render_to_target(framebuffer)?;
+            framebuffer.force_flush()?;
+        }
+
+        if let Event::Event(name) = evt {
+            if config.forward_buttons.iter().any(|b| b == name) && server.is_connected() {
+                // best effort, the local handling below doesn't depend on it
+                let cmd = protocol::ClientCommand::Button {
+                    name: name.to_string(),
+                };
+                if let Err(e) = server.send_client_command(cmd).await {
+                    log::warn!("Failed to forward {}: {:?}", name, e);
+                }
+            }
+        }
+
+        let ptt_press = matches!(evt, Event::Event(Event::K0_DOWN));
 
-    while let Some(evt) = select_evt(&mut evt_rx, &mut server, &notify, wait_notify, timeout).await
-    {
         match evt {
-            Event::Event(Event::K0) => {
+            Event::Event(Event::K0_DOWN) if state == State::Listening => {
+                log::info!("Push-to-talk pressed");
+                submit_state.clear();
+                ptt_held = true;
+                crate::audio::VAD_ACTIVE.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+            Event::Event(Event::K0_UP) => {
+                if !ptt_held {
+                    continue;
+                }
+                log::info!("Push-to-talk released");
+                ptt_held = false;
+                crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+
+                if state != State::Listening {
+                    continue;
+                }
+                if !submit_state.start_submit {
+                    log::info!(
+                        "Dropping short push-to-talk of {:.2}s",
+                        submit_state.submit_audio
+                    );
+                    submit_state.clear();
+                    continue;
+                }
+
+                if !submit_state.audio_buffer.is_empty() {
+                    server
+                        .send_client_audio_chunk_i16(std::mem::take(&mut submit_state.audio_buffer))
+                        .await?;
+                }
+                server
+                    .send_client_command(protocol::ClientCommand::Submit)
+                    .await?;
+                log::info!("Submitted push-to-talk audio");
+
+                vad_end_at = Some(std::time::Instant::now());
+                metrics.start_turn(std::time::Instant::now());
+                need_compute = metrics.is_timeout();
+                response_audio.clear();
+                submit_state.clear();
+
+                state = State::Waiting;
+                gui.set_state("Waiting...".to_string());
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
+            Event::Event(Event::WAKE_WORD) if state != State::Idle => {
+                // also covers our own playback, which AEC doesn't always cancel
+                log::info!("Ignoring wake word while not idle");
+            }
+            Event::Event(Event::K0 | Event::WAKE_WORD | Event::K0_DOWN) => {
+                // a user turn replaces whatever quiet response was showing
+                quiet_response = false;
                 log::info!("Received event: k0");
 
                 if state == State::Listening {
                     state = State::Idle;
-                    gui.set_state("Idle".to_string());
+                    gui.set_state(idle_state(&config));
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
-                    server.close().await?;
+                    if !config.keep_connection_on_idle {
+                        server.close().await?;
+                    } else if submit_state.start_submit {
+                        // the connection stays, so tell the server to drop the turn
+                        server.cancel();
+                    }
+                    crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                    submit_state.clear();
                 } else {
+                    gui.set_status_icon(Some(crate::ui::StatusIcon::Connecting));
                     gui.set_state("Connecting...".to_string());
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
 
                     crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
 
-                    server.reconnect_with_retry(3).await?;
+                    if matches!(state, State::Waiting | State::Speaking) {
+                        server.cancel();
+                    }
+
+                    if !(config.keep_connection_on_idle
+                        && state == State::Idle
+                        && server.is_connected())
+                    {
+                        if let Err(e) = server.reconnect_with_retry(3).await {
+                            log::error!("Reconnect failed: {}", e);
+                            analytics::record_error();
+                            crate::telemetry::log_turn(format_args!("reconnect failed: {}", e));
+                            if reconnect_guard.record_failure() {
+                                return Err(anyhow::anyhow!(
+                                    "Giving up after {} failed reconnect cycles",
+                                    reconnect_guard.failures
+                                ));
+                            }
+                            state = State::Idle;
+                            gui.set_status_icon(Some(crate::ui::StatusIcon::Error));
+                            gui.set_state("Reconnect failed".to_string());
+                            gui.set_text("Press K0 to retry".to_string());
+                            gui.render_to_target(framebuffer)?;
+                            framebuffer.flush()?;
+                            continue;
+                        }
+                        hello_wav.clear();
+                        hello_receiving = false;
+                    }
+                    gui.set_status_icon(Some(crate::ui::StatusIcon::Connected));
 
                     let hello_notify = Arc::new(tokio::sync::Notify::new());
                     player_tx
@@ -247,6 +956,11 @@ pub async fn main_work<'d, const N: usize>(
                     gui.set_state("Ready".to_string());
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
+
+                    if ptt_press {
+                        ptt_held = true;
+                        crate::audio::VAD_ACTIVE.store(true, std::sync::atomic::Ordering::Relaxed);
+                    }
                 }
             }
             Event::Event(Event::K0_) => {
@@ -254,6 +968,8 @@ pub async fn main_work<'d, const N: usize>(
                 {
                     allow_interrupt = !allow_interrupt;
                     log::info!("Set allow_interrupt to {}", allow_interrupt);
+                    prefs.send_modify(|c| c.allow_interrupt = allow_interrupt);
+                    gui.set_interrupt_indicator(allow_interrupt);
                     gui.set_state(format!("Interrupt: {}", allow_interrupt));
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
@@ -267,6 +983,8 @@ pub async fn main_work<'d, const N: usize>(
                 player_tx
                     .send(AudioEvent::VolSet(vol))
                     .map_err(|e| anyhow::anyhow!("Error sending volume set: {e:?}"))?;
+                muted = false;
+                prefs.send_modify(|c| c.volume = vol);
                 log::info!("Volume set to {}", vol);
                 gui.set_state(format!("Volume: {}", vol));
                 gui.render_to_target(framebuffer)?;
@@ -280,11 +998,33 @@ pub async fn main_work<'d, const N: usize>(
                 player_tx
                     .send(AudioEvent::VolSet(vol))
                     .map_err(|e| anyhow::anyhow!("Error sending volume set: {e:?}"))?;
+                muted = false;
+                prefs.send_modify(|c| c.volume = vol);
                 log::info!("Volume set to {}", vol);
                 gui.set_state(format!("Volume: {}", vol));
                 gui.render_to_target(framebuffer)?;
                 framebuffer.flush()?;
             }
+            Event::Event(Event::MUTE) => {
+                muted = !muted;
+                // VolSet unmutes, so it goes first; it also undoes the step from
+                // the first press of a VOL_UP + VOL_DOWN chord
+                player_tx
+                    .send(AudioEvent::VolSet(vol))
+                    .map_err(|e| anyhow::anyhow!("Error sending volume set: {e:?}"))?;
+                player_tx
+                    .send(AudioEvent::Mute(muted))
+                    .map_err(|e| anyhow::anyhow!("Error sending mute: {e:?}"))?;
+                prefs.send_modify(|c| c.volume = vol);
+                log::info!("Muted: {}", muted);
+                gui.set_state(if muted {
+                    "Muted".to_string()
+                } else {
+                    format!("Volume: {}", vol)
+                });
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
             Event::Event(Event::VOL_SWITCH) => {
                 vol -= 1;
                 if vol < 1 {
@@ -293,25 +1033,139 @@ pub async fn main_work<'d, const N: usize>(
                 player_tx
                     .send(AudioEvent::VolSet(vol))
                     .map_err(|e| anyhow::anyhow!("Error sending volume set: {e:?}"))?;
+                muted = false;
+                prefs.send_modify(|c| c.volume = vol);
                 log::info!("Volume set to {}", vol);
                 gui.set_state(format!("Volume: {}", vol));
                 gui.render_to_target(framebuffer)?;
                 framebuffer.flush()?;
             }
+            // shows the end of the turn log, for diagnosing without a serial console
+            Event::Event(Event::K1) if state == State::Idle => {
+                let log = crate::telemetry::turn_log();
+                for entry in &log {
+                    log::info!("Turn log: {}", entry);
+                }
+                let tail = &log[log.len().saturating_sub(TURN_LOG_SHOWN)..];
+                gui.set_state("Turn log".to_string());
+                gui.set_text(tail.join("\n"));
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
             Event::Event(Event::YES | Event::K1) => {}
+            Event::Event(Event::IDLE) if state == State::Idle => {
+                if !replaying && !video_shown {
+                    gui.set_state(idle_state(&config));
+                    let step = idle_gif
+                        .as_mut()
+                        .map(|gif| gif.step(framebuffer, std::time::Instant::now()));
+                    let frame = match step {
+                        Some(Ok(frame)) => frame,
+                        Some(Err(e)) => {
+                            log::warn!("Stopping idle GIF: {:?}", e);
+                            idle_gif = None;
+                            false
+                        }
+                        None => false,
+                    };
+                    if frame {
+                        // the frame replaced the whole background, redraw on top of it
+                        idle_gif_shown = true;
+                        gui.invalidate();
+                        gui.render_to_target(framebuffer)?;
+                        framebuffer.force_flush()?;
+                    } else if !idle_gif_shown {
+                        gui.render_to_target(framebuffer)?;
+                        framebuffer.flush()?;
+                    }
+                }
+            }
             Event::Event(Event::IDLE) => {
                 log::info!("Received idle event");
                 if state == State::Listening {
                     state = State::Idle;
-                    gui.set_state("Idle".to_string());
+                    gui.set_state(idle_state(&config));
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
-                    server.close().await?;
+                    if !config.keep_connection_on_idle {
+                        server.close().await?;
+                    }
                 }
             }
+            Event::Event(Event::SERVER_CLOSED)
+                if state == State::Idle && !config.keep_connection_on_idle =>
+            {
+                log::info!("Server closed the connection while Idle");
+            }
+            Event::Event(Event::SERVER_CLOSED) => {
+                log::warn!("Server closed the connection, reconnecting");
+                crate::telemetry::log_turn("server closed the connection");
+                // a turn in progress can't continue on a new socket
+                crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                submit_state.clear();
+                vad_end_at = None;
+                quiet_response = false;
+                state = State::Idle;
+
+                if let Err(e) = server.reconnect_with_retry(3).await {
+                    log::error!("Reconnect failed: {}", e);
+                    analytics::record_error();
+                    crate::telemetry::log_turn(format_args!("reconnect failed: {}", e));
+                    if reconnect_guard.record_failure() {
+                        return Err(anyhow::anyhow!(
+                            "Giving up after {} failed reconnect cycles",
+                            reconnect_guard.failures
+                        ));
+                    }
+                    gui.set_status_icon(Some(crate::ui::StatusIcon::Error));
+                    gui.set_state("Reconnect failed".to_string());
+                    gui.set_text("Press K0 to retry".to_string());
+                } else {
+                    hello_wav.clear();
+                    hello_receiving = false;
+                    gui.set_status_icon(Some(crate::ui::StatusIcon::Connected));
+                    gui.set_state(idle_state(&config));
+                }
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
+            Event::Event(Event::K0_DOUBLE) => {
+                if state != State::Idle && state != State::Listening {
+                    log::info!("Ignoring K0 double press while busy");
+                    continue;
+                }
+                let Some(audio) = &last_response else {
+                    gui.set_state("Nothing to replay".to_string());
+                    gui.render_to_target(framebuffer)?;
+                    framebuffer.flush()?;
+                    continue;
+                };
+                log::info!("Replaying last response ({} samples)", audio.len());
+                player_tx
+                    .send(AudioEvent::SpeechChunki16(audio.clone()))
+                    .map_err(|e| anyhow::anyhow!("Error sending replay: {e:?}"))?;
+                player_tx
+                    .send(AudioEvent::EndSpeech(notify.clone()))
+                    .map_err(|e| anyhow::anyhow!("Error sending replay end: {e:?}"))?;
+                wait_notify = true;
+                replaying = true;
+                gui.set_state("Replaying...".to_string());
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
             Event::Event(Event::NOTIFY) => {
                 log::info!("Received notify event");
                 wait_notify = false;
+                if replaying {
+                    replaying = false;
+                    gui.set_state(if state == State::Idle {
+                        idle_state(&config)
+                    } else {
+                        "Ready".to_string()
+                    });
+                    gui.render_to_target(framebuffer)?;
+                    framebuffer.flush()?;
+                }
             }
             Event::Event(evt) => {
                 log::info!("Received event: {:?}", evt);
@@ -322,16 +1176,39 @@ pub async fn main_work<'d, const N: usize>(
                     framebuffer.flush()?;
                 }
             }
+            Event::MicAudioChunk(_) if config.push_to_talk && !ptt_held => {
+                log::debug!("Ignoring mic audio while push-to-talk is released");
+                audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+            }
             Event::MicAudioChunk(data) if state == State::Listening => {
                 submit_state.submit_audio += data.len() as f32 / 16000.0;
                 submit_state.audio_buffer.extend_from_slice(&data);
+                // a held button is an explicit turn, however quiet or long
+                let ptt_turn = config.push_to_talk && ptt_held;
 
                 if !submit_state.start_submit {
+                    if submit_state.submit_audio < config.min_submit_secs {
+                        continue;
+                    }
+                    let amplitude = audio::mean_amplitude(&submit_state.audio_buffer);
+                    log::info!(
+                        "Trigger mean amplitude {} (min {})",
+                        amplitude,
+                        config.min_submit_amplitude
+                    );
+                    if amplitude < config.min_submit_amplitude && !ptt_turn {
+                        log::info!("Dropping quiet trigger, mean amplitude {}", amplitude);
+                        audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                        submit_state.clear();
+                        continue;
+                    }
+
                     log::info!("Start submitting audio");
                     server
                         .send_client_command(protocol::ClientCommand::StartChat)
                         .await?;
                     log::info!("Submitted StartChat command");
+                    quiet_response = false;
                     gui.set_state("Listening...".to_string());
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
@@ -339,14 +1216,22 @@ pub async fn main_work<'d, const N: usize>(
                     submit_state.got_asr_result = false;
                 }
 
-                if submit_state.audio_buffer.len() >= 8192 && submit_state.submit_audio > 0.3 {
+                if submit_state.audio_buffer.len() >= submit_chunk_samples
+                    && submit_state.submit_audio > MIN_CHUNK_STREAM_SECS
+                {
                     server
                         .send_client_audio_chunk_i16(submit_state.audio_buffer)
                         .await?;
-                    submit_state.audio_buffer = Vec::with_capacity(8192);
+                    submit_state.audio_buffer = Vec::with_capacity(submit_chunk_samples);
 
-                    if submit_state.submit_audio > 10.0 && !submit_state.got_asr_result {
-                        log::info!("No ASR result after 10s audio, ending request");
+                    if submit_state.submit_audio > MAX_SECS_WITHOUT_ASR
+                        && !submit_state.got_asr_result
+                        && !ptt_turn
+                    {
+                        log::info!(
+                            "No ASR result after {}s audio, ending request",
+                            MAX_SECS_WITHOUT_ASR
+                        );
                         crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
 
                         submit_state.clear();
@@ -363,16 +1248,57 @@ pub async fn main_work<'d, const N: usize>(
                 submit_state.submit_audio += data.len() as f32 / 16000.0;
                 submit_state.audio_buffer.extend_from_slice(&data);
 
-                if submit_state.submit_audio > 0.6 {
+                if submit_state.submit_audio > config.min_interrupt_secs {
+                    let amplitude = audio::mean_amplitude(&submit_state.audio_buffer);
+                    log::info!(
+                        "Interrupt mean amplitude {} (min {})",
+                        amplitude,
+                        config.min_interrupt_amplitude
+                    );
+                    if amplitude < config.min_interrupt_amplitude {
+                        log::info!("Ignoring quiet interrupt, mean amplitude {}", amplitude);
+                        audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                        submit_state.clear();
+                        continue;
+                    }
+
+                    analytics::record_interrupt();
+                    server.cancel();
                     state = State::Listening;
                     gui.set_state("Listening...".to_string());
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
 
-                    server.reconnect_with_retry(3).await?;
+                    if let Err(e) = server.reconnect_with_retry(3).await {
+                        log::error!("Reconnect failed: {}", e);
+                        analytics::record_error();
+                        crate::telemetry::log_turn(format_args!("reconnect failed: {}", e));
+                        if reconnect_guard.record_failure() {
+                            return Err(anyhow::anyhow!(
+                                "Giving up after {} failed reconnect cycles",
+                                reconnect_guard.failures
+                            ));
+                        }
+                        crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                        submit_state.clear();
+                        player_tx
+                            .send(AudioEvent::ClearSpeech)
+                            .map_err(|_| anyhow::anyhow!("Error sending clear"))?;
+                        state = State::Idle;
+                        gui.set_status_icon(Some(crate::ui::StatusIcon::Error));
+                        gui.set_state("Reconnect failed".to_string());
+                        gui.set_text("Press K0 to retry".to_string());
+                        gui.render_to_target(framebuffer)?;
+                        framebuffer.flush()?;
+                        continue;
+                    }
+                    gui.set_status_icon(Some(crate::ui::StatusIcon::Connected));
+                    hello_wav.clear();
+                    hello_receiving = false;
 
                     submit_state.start_submit = true;
                     submit_state.got_asr_result = false;
+                    quiet_response = false;
 
                     server
                         .send_client_command(protocol::ClientCommand::StartChat)
@@ -389,9 +1315,23 @@ pub async fn main_work<'d, const N: usize>(
             }
             Event::MicAudioEnd => {
                 log::info!("Received MicAudioEnd");
+                if state == State::Listening && !submit_state.start_submit {
+                    log::info!(
+                        "Dropping short trigger of {:.2}s",
+                        submit_state.submit_audio
+                    );
+                    submit_state.clear();
+                } else if state == State::Listening && !submit_state.audio_buffer.is_empty() {
+                    // the tail of the speech, shorter than a chunk
+                    server
+                        .send_client_audio_chunk_i16(std::mem::take(&mut submit_state.audio_buffer))
+                        .await?;
+                }
             }
             Event::ServerEvent(ServerEvent::ASR { text }) => {
                 log::info!("Received ASR: {:?}", text);
+                // only the length, the log is served over HTTP
+                crate::telemetry::log_turn(format_args!("asr {} chars", text.chars().count()));
                 submit_state.got_asr_result = true;
                 gui.set_state("ASR".to_string());
                 gui.set_asr(text.trim().to_string());
@@ -404,7 +1344,29 @@ pub async fn main_work<'d, const N: usize>(
                 gui.render_to_target(framebuffer)?;
                 framebuffer.flush()?;
             }
+            Event::ServerEvent(ServerEvent::StartAudio { text })
+                if state == State::Idle
+                    && config.quiet_hours.is_some_and(|(start, end)| {
+                        crate::sntp::in_hours(
+                            std::time::SystemTime::now(),
+                            config.utc_offset_minutes,
+                            start,
+                            end,
+                        )
+                    }) =>
+            {
+                // its chunks and EndAudio are dropped outside Speaking
+                log::info!("Quiet hours, showing server audio as text: {:?}", text);
+                quiet_response = true;
+                gui.set_text(text.trim().to_string());
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
             Event::ServerEvent(ServerEvent::StartAudio { text }) => {
+                quiet_response = false;
+                if let Some(t) = vad_end_at.take() {
+                    analytics::record_latency(t.elapsed());
+                }
                 start_audio = true;
                 state = State::Speaking;
                 log::info!("Received audio start: {:?}", text);
@@ -423,6 +1385,15 @@ pub async fn main_work<'d, const N: usize>(
                     continue;
                 }
 
+                metrics.record_chunk(std::time::Instant::now(), data.len() * 2);
+
+                if response_audio.len() + data.len() <= MAX_REPLAY_SAMPLES {
+                    response_audio.extend_from_slice(&data);
+                } else {
+                    response_audio.clear();
+                    response_audio.shrink_to_fit();
+                }
+
                 if need_compute {
                     if start_audio {
                         metrics.reset();
@@ -435,6 +1406,8 @@ pub async fn main_work<'d, const N: usize>(
                     if let Err(e) = player_tx.send(AudioEvent::SpeechChunki16WithVowel(data, vowel))
                     {
                         log::error!("Error sending audio chunk: {:?}", e);
+                        analytics::record_error();
+                        crate::telemetry::log_turn("error: player queue closed");
                         gui.set_state("Error on audio chunk".to_string());
                         gui.render_to_target(framebuffer)?;
                         framebuffer.flush()?;
@@ -456,6 +1429,8 @@ pub async fn main_work<'d, const N: usize>(
                 if recv_audio_buffer.len() > 0 {
                     if let Err(e) = player_tx.send(AudioEvent::SpeechChunki16(recv_audio_buffer)) {
                         log::error!("Error sending audio chunk: {:?}", e);
+                        analytics::record_error();
+                        crate::telemetry::log_turn("error: player queue closed");
                         gui.set_state("Error on audio chunk".to_string());
                         gui.render_to_target(framebuffer)?;
                         framebuffer.flush()?;
@@ -465,17 +1440,24 @@ pub async fn main_work<'d, const N: usize>(
 
                 if let Err(e) = player_tx.send(AudioEvent::EndSpeech(notify.clone())) {
                     log::error!("Error sending audio chunk: {:?}", e);
+                    analytics::record_error();
+                    crate::telemetry::log_turn("error: player queue closed");
                     gui.set_state("Error on audio chunk".to_string());
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
                 }
 
                 if need_compute {
-                    speed = metrics.speed();
-                    need_compute = false;
+                    // without enough audio to time, keep the current mode and
+                    // measure again on the next turn
+                    if let Some(measured) = metrics.speed() {
+                        speed = measured;
+                        need_compute = false;
+                    }
                 }
 
                 log::info!("Audio speed: {:.2}x", speed);
+                crate::telemetry::log_turn(format_args!("speed {:.2}x", speed));
 
                 wait_notify = true;
 
@@ -484,6 +1466,28 @@ pub async fn main_work<'d, const N: usize>(
 
             Event::ServerEvent(ServerEvent::EndResponse) => {
                 log::info!("Received request end");
+                if std::mem::take(&mut quiet_response) {
+                    continue;
+                }
+                reconnect_guard.reset();
+                vad_end_at = None;
+                analytics::record_turn();
+                if !response_audio.is_empty() {
+                    last_response = Some(std::mem::take(&mut response_audio));
+                }
+
+                // best effort, a closed socket shouldn't fail the turn
+                if metrics.submit_time.is_some() {
+                    let cmd = protocol::ClientCommand::Metrics {
+                        first_audio_ms: metrics.first_audio_ms(),
+                        total_audio_bytes: metrics.total_bytes,
+                        speed,
+                    };
+                    metrics.submit_time = None;
+                    if let Err(e) = server.send_client_command(cmd).await {
+                        log::warn!("Failed to send metrics: {:?}", e);
+                    }
+                }
                 crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
 
                 submit_state.clear();
@@ -497,15 +1501,22 @@ pub async fn main_work<'d, const N: usize>(
             Event::ServerEvent(ServerEvent::HelloStart) => {
                 log::info!("Received hello start");
                 hello_wav.clear();
+                hello_receiving = true;
             }
             Event::ServerEvent(ServerEvent::HelloChunk { data }) => {
                 log::debug!("Received hello chunk");
-                if !init_hello {
+                if hello_receiving && !init_hello {
                     hello_wav.extend_from_slice(&data);
                 }
             }
             Event::ServerEvent(ServerEvent::HelloEnd) => {
                 log::info!("Received hello end");
+                if !hello_receiving {
+                    log::warn!("Received HelloEnd without HelloStart, ignoring");
+                    continue;
+                }
+                hello_receiving = false;
+
                 if !init_hello {
                     if let Err(_) = player_tx.send(AudioEvent::SetHello(hello_wav)) {
                         log::error!("Error sending hello end");
@@ -518,7 +1529,31 @@ pub async fn main_work<'d, const N: usize>(
                 }
             }
 
-            Event::ServerEvent(ServerEvent::StartVideo | ServerEvent::EndVideo) => {}
+            Event::ServerEvent(ServerEvent::StartVideo) => {
+                log::info!("Video started");
+                video_shown = true;
+                crate::ui::draw_video_blank(framebuffer)?;
+                framebuffer.force_flush()?;
+            }
+            Event::ServerEvent(ServerEvent::VideoChunk { data }) => {
+                if !video_shown {
+                    log::warn!("Ignoring video frame outside StartVideo/EndVideo");
+                    continue;
+                }
+                match crate::ui::draw_video_frame(framebuffer, &data) {
+                    Ok(()) => framebuffer.force_flush()?,
+                    Err(e) => log::warn!("Skipping video frame: {:?}", e),
+                }
+            }
+            Event::ServerEvent(ServerEvent::EndVideo) => {
+                log::info!("Video ended");
+                if video_shown {
+                    video_shown = false;
+                    gui.invalidate();
+                    gui.render_to_target(framebuffer)?;
+                    framebuffer.force_flush()?;
+                }
+            }
             Event::ServerEvent(ServerEvent::AudioChunk { .. }) => {
                 log::warn!("Received deprecated AudioChunk, please use AudioChunki16 instead");
             }
@@ -527,6 +1562,116 @@ pub async fn main_work<'d, const N: usize>(
                     "Received deprecated AudioChunkWithVowel, please use AudioChunki16 instead"
                 );
             }
+            Event::ServerEvent(ServerEvent::Emotion { name }) => {
+                let image = emotion_image(&name);
+                if image.is_none() {
+                    log::info!("Unknown emotion {:?}, showing the default avatar", name);
+                }
+                if gui.set_emotion(image) {
+                    gui.render_to_target(framebuffer)?;
+                    framebuffer.flush()?;
+                }
+            }
+            Event::ServerEvent(ServerEvent::SetVolume { level }) => {
+                vol = level.clamp(1, 5);
+                if vol != level {
+                    log::warn!("Server volume {} out of range, clamped to {}", level, vol);
+                }
+                player_tx
+                    .send(AudioEvent::VolSet(vol))
+                    .map_err(|e| anyhow::anyhow!("Error sending volume set: {e:?}"))?;
+                muted = false;
+                prefs.send_modify(|c| c.volume = vol);
+                log::info!("Volume set to {} by server", vol);
+                gui.set_state(format!("Volume: {}", vol));
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
+            Event::ServerEvent(ServerEvent::ShowText { area, text }) => {
+                log::info!("Server text for {:?}: {}", area, text);
+                match area {
+                    TextArea::State => gui.set_state(text),
+                    TextArea::Content => gui.set_text(text),
+                }
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+            }
+            Event::ServerEvent(ServerEvent::OtaUpdate { url, sha256 }) => {
+                if state == State::Speaking || wait_notify {
+                    log::warn!("Ignoring OTA update while audio is playing");
+                    continue;
+                }
+
+                log::info!("Starting OTA update from {}", url);
+                crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                submit_state.clear();
+                server.close().await?;
+                state = State::Idle;
+
+                gui.set_state("Updating 0%".to_string());
+                gui.set_text("Downloading firmware, do not power off".to_string());
+                gui.render_to_target(framebuffer)?;
+                framebuffer.flush()?;
+
+                // the download blocks, so it runs on its own thread and reports
+                // its progress back here for the screen; the TLS handshake needs
+                // the larger stack
+                let (progress_tx, mut progress_rx) = tokio::sync::watch::channel((0, None));
+                let (done_tx, mut done_rx) = tokio::sync::oneshot::channel();
+                std::thread::Builder::new()
+                    .stack_size(16 * 1024)
+                    .spawn(move || {
+                        let r = crate::ota::update_from_url(&url, &sha256, |written, total| {
+                            let _ = progress_tx.send((written, total));
+                        });
+                        let _ = done_tx.send(r);
+                    })?;
+
+                let mut shown = None;
+                let r = loop {
+                    tokio::select! {
+                        r = &mut done_rx => {
+                            break r.unwrap_or_else(|_| Err(anyhow::anyhow!("OTA thread exited")));
+                        }
+                        Ok(()) = progress_rx.changed() => {
+                            let (written, total): (usize, Option<usize>) =
+                                *progress_rx.borrow_and_update();
+                            let text = match total {
+                                Some(total) if total > 0 => {
+                                    format!("Updating {}%", written * 100 / total)
+                                }
+                                _ => format!("Updating {}KB", written / (64 * 1024) * 64),
+                            };
+                            if shown.as_ref() != Some(&text) {
+                                gui.set_state(text.clone());
+                                gui.render_to_target(framebuffer)?;
+                                framebuffer.flush()?;
+                                shown = Some(text);
+                            }
+                        }
+                    }
+                };
+
+                match r {
+                    Ok(()) => {
+                        gui.set_state("Update complete".to_string());
+                        gui.set_text("Restarting...".to_string());
+                        gui.render_to_target(framebuffer)?;
+                        framebuffer.flush()?;
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        unsafe { esp_idf_svc::sys::esp_restart() }
+                    }
+                    Err(e) => {
+                        log::error!("OTA update failed: {:?}", e);
+                        analytics::record_error();
+                        crate::telemetry::log_turn(format_args!("OTA failed: {}", e));
+                        gui.set_state("Update failed".to_string());
+                        gui.set_text("Press K0 to continue".to_string());
+                        gui.render_to_target(framebuffer)?;
+                        framebuffer.flush()?;
+                    }
+                }
+            }
             Event::ServerEvent(ServerEvent::EndVad) => {
                 log::info!("Received EndVad event from server");
                 crate::audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
@@ -536,7 +1681,10 @@ pub async fn main_work<'d, const N: usize>(
                     continue;
                 }
 
+                vad_end_at = Some(std::time::Instant::now());
+                metrics.start_turn(std::time::Instant::now());
                 need_compute = metrics.is_timeout();
+                response_audio.clear();
 
                 submit_state.clear();
 
@@ -549,10 +1697,19 @@ pub async fn main_work<'d, const N: usize>(
             Event::ServerUrl(url) => {
                 log::info!("Received ServerUrl: {}", url);
                 if url != server.url {
-                    init_hello = false;
-                    server = Server::new(server.id, url).await?;
+                    init_hello = config.custom_hello;
+                    hello_wav.clear();
+                    hello_receiving = false;
+                    server = Server::new(
+                        server.id,
+                        url,
+                        server.role,
+                        server.ping_interval,
+                        server.connect_timeout,
+                    )
+                    .await?;
                     state = State::Idle;
-                    gui.set_state("Idle".to_string());
+                    gui.set_state(idle_state(&config));
                     gui.set_text(format!("Server URL updated:\n{}", server.url));
                     gui.render_to_target(framebuffer)?;
                     framebuffer.flush()?;
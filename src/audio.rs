@@ -1,37 +1,81 @@
+use std::cell::RefCell;
 use std::collections::LinkedList;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use esp_idf_svc::hal::gpio::AnyIOPin;
 use esp_idf_svc::hal::i2s::{config, I2sDriver, I2S0, I2S1};
 
 use esp_idf_svc::sys::esp_sr;
 
-const SAMPLE_RATE: u32 = 16000;
+pub const SAMPLE_RATE: u32 = 16000;
 
 pub static mut AFE_LINEAR_GAIN: f32 = 1.5;
 pub static mut AGC_TARGET_LEVEL_DBFS: i32 = 3;
 pub static mut AGC_COMPRESSION_GAIN_DB: i32 = 15;
 
-unsafe fn afe_init() -> (
+/// VAD sensitivity, applied when the AFE is created.
+#[derive(Debug, Clone)]
+pub struct AfeTuning {
+    /// esp-sr `VAD_MODE_0..=VAD_MODE_4`, higher is more aggressive (less
+    /// likely to treat noise as speech). Clamped to 4.
+    pub vad_mode: u8,
+    /// Silence needed before speech is considered ended.
+    pub vad_min_noise_ms: i32,
+    /// WakeNet model to load from the `model` partition, e.g. `wn9_hiesp`.
+    /// `None` uses the first WakeNet model found there. Only used with the
+    /// `wakeword` feature.
+    #[cfg_attr(not(feature = "wakeword"), allow(unused))]
+    pub wakenet_model: Option<String>,
+}
+
+impl AfeTuning {
+    pub const DEFAULT: Self = Self {
+        vad_mode: 4,
+        vad_min_noise_ms: 400,
+        wakenet_model: None,
+    };
+}
+
+pub static AFE_TUNING: Mutex<AfeTuning> = Mutex::new(AfeTuning::DEFAULT);
+
+/// AEC settings `afe_init` starts from; each board's `afe_config` hook runs
+/// afterwards and can change them along with the rest of `afe_config_t`.
+///
+/// `VOIP_HIGH_PERF` removes the most echo, which matters for barge-in while
+/// the device is speaking. The filter length is esp-sr's default (4): longer
+/// filters cancel longer echo tails, e.g. in resonant enclosures, at more CPU.
+pub const DEFAULT_AEC_MODE: esp_sr::aec_mode_t = esp_sr::aec_mode_t_AEC_MODE_VOIP_HIGH_PERF;
+pub const DEFAULT_AEC_FILTER_LENGTH: i32 = 4;
+
+unsafe fn afe_init(
+    tuning: AfeTuning,
+) -> (
     *mut esp_sr::esp_afe_sr_iface_t,
     *mut esp_sr::esp_afe_sr_data_t,
 ) {
-    // let models = esp_sr::esp_srmodel_init(c"model".as_ptr());
-    let models = std::ptr::null_mut();
+    #[cfg(not(feature = "wakeword"))]
+    let (models, afe_type) = (std::ptr::null_mut(), esp_sr::afe_type_t_AFE_TYPE_VC);
+    // WakeNet needs the models flashed to the `model` partition and only runs in
+    // the speech-recognition AFE pipeline; AEC is still configured below.
+    #[cfg(feature = "wakeword")]
+    let (models, afe_type) = (
+        esp_sr::esp_srmodel_init(c"model".as_ptr()),
+        esp_sr::afe_type_t_AFE_TYPE_SR,
+    );
     let afe_config = esp_sr::afe_config_init(
         c"MR".as_ptr() as _,
         models,
-        esp_sr::afe_type_t_AFE_TYPE_VC,
+        afe_type,
         esp_sr::afe_mode_t_AFE_MODE_HIGH_PERF,
     );
     let afe_config = afe_config.as_mut().unwrap();
 
     afe_config.pcm_config.sample_rate = 16000;
     afe_config.afe_ringbuf_size = 40;
-    afe_config.vad_min_noise_ms = 400;
+    afe_config.vad_min_noise_ms = tuning.vad_min_noise_ms;
     afe_config.vad_min_speech_ms = 200;
     // afe_config.vad_delay_ms = 250; // Don't change it!!
-    afe_config.vad_mode = esp_sr::vad_mode_t_VAD_MODE_4;
+    afe_config.vad_mode = tuning.vad_mode.min(4) as esp_sr::vad_mode_t;
 
     afe_config.agc_init = true;
     afe_config.afe_linear_gain = AFE_LINEAR_GAIN;
@@ -39,12 +83,31 @@ unsafe fn afe_init() -> (
     afe_config.agc_compression_gain_db = AGC_COMPRESSION_GAIN_DB;
 
     afe_config.aec_init = true;
-    afe_config.aec_mode = esp_sr::aec_mode_t_AEC_MODE_VOIP_HIGH_PERF;
-    // afe_config.aec_filter_length = 5;
+    afe_config.aec_mode = DEFAULT_AEC_MODE;
+    afe_config.aec_filter_length = DEFAULT_AEC_FILTER_LENGTH;
     afe_config.ns_init = false;
-    afe_config.wakenet_init = false;
+    #[cfg(not(feature = "wakeword"))]
+    {
+        afe_config.wakenet_init = false;
+    }
+    #[cfg(feature = "wakeword")]
+    {
+        afe_config.wakenet_init = true;
+        if let Some(name) = tuning.wakenet_model {
+            let name = std::ffi::CString::new(name).unwrap_or_default();
+            let model = esp_sr::esp_srmodel_filter(models, c"wn".as_ptr(), name.as_ptr());
+            if model.is_null() {
+                log::warn!("WakeNet model {:?} not found, using the default", name);
+            } else {
+                afe_config.wakenet_model_name = model;
+            }
+        }
+    }
     afe_config.memory_alloc_mode = esp_sr::afe_memory_alloc_mode_t_AFE_MEMORY_ALLOC_MORE_PSRAM;
 
+    // board overrides, applied last: a board's `afe_config` can change any of
+    // the above, e.g. `aec_mode` or `aec_filter_length` to suit its speaker and
+    // enclosure
     crate::boards::afe_config(afe_config);
 
     log::info!("{afe_config:?}");
@@ -75,12 +138,14 @@ unsafe impl Sync for AFE {}
 struct AFEResult {
     data: Vec<i16>,
     speech: bool,
+    #[cfg_attr(not(feature = "wakeword"), allow(unused))]
+    wakeup: bool,
 }
 
 impl AFE {
-    fn new() -> Self {
+    fn new(tuning: AfeTuning) -> Self {
         unsafe {
-            let (handle, data) = afe_init();
+            let (handle, data) = afe_init(tuning);
             let feed_chunksize =
                 (handle.as_mut().unwrap().get_feed_chunksize.unwrap())(data) as usize;
 
@@ -132,6 +197,7 @@ impl AFE {
 
             let data_size = result.data_size;
             let speech = result.vad_state == esp_sr::vad_state_t_VAD_SPEECH;
+            let wakeup = result.wakeup_state == esp_sr::wakenet_state_t_WAKENET_DETECTED;
 
             let mut data = Vec::with_capacity((data_size + result.vad_cache_size) as usize / 2);
             if result.vad_cache_size > 0 {
@@ -152,7 +218,11 @@ impl AFE {
                 data.extend_from_slice(data_);
             }
 
-            Ok(AFEResult { data, speech })
+            Ok(AFEResult {
+                data,
+                speech,
+                wakeup,
+            })
         }
     }
 
@@ -170,6 +240,7 @@ impl AFE {
 
             let data_size = result.data_size;
             let speech = result.vad_state == esp_sr::vad_state_t_VAD_SPEECH;
+            let wakeup = result.wakeup_state == esp_sr::wakenet_state_t_WAKENET_DETECTED;
 
             let mut data = Vec::with_capacity((data_size) as usize / 2);
             if data_size > 0 {
@@ -177,13 +248,190 @@ impl AFE {
                 data.extend_from_slice(data_);
             }
 
-            Ok(AFEResult { data, speech })
+            Ok(AFEResult {
+                data,
+                speech,
+                wakeup,
+            })
         }
     }
 }
 
 pub static WAKE_WAV: &[u8] = include_bytes!("../assets/hello_beep.wav");
 
+/// Largest custom wake sound accepted, before resampling.
+pub const WAKE_SOUND_MAX_SIZE: usize = 512 * 1024;
+
+/// Format of a PCM WAV file, see `parse_wav`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+/// Returns the format and the raw data chunk of a PCM WAV file.
+pub fn parse_wav(wav: &[u8]) -> anyhow::Result<(WavFormat, &[u8])> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        anyhow::bail!("Not a WAV file");
+    }
+
+    let mut format = None;
+    let mut rest = &wav[12..];
+    while rest.len() >= 8 {
+        let id = &rest[0..4];
+        let len = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let body = rest
+            .get(8..8 + len)
+            .ok_or_else(|| anyhow::anyhow!("Truncated WAV chunk {:?}", id))?;
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    anyhow::bail!("Invalid WAV fmt chunk");
+                }
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                if tag != 1 {
+                    anyhow::bail!("Unsupported WAV format {}, need PCM", tag);
+                }
+                format = Some(WavFormat {
+                    channels: u16::from_le_bytes([body[2], body[3]]),
+                    sample_rate: u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+                    bits_per_sample: u16::from_le_bytes([body[14], body[15]]),
+                });
+            }
+            b"data" => {
+                let format = format.ok_or_else(|| anyhow::anyhow!("WAV data before fmt chunk"))?;
+                return Ok((format, body));
+            }
+            _ => {}
+        }
+        // chunks are padded to an even length
+        rest = rest.get(8 + len + (len & 1)..).unwrap_or_default();
+    }
+    anyhow::bail!("No data chunk in WAV file")
+}
+
+/// Decodes a 16-bit PCM WAV of any rate and channel count into 16kHz mono, the
+/// format the player expects.
+pub fn wav_to_16k_mono(wav: &[u8]) -> anyhow::Result<Vec<i16>> {
+    let (format, data) = parse_wav(wav)?;
+    if format.bits_per_sample != 16 {
+        anyhow::bail!("Unsupported WAV: {} bits, need 16", format.bits_per_sample);
+    }
+    if format.channels == 0 || format.sample_rate == 0 {
+        anyhow::bail!("Invalid WAV format {:?}", format);
+    }
+    let samples = crate::protocol::decode_pcm_le(data);
+    Ok(resample_to_16k_mono(
+        &samples,
+        format.sample_rate,
+        format.channels,
+    ))
+}
+
+/// Mixes interleaved `channels` down to mono and linearly resamples from
+/// `in_rate` to 16kHz. There is no low-pass filter, which is fine for short
+/// prompt sounds but aliases content above 8kHz.
+pub fn resample_to_16k_mono(samples: &[i16], in_rate: u32, channels: u16) -> Vec<i16> {
+    let channels = channels.max(1) as usize;
+    let mono: Vec<i16> = samples
+        .chunks_exact(channels)
+        .map(|frame| (frame.iter().map(|s| *s as i32).sum::<i32>() / channels as i32) as i16)
+        .collect();
+    if in_rate == SAMPLE_RATE || mono.is_empty() {
+        return mono;
+    }
+
+    let out_len = (mono.len() as u64 * SAMPLE_RATE as u64 / in_rate as u64) as usize;
+    let step = in_rate as f64 / SAMPLE_RATE as f64;
+    (0..out_len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let idx = pos as usize;
+            let frac = pos - idx as f64;
+            let a = mono[idx] as f64;
+            let b = *mono.get(idx + 1).unwrap_or(&mono[idx]) as f64;
+            (a + (b - a) * frac).round() as i16
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_wav() {
+    let wav = |channels: u16, rate: u32, bits: u16, data: &[u8]| {
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        wav.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        wav.extend_from_slice(b"fmt \x10\0\0\0\x01\0");
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&rate.to_le_bytes());
+        wav.extend_from_slice(&(rate * channels as u32 * bits as u32 / 8).to_le_bytes());
+        wav.extend_from_slice(&(channels * bits / 8).to_le_bytes());
+        wav.extend_from_slice(&bits.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(data);
+        wav
+    };
+
+    let stereo = wav(2, 48000, 16, &[1, 2, 3, 4]);
+    let (format, data) = parse_wav(&stereo).unwrap();
+    assert_eq!(
+        format,
+        WavFormat {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 16
+        }
+    );
+    assert_eq!(data, [1, 2, 3, 4]);
+
+    assert_eq!(
+        wav_to_16k_mono(&wav(1, 16000, 16, &[1, 0, 2, 0])).unwrap(),
+        [1, 2]
+    );
+    assert_eq!(
+        wav_to_16k_mono(&wav(2, 16000, 16, &[10, 0, 20, 0])).unwrap(),
+        [15]
+    );
+    assert!(wav_to_16k_mono(&wav(1, 16000, 8, &[1, 2, 3, 4])).is_err());
+
+    let mut truncated = wav(1, 16000, 16, &[1, 2, 3, 4]);
+    truncated.pop();
+    assert!(parse_wav(&truncated).is_err());
+    assert!(parse_wav(b"GIF89a").is_err());
+
+    assert!(!wav_to_16k_mono(WAKE_WAV).unwrap().is_empty());
+}
+
+#[test]
+fn test_resample_to_16k_mono() {
+    // 1s of a 1kHz sine at 48kHz, stereo
+    let amplitude = 30000.0;
+    let sine: Vec<i16> = (0..48000)
+        .flat_map(|i| {
+            let s = (amplitude * (2.0 * std::f64::consts::PI * 1000.0 * i as f64 / 48000.0).sin())
+                as i16;
+            [s, s]
+        })
+        .collect();
+
+    let out = resample_to_16k_mono(&sine, 48000, 2);
+    assert_eq!(out.len(), 16000);
+    assert!(out.iter().all(|s| (s.unsigned_abs() as f64) <= amplitude));
+    // still a 1kHz sine: 16 samples per period
+    assert!((out[4] as f64 - amplitude).abs() < 100.0);
+    assert!((out[12] as f64 + amplitude).abs() < 100.0);
+
+    let out = resample_to_16k_mono(&vec![100; 44100], 44100, 1);
+    assert_eq!(out.len(), 16000);
+    assert!(out.iter().all(|s| *s == 100));
+
+    assert_eq!(resample_to_16k_mono(&[1, 2, 3], 16000, 1), [1, 2, 3]);
+    assert_eq!(resample_to_16k_mono(&[1, 2], 8000, 1).len(), 4);
+    assert!(resample_to_16k_mono(&[], 48000, 2).is_empty());
+}
+
 pub type PlayerTx = tokio::sync::mpsc::UnboundedSender<AudioEvent>;
 pub type PlayerRx = tokio::sync::mpsc::UnboundedReceiver<AudioEvent>;
 pub type EventTx = tokio::sync::mpsc::Sender<crate::app::Event>;
@@ -209,6 +457,13 @@ fn afe_worker(afe_handle: Arc<AFE>, tx: EventTx) -> anyhow::Result<()> {
             continue;
         }
 
+        #[cfg(feature = "wakeword")]
+        if result.wakeup {
+            log::info!("Wake word detected");
+            tx.blocking_send(crate::app::Event::Event(crate::app::Event::WAKE_WORD))
+                .map_err(|_| anyhow::anyhow!("Failed to send data"))?;
+        }
+
         let global_vad = VAD_ACTIVE.load(std::sync::atomic::Ordering::Relaxed);
 
         if result.speech {
@@ -290,7 +545,14 @@ pub enum AudioEvent {
     SpeechChunki16(Vec<i16>),
     SpeechChunki16WithVowel(Vec<i16>, u8),
     EndSpeech(Arc<tokio::sync::Notify>),
+    /// Sets the volume level, and unmutes.
     VolSet(u8),
+    /// Plays silence instead of speech until unmuted, see `SendBuffer::set_mute`.
+    Mute(bool),
+    /// Stops the I2S channels (and with them the AFE) for power saving, drops
+    /// queued speech. Events sent meanwhile are still handled; speech queued
+    /// while paused plays once resumed.
+    Pause(bool),
 }
 
 pub enum SendBufferItem {
@@ -299,23 +561,79 @@ pub enum SendBufferItem {
     EndSpeech(Arc<tokio::sync::Notify>),
 }
 
+/// Mean absolute sample value, 0 for an empty slice.
+pub fn mean_amplitude(data: &[i16]) -> u16 {
+    if data.is_empty() {
+        return 0;
+    }
+    let sum: u64 = data.iter().map(|s| s.unsigned_abs() as u64).sum();
+    (sum / data.len() as u64) as u16
+}
+
+#[test]
+fn test_mean_amplitude() {
+    assert_eq!(mean_amplitude(&[]), 0);
+    assert_eq!(mean_amplitude(&[100, -100, 0, 200]), 100);
+    assert_eq!(mean_amplitude(&[i16::MIN, i16::MIN]), 32768);
+}
+
 pub struct SendBuffer {
     pub cache: std::collections::LinkedList<SendBufferItem>,
     pub chunk_size: usize,
     pub rest: Vec<i16>,
-    pub volume: i16,
+    /// Volume level the gain is ramping towards.
+    pub target_volume: i16,
+    /// Gain applied to the next sample, in the same 1/256 units as `VolumeCurve`.
+    pub current_volume: f32,
+    /// How much `current_volume` moves per sample while ramping.
+    volume_step: f32,
+    /// Volume to go back to on unmute, `Some` while muted.
+    pub unmuted_volume: Option<i16>,
+}
+
+/// Volume changes are spread over this many samples (50ms at 16kHz) instead of
+/// applied at once, since a sudden gain jump mid-playback clicks.
+const VOLUME_RAMP_SAMPLES: f32 = 800.0;
+
+fn volume_gain(volume: i16) -> f32 {
+    let curve = &crate::boards::VOLUME_CURVE;
+    curve[(volume.max(0) as usize).min(curve.len() - 1)] as f32
 }
 
+/// Gain for each volume level 0..=5, in 1/256 steps (256 = unity gain).
+///
+/// Each board picks its own curve as `boards::VOLUME_CURVE`. The user-facing
+/// 1..=5 level from `AudioEvent::VolSet` indexes into it, and levels above 5
+/// use the last entry.
+pub type VolumeCurve = [u16; 6];
+
 #[inline]
-fn get_volume(value: i16, volume: i16) -> i16 {
-    match volume {
-        0 => 0,
-        1 => value / 16,
-        2 => value / 8,
-        3 => value / 4,
-        4 => value / 2,
-        _ => value,
+pub fn scale_volume(value: i16, curve: &VolumeCurve, level: u8) -> i16 {
+    let gain = curve[(level as usize).min(curve.len() - 1)] as i32;
+    (value as i32 * gain / 256).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+#[test]
+fn test_scale_volume() {
+    const CURVE: VolumeCurve = [0, 16, 32, 64, 128, 256];
+    for value in [i16::MIN, -12345, -17, -1, 0, 1, 17, 12345, i16::MAX] {
+        assert_eq!(scale_volume(value, &CURVE, 0), 0);
+        assert_eq!(scale_volume(value, &CURVE, 1), value / 16);
+        assert_eq!(scale_volume(value, &CURVE, 2), value / 8);
+        assert_eq!(scale_volume(value, &CURVE, 3), value / 4);
+        assert_eq!(scale_volume(value, &CURVE, 4), value / 2);
+        assert_eq!(scale_volume(value, &CURVE, 5), value);
+        assert_eq!(scale_volume(value, &CURVE, 9), value);
     }
+
+    let loud: VolumeCurve = [0, 64, 128, 256, 384, 512];
+    assert_eq!(scale_volume(20000, &loud, 5), i16::MAX);
+    assert_eq!(scale_volume(-20000, &loud, 5), i16::MIN);
+}
+
+#[inline]
+fn get_volume(value: i16, volume: i16) -> i16 {
+    scale_volume(value, &crate::boards::VOLUME_CURVE, volume.max(0) as u8)
 }
 
 impl SendBuffer {
@@ -324,83 +642,83 @@ impl SendBuffer {
             cache: std::collections::LinkedList::new(),
             chunk_size,
             rest: Vec::new(),
-            volume: 3,
+            target_volume: 3,
+            current_volume: volume_gain(3),
+            volume_step: 0.0,
+            unmuted_volume: None,
         }
     }
 
-    pub fn push_u8(&mut self, data: &[u8]) {
-        if self.rest.len() > 0 {
-            let needed = self.chunk_size * 2 - self.rest.len() * 2;
-            if data.len() >= needed {
-                let mut to_add = vec![0i16; needed / 2];
-                for i in 0..(needed / 2) {
-                    to_add[i] = i16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
-                }
-                self.rest.extend_from_slice(&to_add);
-                let mut v = std::mem::take(&mut self.rest);
-                v.iter_mut().for_each(|x| {
-                    *x = get_volume(*x, self.volume);
-                });
+    /// Ramps to `volume` over `VOLUME_RAMP_SAMPLES` of the audio pushed next.
+    pub fn set_volume(&mut self, volume: i16) {
+        self.target_volume = volume;
+        self.unmuted_volume = None;
+        self.volume_step = (volume_gain(volume) - self.current_volume).abs() / VOLUME_RAMP_SAMPLES;
+    }
 
-                self.cache.push_back(SendBufferItem::Audio(v));
+    /// Switches to `volume` at once, for when nothing is playing.
+    pub fn set_volume_now(&mut self, volume: i16) {
+        self.target_volume = volume;
+        self.unmuted_volume = None;
+        self.current_volume = volume_gain(volume);
+        self.volume_step = 0.0;
+    }
 
-                self.push_u8(&data[needed..]);
-            } else {
-                let mut to_add = vec![0i16; data.len() / 2];
-                for i in 0..(data.len() / 2) {
-                    to_add[i] = i16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+    /// Muting drops the volume to 0 and silences audio already queued, so the
+    /// speaker (and the AEC reference built from the same chunks) keeps
+    /// getting frames, just silent ones. Unmuting ramps back to the previous
+    /// volume.
+    pub fn set_mute(&mut self, mute: bool) {
+        match (mute, self.unmuted_volume) {
+            (true, None) => {
+                let volume = self.target_volume;
+                self.set_volume_now(0);
+                self.unmuted_volume = Some(volume);
+                for item in self.cache.iter_mut() {
+                    if let SendBufferItem::Audio(v) = item {
+                        v.fill(0);
+                    }
                 }
-                self.rest.extend_from_slice(&to_add);
             }
-            return;
-        }
-
-        for chunk in data.chunks(self.chunk_size * 2) {
-            let mut v = vec![0i16; chunk.len() / 2];
-
-            for i in 0..(chunk.len() / 2) {
-                v[i] = i16::from_le_bytes([chunk[i * 2], chunk[i * 2 + 1]]);
-            }
-            if v.len() < self.chunk_size {
-                self.rest = v;
-            } else {
-                v.iter_mut().for_each(|x| {
-                    *x = get_volume(*x, self.volume);
-                });
-                self.cache.push_back(SendBufferItem::Audio(v));
+            (false, Some(volume)) => {
+                self.set_volume(volume);
             }
+            _ => {}
         }
     }
 
-    pub fn push_i16(&mut self, data: &[i16]) {
-        if self.rest.len() > 0 {
-            let needed = self.chunk_size - self.rest.len();
-            if data.len() >= needed {
-                self.rest.extend_from_slice(&data[0..needed]);
-                let mut v = std::mem::take(&mut self.rest);
-                v.iter_mut().for_each(|x| {
-                    *x = get_volume(*x, self.volume);
-                });
+    pub fn push_u8(&mut self, data: &[u8]) {
+        self.push_i16(&crate::protocol::decode_pcm_le(data));
+    }
 
-                self.cache.push_back(SendBufferItem::Audio(v));
+    /// Appends samples, queueing every full `chunk_size` chunk and keeping the
+    /// remainder in `rest` for the next call.
+    pub fn push_i16(&mut self, mut data: &[i16]) {
+        while !data.is_empty() {
+            let n = (self.chunk_size - self.rest.len()).min(data.len());
+            self.rest.extend_from_slice(&data[..n]);
+            data = &data[n..];
 
-                self.push_i16(&data[needed..]);
-            } else {
-                self.rest.extend_from_slice(data);
+            if self.rest.len() == self.chunk_size {
+                let mut v = std::mem::take(&mut self.rest);
+                self.apply_volume(&mut v);
+                self.cache.push_back(SendBufferItem::Audio(v));
             }
-            return;
         }
+    }
 
-        for chunk in data.chunks(self.chunk_size) {
-            if chunk.len() < self.chunk_size {
-                self.rest = chunk.to_vec();
-            } else {
-                let mut v = vec![0i16; chunk.len()];
-                for i in 0..chunk.len() {
-                    v[i] = get_volume(chunk[i], self.volume);
-                }
-                self.cache.push_back(SendBufferItem::Audio(v));
+    /// Scales `samples` by the current gain, stepping it towards the target
+    /// volume on every sample without going past it.
+    fn apply_volume(&mut self, samples: &mut [i16]) {
+        let target = volume_gain(self.target_volume);
+        for x in samples {
+            if self.current_volume < target {
+                self.current_volume = (self.current_volume + self.volume_step).min(target);
+            } else if self.current_volume > target {
+                self.current_volume = (self.current_volume - self.volume_step).max(target);
             }
+            *x = (*x as f32 * self.current_volume / 256.0).clamp(i16::MIN as f32, i16::MAX as f32)
+                as i16;
         }
     }
 
@@ -441,6 +759,105 @@ impl SendBuffer {
     }
 }
 
+#[test]
+fn test_send_buffer_large_push() {
+    let mut buffer = SendBuffer::new(512);
+    // full volume, so samples pass through unscaled
+    buffer.set_volume_now(5);
+
+    buffer.push_i16(&[1; 100]);
+    assert!(buffer.cache.is_empty());
+    assert_eq!(buffer.rest.len(), 100);
+
+    let samples: Vec<i16> = (0..32 * 1024).map(|i| i as i16).collect();
+    buffer.push_u8(&crate::protocol::encode_pcm_le(&samples));
+
+    let total = 100 + samples.len();
+    assert_eq!(buffer.cache.len(), total / 512);
+    assert_eq!(buffer.rest.len(), total % 512);
+
+    let mut out = Vec::new();
+    while let Some(SendBufferItem::Audio(v)) = buffer.get_chunk() {
+        assert_eq!(v.len(), 512);
+        out.extend(v);
+    }
+    out.extend_from_slice(&buffer.rest);
+    assert_eq!(&out[..100], &[1; 100]);
+    assert_eq!(&out[100..], &samples[..]);
+}
+
+#[test]
+fn test_send_buffer_mute() {
+    let mut buffer = SendBuffer::new(4);
+    buffer.set_volume_now(5);
+    buffer.push_i16(&[100; 4]);
+
+    buffer.set_mute(true);
+    buffer.set_mute(true);
+    assert_eq!(buffer.target_volume, 0);
+    buffer.push_i16(&[100; 4]);
+    // queued and new chunks are both silent, but still there to play
+    for _ in 0..2 {
+        assert!(matches!(buffer.get_chunk(), Some(SendBufferItem::Audio(v)) if v == [0; 4]));
+    }
+
+    // unmuting fades back in
+    buffer.set_mute(false);
+    assert_eq!(buffer.target_volume, 5);
+    buffer.push_i16(&[100; 1000]);
+    let Some(SendBufferItem::Audio(first)) = buffer.get_chunk() else {
+        panic!("expected audio");
+    };
+    assert!(first[0] < 100);
+    while let Some(SendBufferItem::Audio(v)) = buffer.get_chunk() {
+        if buffer.cache.is_empty() {
+            assert_eq!(v, [100; 4]);
+        }
+    }
+
+    // changing the volume while muted unmutes
+    buffer.set_mute(true);
+    buffer.set_volume(4);
+    assert_eq!(buffer.target_volume, 4);
+    assert_eq!(buffer.unmuted_volume, None);
+}
+
+#[test]
+fn test_send_buffer_volume_ramp() {
+    let mut buffer = SendBuffer::new(256);
+    buffer.set_volume_now(5);
+    let loud = volume_gain(5);
+
+    for (level, gain) in [(1, volume_gain(1)), (5, loud), (0, 0.0)] {
+        let from = buffer.current_volume;
+        buffer.set_volume(level);
+        buffer.push_i16(&[10000; 256 * 5]);
+
+        let mut out = Vec::new();
+        while let Some(SendBufferItem::Audio(v)) = buffer.get_chunk() {
+            out.extend(v);
+        }
+        let target = (10000.0 * gain / 256.0) as i16;
+        let start = (10000.0 * from / 256.0) as i16;
+
+        // no snap: the first sample is still close to the old level
+        assert!((out[0] - start).abs() <= (start - target).abs() / 100 + 1);
+        // moves one way only and never past the target
+        for w in out.windows(2) {
+            if target < start {
+                assert!(w[1] <= w[0] && w[1] >= target);
+            } else {
+                assert!(w[1] >= w[0] && w[1] <= target);
+            }
+        }
+        // and gets there within the ramp
+        assert!(out[VOLUME_RAMP_SAMPLES as usize..]
+            .iter()
+            .all(|&x| x == target));
+        assert_eq!(buffer.current_volume, gain);
+    }
+}
+
 struct RingBuffer<const MAX: usize> {
     buff: Vec<Vec<i16>>,
     start_index: usize,
@@ -476,11 +893,143 @@ impl<const MAX: usize> RingBuffer<MAX> {
 const CHUNK_SIZE: usize = 256;
 // const CHUNK_SIZE: usize = 512;
 
+/// Set at boot to run `passthrough_task_run` instead of `audio_task_run`.
+///
+/// Passthrough loops the raw mic straight to the speaker, so the AFE and its
+/// AEC are not in the path: whatever the speaker plays is picked up by the mic
+/// again and, at high volume, builds up into a howl. Putting the AEC back in
+/// would not help either, because the playback reference would be a delayed copy
+/// of the mic signal and the canceller would remove the talker together with the
+/// echo. The loop is therefore played at `PASSTHROUGH_VOLUME`; test with
+/// headphones or with the speaker facing away from the mic.
+#[cfg(feature = "passthrough")]
+pub static PASSTHROUGH: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+#[cfg(feature = "passthrough")]
+const PASSTHROUGH_VOLUME: i16 = 2;
+
+#[cfg(feature = "passthrough")]
+fn passthrough_task_run(
+    fn_read: &mut dyn FnMut(&mut [i16]) -> Result<usize, esp_idf_svc::sys::EspError>,
+    fn_write: &mut dyn FnMut(&[i16]) -> Result<usize, esp_idf_svc::sys::EspError>,
+) -> anyhow::Result<()> {
+    log::info!("Passthrough loop started");
+    let mut buffer = vec![0i16; CHUNK_SIZE];
+
+    loop {
+        let len = fn_read(&mut buffer)? / 2;
+        for x in buffer[..len].iter_mut() {
+            *x = get_volume(*x, PASSTHROUGH_VOLUME);
+        }
+        fn_write(&buffer[..len])?;
+    }
+}
+
+/// Longest utterance `echo_test` plays back, 10s at 16kHz; the rest is dropped.
+pub const ECHO_TEST_MAX_SAMPLES: usize = 10 * SAMPLE_RATE as usize;
+
+/// Echo test for checking the mic and speaker without a server: each utterance
+/// is recorded until `MicAudioEnd` and then played back through the player,
+/// so unlike passthrough it goes through the AFE and the normal playback path.
+/// Runs until `evt_rx` closes; `on_state` gets a short status for the screen.
+pub async fn echo_test(
+    player_tx: &PlayerTx,
+    evt_rx: &mut EventRx,
+    mut on_state: impl FnMut(&str),
+) -> anyhow::Result<()> {
+    let played = Arc::new(tokio::sync::Notify::new());
+    let mut recording: Vec<i16> = Vec::new();
+    on_state("Echo test: speak");
+
+    while let Some(evt) = evt_rx.recv().await {
+        match evt {
+            crate::app::Event::MicAudioChunk(data) => {
+                // leave the end of the utterance to the AFE, so it sends MicAudioEnd
+                VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                if recording.is_empty() {
+                    on_state("Echo test: recording");
+                }
+                let room = ECHO_TEST_MAX_SAMPLES - recording.len();
+                recording.extend_from_slice(&data[..data.len().min(room)]);
+            }
+            crate::app::Event::MicAudioEnd if !recording.is_empty() => {
+                log::info!("Echo test playing back {} samples", recording.len());
+                on_state("Echo test: playing");
+                player_tx
+                    .send(AudioEvent::StartSpeech)
+                    .map_err(|_| anyhow::anyhow!("Error sending start"))?;
+                player_tx
+                    .send(AudioEvent::SpeechChunki16(std::mem::take(&mut recording)))
+                    .map_err(|_| anyhow::anyhow!("Error sending echo"))?;
+                player_tx
+                    .send(AudioEvent::EndSpeech(played.clone()))
+                    .map_err(|_| anyhow::anyhow!("Error sending end"))?;
+                played.notified().await;
+
+                // don't record the playback itself
+                while evt_rx.try_recv().is_ok() {}
+                VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+                on_state("Echo test: speak");
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// I2S reads that came back short since boot, for telemetry.
+static READ_MISMATCHES: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+pub fn read_mismatches() -> u32 {
+    READ_MISMATCHES.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Consecutive short reads before they are logged as an error, about 1.6s of
+/// audio; logged again every as many more.
+const READ_MISMATCH_ESCALATE: u32 = 100;
+
+/// Run of consecutive short I2S reads in `audio_task_run`. A short read only
+/// drops that chunk; the loop keeps going so the driver can resync.
+#[derive(Default)]
+struct ReadMismatches {
+    consecutive: u32,
+}
+
+impl ReadMismatches {
+    /// Counts a short read, true when the run should be escalated.
+    fn mismatch(&mut self) -> bool {
+        READ_MISMATCHES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.consecutive += 1;
+        self.consecutive % READ_MISMATCH_ESCALATE == 0
+    }
+
+    /// A full read, returns the length of the run it ended, if any.
+    fn ok(&mut self) -> Option<u32> {
+        (self.consecutive > 0).then(|| std::mem::take(&mut self.consecutive))
+    }
+}
+
+#[test]
+fn test_read_mismatches() {
+    let mut m = ReadMismatches::default();
+    assert_eq!(m.ok(), None);
+    assert!(!m.mismatch());
+    assert_eq!(m.ok(), Some(1));
+    assert_eq!(m.ok(), None);
+
+    let escalated = (0..READ_MISMATCH_ESCALATE * 2)
+        .filter(|_| m.mismatch())
+        .count();
+    assert_eq!(escalated, 2);
+    assert_eq!(m.ok(), Some(READ_MISMATCH_ESCALATE * 2));
+}
+
 fn audio_task_run(
     rx: &mut tokio::sync::mpsc::UnboundedReceiver<AudioEvent>,
     tx: EventTx,
     fn_read: &mut dyn FnMut(&mut [i16]) -> Result<usize, esp_idf_svc::sys::EspError>,
     fn_write: &mut dyn FnMut(&[i16]) -> Result<usize, esp_idf_svc::sys::EspError>,
+    fn_pause: &mut dyn FnMut(bool) -> Result<(), esp_idf_svc::sys::EspError>,
     afe_handle: Arc<AFE>,
 ) -> anyhow::Result<()> {
     let mut conf =
@@ -520,10 +1069,21 @@ fn audio_task_run(
 
     let mut hello_wav = WAKE_WAV.to_vec();
 
-    send_buffer.volume = 5;
+    send_buffer.set_volume_now(5);
+    let mut mismatches = ReadMismatches::default();
+    let mut paused = false;
 
     loop {
-        if let Ok(event) = rx.try_recv() {
+        // nothing to read or play while paused, sleep until the next event
+        let event = if paused {
+            Some(
+                rx.blocking_recv()
+                    .ok_or_else(|| anyhow::anyhow!("Audio event channel closed"))?,
+            )
+        } else {
+            rx.try_recv().ok()
+        };
+        if let Some(event) = event {
             match event {
                 AudioEvent::Hello(notify) => {
                     log::info!("Received Hello event");
@@ -550,10 +1110,25 @@ fn audio_task_run(
                     send_buffer.push_back_end_speech(sender);
                 }
                 AudioEvent::VolSet(vol) => {
-                    send_buffer.volume = vol as i16;
+                    send_buffer.set_volume(vol as i16);
+                }
+                AudioEvent::Mute(mute) => {
+                    send_buffer.set_mute(mute);
                 }
+                AudioEvent::Pause(pause) if pause != paused => {
+                    log::info!("Audio {}", if pause { "paused" } else { "resumed" });
+                    if pause {
+                        send_buffer.clear();
+                    }
+                    fn_pause(pause)?;
+                    paused = pause;
+                }
+                AudioEvent::Pause(_) => {}
             }
         }
+        if paused {
+            continue;
+        }
         let play_data_ = {
             loop {
                 break match send_buffer.get_chunk() {
@@ -578,13 +1153,25 @@ fn audio_task_run(
         let len = fn_read(&mut read_buffer)?;
 
         if len != feed_chunksize * 2 {
-            log::warn!(
-                "Read size mismatch: expected {}, got {}",
-                feed_chunksize * 2,
-                len
-            );
-            break;
+            if mismatches.mismatch() {
+                log::error!(
+                    "I2S reads short for {} chunks in a row (expected {}, got {}), {} since boot",
+                    mismatches.consecutive,
+                    feed_chunksize * 2,
+                    len,
+                    read_mismatches()
+                );
+            } else {
+                log::warn!(
+                    "Read size mismatch: expected {}, got {}",
+                    feed_chunksize * 2,
+                    len
+                );
+            }
         } else {
+            if let Some(run) = mismatches.ok() {
+                log::info!("I2S reads back in sync after {} short reads", run);
+            }
             let total = len / 2;
             let mut samples_with_ref = Vec::with_capacity(total);
 
@@ -597,11 +1184,18 @@ fn audio_task_run(
         }
         ring_cache_buffer.push(play_data.to_vec());
     }
-
-    log::warn!("I2S loop exited");
-    Ok(())
 }
 
+/// I2S DMA buffering used by the audio workers unless a board overrides it.
+///
+/// Total buffered audio is `count * frames` samples per direction (64ms at
+/// 16kHz with the defaults). More or larger buffers ride out scheduling
+/// hiccups that otherwise underrun into choppy playback, at the cost of that
+/// much added latency and internal RAM; smaller ones cut latency but underrun
+/// sooner.
+pub const DEFAULT_DMA_BUFFER_COUNT: u32 = 2;
+pub const DEFAULT_FRAMES_PER_BUFFER: u32 = 512;
+
 pub struct BoxAudioWorker {
     pub i2s: I2S0,
     pub bclk: AnyIOPin,
@@ -609,6 +1203,10 @@ pub struct BoxAudioWorker {
     pub dout: AnyIOPin,
     pub ws: AnyIOPin,
     pub mclk: Option<AnyIOPin>,
+
+    /// See `DEFAULT_DMA_BUFFER_COUNT`.
+    pub dma_buffer_count: u32,
+    pub frames_per_buffer: u32,
 }
 
 impl BoxAudioWorker {
@@ -616,8 +1214,8 @@ impl BoxAudioWorker {
         let i2s_config = config::StdConfig::new(
             config::Config::default()
                 .auto_clear(true)
-                .dma_buffer_count(2)
-                .frames_per_buffer(512),
+                .dma_buffer_count(self.dma_buffer_count)
+                .frames_per_buffer(self.frames_per_buffer),
             config::StdClkConfig::from_sample_rate_hz(SAMPLE_RATE),
             config::StdSlotConfig::philips_slot_default(
                 config::DataBitWidth::Bits16,
@@ -640,9 +1238,11 @@ impl BoxAudioWorker {
         let (mut rx_driver, mut tx_driver) = driver.split();
         rx_driver.rx_enable()?;
         tx_driver.tx_enable()?;
+        // shared with `fn_pause`
+        let (rx_driver, tx_driver) = (RefCell::new(rx_driver), RefCell::new(tx_driver));
 
         let mut fn_write = |play_data: &[i16]| -> Result<usize, esp_idf_svc::sys::EspError> {
-            tx_driver.write(
+            tx_driver.borrow_mut().write(
                 unsafe {
                     std::slice::from_raw_parts(
                         play_data.as_ptr() as *const u8,
@@ -661,13 +1261,30 @@ impl BoxAudioWorker {
                 )
             };
 
-            rx_driver.read(
+            rx_driver.borrow_mut().read(
                 read_buffer_,
                 esp_idf_svc::hal::delay::TickType::new_millis(50).0,
             )
         };
 
-        let afe_handle = Arc::new(AFE::new());
+        // disabled channels release their PM lock, so the chip can light sleep
+        let mut fn_pause = |pause: bool| -> Result<(), esp_idf_svc::sys::EspError> {
+            let (mut rx_driver, mut tx_driver) = (rx_driver.borrow_mut(), tx_driver.borrow_mut());
+            if pause {
+                rx_driver.rx_disable()?;
+                tx_driver.tx_disable()
+            } else {
+                rx_driver.rx_enable()?;
+                tx_driver.tx_enable()
+            }
+        };
+
+        #[cfg(feature = "passthrough")]
+        if PASSTHROUGH.load(std::sync::atomic::Ordering::Relaxed) {
+            return passthrough_task_run(&mut fn_read, &mut fn_write);
+        }
+
+        let afe_handle = Arc::new(AFE::new(AFE_TUNING.lock().unwrap().clone()));
         let afe_handle_ = afe_handle.clone();
         crate::log_heap();
         let tx_ = tx.clone();
@@ -679,7 +1296,14 @@ impl BoxAudioWorker {
             }
         })?;
 
-        audio_task_run(&mut rx, tx_, &mut fn_read, &mut fn_write, afe_handle)
+        audio_task_run(
+            &mut rx,
+            tx_,
+            &mut fn_read,
+            &mut fn_write,
+            &mut fn_pause,
+            afe_handle,
+        )
     }
 }
 
@@ -695,26 +1319,51 @@ pub struct BoardsAudioWorker {
     pub in_clk: AnyIOPin,
     pub din: AnyIOPin,
     pub in_mclk: Option<AnyIOPin>,
+
+    /// See `DEFAULT_DMA_BUFFER_COUNT`.
+    pub dma_buffer_count: u32,
+    pub frames_per_buffer: u32,
+    /// Drive both channels of a stereo DAC with the (mono) speech. The AEC
+    /// reference stays mono, it is taken before the samples are duplicated.
+    pub stereo_out: bool,
+}
+
+/// Writes each sample of `mono` twice into `stereo`, as interleaved L/R.
+fn mono_to_stereo(mono: &[i16], stereo: &mut Vec<i16>) {
+    stereo.clear();
+    stereo.extend(mono.iter().flat_map(|&s| [s, s]));
+}
+
+#[test]
+fn test_mono_to_stereo() {
+    let mut stereo = vec![9];
+    mono_to_stereo(&[1, -2, 3], &mut stereo);
+    assert_eq!(stereo, [1, 1, -2, -2, 3, 3]);
+    mono_to_stereo(&[], &mut stereo);
+    assert!(stereo.is_empty());
 }
 
 impl BoardsAudioWorker {
     pub fn run(self, mut rx: PlayerRx, tx: EventTx) -> anyhow::Result<()> {
-        let i2s_config = config::StdConfig::new(
-            config::Config::default()
-                .auto_clear(true)
-                .dma_buffer_count(2)
-                .frames_per_buffer(512),
-            config::StdClkConfig::from_sample_rate_hz(SAMPLE_RATE),
-            config::StdSlotConfig::philips_slot_default(
-                config::DataBitWidth::Bits16,
-                config::SlotMode::Mono,
-            ),
-            config::StdGpioConfig::default(),
-        );
+        let (dma_buffer_count, frames_per_buffer) = (self.dma_buffer_count, self.frames_per_buffer);
+        let i2s_config = |slot_mode| {
+            config::StdConfig::new(
+                config::Config::default()
+                    .auto_clear(true)
+                    .dma_buffer_count(dma_buffer_count)
+                    .frames_per_buffer(frames_per_buffer),
+                config::StdClkConfig::from_sample_rate_hz(SAMPLE_RATE),
+                config::StdSlotConfig::philips_slot_default(
+                    config::DataBitWidth::Bits16,
+                    slot_mode,
+                ),
+                config::StdGpioConfig::default(),
+            )
+        };
 
         let mut rx_driver = I2sDriver::new_std_rx(
             self.in_i2s,
-            &i2s_config,
+            &i2s_config(config::SlotMode::Mono),
             self.in_clk,
             self.din,
             self.in_mclk,
@@ -723,9 +1372,14 @@ impl BoardsAudioWorker {
         .map_err(|e| anyhow::anyhow!("Error create RX: {:?}", e))?;
         rx_driver.rx_enable()?;
 
+        let out_slot_mode = if self.stereo_out {
+            config::SlotMode::Stereo
+        } else {
+            config::SlotMode::Mono
+        };
         let mut tx_driver = I2sDriver::new_std_tx(
             self.out_i2s,
-            &i2s_config,
+            &i2s_config(out_slot_mode),
             self.out_clk,
             self.dout,
             self.out_mclk,
@@ -733,6 +1387,8 @@ impl BoardsAudioWorker {
         )
         .map_err(|e| anyhow::anyhow!("Error create TX: {:?}", e))?;
         tx_driver.tx_enable()?;
+        // shared with `fn_pause`
+        let (rx_driver, tx_driver) = (RefCell::new(rx_driver), RefCell::new(tx_driver));
 
         let mut fn_read = |read_buffer: &mut [i16]| -> Result<usize, esp_idf_svc::sys::EspError> {
             let read_buffer_ = unsafe {
@@ -742,13 +1398,21 @@ impl BoardsAudioWorker {
                 )
             };
 
-            rx_driver.read(
+            rx_driver.borrow_mut().read(
                 read_buffer_,
                 esp_idf_svc::hal::delay::TickType::new_millis(50).0,
             )
         };
+        let stereo_out = self.stereo_out;
+        let mut stereo_buffer = Vec::new();
         let mut fn_write = |play_data: &[i16]| -> Result<usize, esp_idf_svc::sys::EspError> {
-            tx_driver.write(
+            let play_data = if stereo_out {
+                mono_to_stereo(play_data, &mut stereo_buffer);
+                stereo_buffer.as_slice()
+            } else {
+                play_data
+            };
+            tx_driver.borrow_mut().write(
                 unsafe {
                     std::slice::from_raw_parts(
                         play_data.as_ptr() as *const u8,
@@ -759,7 +1423,24 @@ impl BoardsAudioWorker {
             )
         };
 
-        let afe_handle = Arc::new(AFE::new());
+        // disabled channels release their PM lock, so the chip can light sleep
+        let mut fn_pause = |pause: bool| -> Result<(), esp_idf_svc::sys::EspError> {
+            let (mut rx_driver, mut tx_driver) = (rx_driver.borrow_mut(), tx_driver.borrow_mut());
+            if pause {
+                rx_driver.rx_disable()?;
+                tx_driver.tx_disable()
+            } else {
+                rx_driver.rx_enable()?;
+                tx_driver.tx_enable()
+            }
+        };
+
+        #[cfg(feature = "passthrough")]
+        if PASSTHROUGH.load(std::sync::atomic::Ordering::Relaxed) {
+            return passthrough_task_run(&mut fn_read, &mut fn_write);
+        }
+
+        let afe_handle = Arc::new(AFE::new(AFE_TUNING.lock().unwrap().clone()));
         let afe_handle_ = afe_handle.clone();
 
         let tx_ = tx.clone();
@@ -771,6 +1452,13 @@ impl BoardsAudioWorker {
             }
         })?;
 
-        audio_task_run(&mut rx, tx_, &mut fn_read, &mut fn_write, afe_handle)
+        audio_task_run(
+            &mut rx,
+            tx_,
+            &mut fn_read,
+            &mut fn_write,
+            &mut fn_pause,
+            afe_handle,
+        )
     }
 }
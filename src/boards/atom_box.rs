@@ -3,8 +3,21 @@ use esp_idf_svc::{
     sys::EspError,
 };
 
+/// Pixel format of the panel, see `ui::ColorFormat`. The vendor driver sets
+/// the panel up for Rgb565, so this stays in step with it.
+pub type ColorFormat = embedded_graphics::pixelcolor::Rgb565;
+/// Byte order of `ColorFormat` in the framebuffer.
+pub type ColorByteOrder = embedded_graphics::pixelcolor::raw::LittleEndian;
+
 const AUDIO_STACK_SIZE: usize = 15 * 1024;
 pub const AFE_AEC_OFFSET: usize = 512;
+/// Software gain for volume levels 0..=5, see `audio::scale_volume`.
+pub const VOLUME_CURVE: crate::audio::VolumeCurve = [0, 16, 32, 64, 128, 256];
+/// es8311 codec volume for levels 0..=5, used by `set_volum`.
+///
+/// `AudioEvent::VolSet` only changes the software gain from `VOLUME_CURVE`;
+/// the codec stays at the level set in `audio_init` unless `set_volum` is called.
+pub const HARDWARE_VOLUME_CURVE: [i32; 6] = [20, 30, 40, 50, 60, 70];
 
 pub fn afe_config(afe_config: &mut esp_idf_svc::sys::esp_sr::afe_config_t) {
     afe_config.agc_init = true;
@@ -45,6 +58,8 @@ pub fn start_audio_workers(
         dout: dout.into(),
         ws: ws.into(),
         mclk: None,
+        dma_buffer_count: crate::audio::DEFAULT_DMA_BUFFER_COUNT,
+        frames_per_buffer: crate::audio::DEFAULT_FRAMES_PER_BUFFER,
     };
 
     let r = std::thread::Builder::new()
@@ -111,11 +126,13 @@ pub fn start_btn_worker(
     }))
 }
 
+/// No battery gauge on this board.
+pub fn battery_percent() -> Option<u8> {
+    None
+}
+
 pub fn set_volum(vol: u8) {
-    let v = match vol {
-        0..5 => vol as i32 * 50 / 5 + 20,
-        _ => 70,
-    };
+    let v = HARDWARE_VOLUME_CURVE[(vol as usize).min(HARDWARE_VOLUME_CURVE.len() - 1)];
 
     unsafe {
         esp_idf_svc::sys::hal_driver::es8311_set_voice_volume(v);
@@ -155,20 +172,19 @@ pub mod ui {
     use embedded_graphics::{
         framebuffer::{buffer_size, Framebuffer},
         image::GetPixel,
-        pixelcolor::raw::{LittleEndian, RawU16},
         prelude::*,
-        primitives::{PrimitiveStyleBuilder, Rectangle},
+        primitives::Rectangle,
         text::{Alignment, Text},
         Drawable,
     };
     use u8g2_fonts::U8g2TextStyle;
 
-    use crate::ui::{ColorFormat, DisplayTargetDrive, DynamicImage, ImageArea};
+    use crate::ui::{ColorFormat, ColorRaw, DisplayTargetDrive, DynamicImage, ImageArea};
 
     type FrameBufferChunk8x12 = Framebuffer<
         ColorFormat,
-        RawU16,
-        LittleEndian,
+        ColorRaw,
+        ColorByteOrder,
         8,
         12,
         { buffer_size::<ColorFormat>(8, 12) },
@@ -352,6 +368,12 @@ pub mod ui {
             self.background_buffers.clone_from(&self.buffers);
             Ok(())
         }
+
+        fn force_flush(&mut self) -> anyhow::Result<()> {
+            let all = 0..self.buffers.len();
+            self.resume_indexs = all.filter(|&i| self.draw_mask[i] == 0).collect();
+            self.flush()
+        }
     }
 
     impl BoxFrameBuffer {
@@ -368,6 +390,13 @@ pub mod ui {
         state_text: String,
         state_text_updated: bool,
         state_chunks: Vec<usize>,
+        interrupt_indicator: bool,
+        /// `battery_bucket` of the last level set, `None` hides the indicator.
+        battery_bucket: Option<u8>,
+        /// `wifi_bars` of the last RSSI set, 0 while disconnected. `None`
+        /// until the first `set_wifi`, which keeps the indicator hidden.
+        wifi_bars: Option<u8>,
+        status_icon: Option<crate::ui::StatusIcon>,
 
         asr_text: String,
         asr_text_updated: bool,
@@ -378,16 +407,23 @@ pub mod ui {
         content_chunks: Vec<usize>,
 
         avatar: DynamicImage<N>,
+        avatar_area: Rectangle,
+        /// Image shown in place of the avatar, see `set_emotion`.
+        emotion: Option<(&'static [u8], DynamicImage<1>)>,
         avatar_updated: bool,
         avatar_chunks: Vec<usize>,
     }
 
     impl<const N: usize> ChatUI<N> {
-        pub fn new(avatar: DynamicImage<N>) -> Self {
+        pub fn new(avatar: DynamicImage<N>, avatar_area: Rectangle) -> Self {
             Self {
                 state_text: String::new(),
                 state_text_updated: false,
                 state_chunks: Vec::new(),
+                interrupt_indicator: false,
+                battery_bucket: None,
+                wifi_bars: None,
+                status_icon: None,
 
                 asr_text: String::new(),
                 asr_text_updated: false,
@@ -398,6 +434,8 @@ pub mod ui {
                 content_chunks: Vec::new(),
 
                 avatar: avatar,
+                avatar_area,
+                emotion: None,
                 avatar_updated: true,
                 avatar_chunks: Vec::new(),
             }
@@ -410,6 +448,47 @@ pub mod ui {
             }
         }
 
+        pub fn set_interrupt_indicator(&mut self, on: bool) {
+            if self.interrupt_indicator != on {
+                self.interrupt_indicator = on;
+                self.state_text_updated = true;
+            }
+        }
+
+        /// Sets the battery level shown in the state area. Returns true only when
+        /// the displayed level changed and needs a render.
+        pub fn set_battery(&mut self, percent: Option<u8>) -> bool {
+            let bucket = percent.map(crate::ui::battery_bucket);
+            if self.battery_bucket == bucket {
+                return false;
+            }
+            self.battery_bucket = bucket;
+            self.state_text_updated = true;
+            true
+        }
+
+        /// Shows `icon` next to the state text, `None` hides it.
+        pub fn set_status_icon(&mut self, icon: Option<crate::ui::StatusIcon>) {
+            if self.status_icon != icon {
+                self.status_icon = icon;
+                self.state_text_updated = true;
+            }
+        }
+
+        /// Sets the WiFi signal shown in the state area from the AP's RSSI,
+        /// `None` meaning not connected, which shows zero bars in red rather
+        /// than hiding the indicator. Returns true only when the number of
+        /// bars changed and needs a render.
+        pub fn set_wifi(&mut self, rssi: Option<i8>) -> bool {
+            let bars = Some(crate::ui::wifi_bars(rssi));
+            if self.wifi_bars == bars {
+                return false;
+            }
+            self.wifi_bars = bars;
+            self.state_text_updated = true;
+            true
+        }
+
         pub fn set_asr(&mut self, text: String) {
             if self.asr_text != text {
                 self.asr_text = text;
@@ -426,6 +505,28 @@ pub mod ui {
             }
         }
 
+        /// Shows the PNG `emotion` in place of the avatar, or the avatar again
+        /// for `None`. Returns true when the header changed and needs a render.
+        /// Nothing changes on layouts without an avatar.
+        pub fn set_emotion(&mut self, emotion: Option<&'static [u8]>) -> bool {
+            if self.avatar.image_data.is_empty() {
+                return false;
+            }
+            let current = self.emotion.as_ref().map(|(png, _)| png.as_ptr());
+            if current == emotion.map(|png| png.as_ptr()) {
+                return false;
+            }
+
+            self.emotion = emotion.and_then(|png| {
+                DynamicImage::new_from_png(self.avatar_area, png)
+                    .map_err(|e| log::warn!("Failed to decode emotion image: {:?}", e))
+                    .ok()
+                    .map(|img| (png, img))
+            });
+            self.avatar_updated = true;
+            true
+        }
+
         pub fn set_avatar_index(&mut self, index: usize) -> bool {
             if !self.avatar.image_data.is_empty() {
                 self.avatar.set_index(index);
@@ -436,6 +537,25 @@ pub mod ui {
             }
         }
 
+        /// Content is drawn through the diff chunks and is not scrolled on this
+        /// board; long text is clipped.
+        pub fn scroll_content(&mut self) -> bool {
+            false
+        }
+
+        pub fn until_scroll(&self) -> Option<std::time::Duration> {
+            None
+        }
+
+        /// Marks every area as changed, so the next render redraws all of them
+        /// after something else drew over the screen.
+        pub fn invalidate(&mut self) {
+            self.state_text_updated = true;
+            self.asr_text_updated = true;
+            self.content_updated = true;
+            self.avatar_updated = true;
+        }
+
         pub fn clear_update_flags(&mut self) {
             self.state_text_updated = false;
             self.asr_text_updated = false;
@@ -458,13 +578,22 @@ pub mod ui {
                 Text::with_alignment(
                     &self.state_text,
                     state_area_box.center(),
-                    U8g2TextStyle::new(
-                        u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312a,
-                        ColorFormat::CSS_LIGHT_CYAN,
-                    ),
+                    crate::ui::state_text_style(),
                     Alignment::Center,
                 )
                 .draw(target)?;
+                if self.interrupt_indicator {
+                    crate::ui::draw_interrupt_indicator(target, state_area_box)?;
+                }
+                if let Some(icon) = self.status_icon {
+                    crate::ui::draw_status_icon(target, state_area_box, icon)?;
+                }
+                if let Some(bars) = self.wifi_bars {
+                    crate::ui::draw_wifi_indicator(target, state_area_box, bars)?;
+                }
+                if let Some(bucket) = self.battery_bucket {
+                    crate::ui::draw_battery_indicator(target, state_area_box, bucket)?;
+                }
                 target.resume_chunks(&self.state_chunks);
                 self.state_chunks = target.diff_indexs.clone();
                 start_i = self.state_chunks.len();
@@ -489,13 +618,7 @@ pub mod ui {
                 embedded_text::TextBox::with_textbox_style(
                     &combined_text,
                     content_area_box,
-                    crate::ui::MyTextStyle(
-                        U8g2TextStyle::new(
-                            u8g2_fonts::fonts::u8g2_font_wqy16_t_gb2312,
-                            ColorFormat::CSS_WHEAT,
-                        ),
-                        3,
-                    ),
+                    crate::ui::content_text_style(),
                     textbox_style,
                 )
                 .draw(target)?;
@@ -505,7 +628,10 @@ pub mod ui {
             }
 
             if self.avatar_updated {
-                self.avatar.render(target)?;
+                match &self.emotion {
+                    Some((_, img)) => img.render(target)?,
+                    None => self.avatar.render(target)?,
+                }
                 target.resume_chunks(&self.avatar_chunks);
                 self.avatar_chunks = target.diff_indexs[start_i..].to_vec();
             }
@@ -516,61 +642,94 @@ pub mod ui {
         }
 
         pub fn layout_without_avatar(bounding_box: Rectangle) -> (Rectangle, Rectangle) {
+            let bar = crate::ui::STATE_BAR_HEIGHT;
             let state_area_box = Rectangle::new(
                 bounding_box.top_left,
-                Size::new(bounding_box.size.width, 32),
+                Size::new(bounding_box.size.width, bar),
             );
 
             let content_area_box = Rectangle::new(
-                bounding_box.top_left + Point::new(0, 32),
-                Size::new(bounding_box.size.width, bounding_box.size.height - 32),
+                bounding_box.top_left + Point::new(0, bar as i32),
+                Size::new(bounding_box.size.width, bounding_box.size.height - bar),
             );
 
             (state_area_box, content_area_box)
         }
 
+        /// State area beside the avatar, as tall as it, and content across the
+        /// full width below, so the avatar's chunks never overlap the text's and
+        /// no part of the screen is left unused.
         pub fn layout(bounding_box: Rectangle) -> (Rectangle, Rectangle) {
+            let avatar = crate::ui::avatar_size(bounding_box.size);
+
             let state_area_box = Rectangle::new(
-                bounding_box.top_left + Point::new(96, 0),
-                Size::new(bounding_box.size.width - 96, 32),
+                bounding_box.top_left + Point::new(avatar as i32, 0),
+                Size::new(bounding_box.size.width - avatar, avatar),
             );
 
             let content_area_box = Rectangle::new(
-                bounding_box.top_left + Point::new(0, 32),
-                Size::new(bounding_box.size.width, bounding_box.size.height - 32),
+                bounding_box.top_left + Point::new(0, avatar as i32),
+                Size::new(bounding_box.size.width, bounding_box.size.height - avatar),
             );
 
             (state_area_box, content_area_box)
         }
     }
 
+    #[test]
+    fn test_set_wifi() {
+        let mut ui = ChatUI::<1>::new(DynamicImage::empty(), Rectangle::zero());
+        assert_eq!(ui.wifi_bars, None);
+
+        assert!(ui.set_wifi(Some(-60)));
+        assert_eq!(ui.wifi_bars, Some(3));
+        assert!(!ui.set_wifi(Some(-62)));
+
+        // disconnected still shows the indicator, with no bars
+        assert!(ui.set_wifi(None));
+        assert_eq!(ui.wifi_bars, Some(0));
+        assert!(!ui.set_wifi(None));
+    }
+
+    #[test]
+    fn test_chat_layout() {
+        let screen = Rectangle::new(
+            Point::zero(),
+            Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32),
+        );
+
+        let (state, content) = ChatUI::<1>::layout(screen);
+        assert_eq!(state, Rectangle::new(Point::new(96, 0), Size::new(224, 96)));
+        assert_eq!(
+            content,
+            Rectangle::new(Point::new(0, 96), Size::new(320, 144))
+        );
+
+        let (state, content) = ChatUI::<1>::layout_without_avatar(screen);
+        assert_eq!(state, Rectangle::new(Point::new(0, 0), Size::new(320, 32)));
+        assert_eq!(
+            content,
+            Rectangle::new(Point::new(0, 32), Size::new(320, 208))
+        );
+    }
+
     pub fn new_chat_ui<const N: usize>(
         target: &mut BoxFrameBuffer,
         avatar_gif: &[u8],
     ) -> anyhow::Result<ChatUI<N>> {
         let bounding_box = target.bounding_box();
-        let avatar_area_box = Rectangle::new(bounding_box.top_left, Size::new(96, 96));
+        let avatar = crate::ui::avatar_size(bounding_box.size);
+        let avatar_area_box = Rectangle::new(bounding_box.top_left, Size::new(avatar, avatar));
 
         let (state_area_box, content_area_box) = if avatar_gif.is_empty() {
             ChatUI::<N>::layout_without_avatar(bounding_box)
         } else {
             ChatUI::<N>::layout(bounding_box)
         };
-        let state_style = PrimitiveStyleBuilder::new()
-            .stroke_color(ColorFormat::CSS_DARK_BLUE)
-            .stroke_width(1)
-            .fill_color(ColorFormat::CSS_DARK_BLUE)
-            .build();
-
-        let pixels = crate::ui::get_background_pixels(target, state_area_box, state_style, 0.5);
+        let pixels = crate::ui::state_background_pixels(target, state_area_box);
         target.draw_iter(pixels)?;
 
-        let content_style = PrimitiveStyleBuilder::new()
-            .stroke_color(ColorFormat::CSS_BLACK)
-            .stroke_width(5)
-            .fill_color(ColorFormat::CSS_BLACK)
-            .build();
-        let pixels = crate::ui::get_background_pixels(target, content_area_box, content_style, 0.5);
+        let pixels = crate::ui::content_background_pixels(target, content_area_box);
         target.draw_iter(pixels)?;
 
         target.background_buffers.clone_from(&target.buffers);
@@ -583,7 +742,7 @@ pub mod ui {
             DynamicImage::new_from_gif(avatar_area_box, avatar_gif).unwrap_or(DynamicImage::empty())
         };
 
-        Ok(ChatUI::new(avatar))
+        Ok(ChatUI::new(avatar, avatar_area_box))
     }
 
     pub struct ConfiguresUI {
@@ -649,14 +808,46 @@ pub mod ui {
     }
 }
 
+/// The box has no second button on a plain GPIO to combine with K0, so safe
+/// mode can't be entered on it.
+#[macro_export]
+macro_rules! safe_mode_requested {
+    ($peripherals:ident, $k0:expr) => {{
+        false
+    }};
+}
+
+/// Same for the self-test.
+#[macro_export]
+macro_rules! self_test_requested {
+    ($peripherals:ident, $k0:expr) => {{
+        false
+    }};
+}
+
+/// And for the echo test.
+#[macro_export]
+macro_rules! echo_test_requested {
+    ($peripherals:ident) => {{
+        false
+    }};
+}
+
+/// The panel is brought up by the vendor driver, which doesn't report failure.
+pub fn display_ready() -> bool {
+    true
+}
+
 #[macro_export]
 macro_rules! start_hal {
-    ($peripherals:ident, $evt_tx:ident) => {{
-        crate::boards::atom_box::audio_init(
-            $peripherals.i2c0,
-            $peripherals.pins.gpio48,
-            $peripherals.pins.gpio45,
-        );
+    ($peripherals:ident, $evt_tx:ident, $safe_mode:expr) => {{
+        if !$safe_mode {
+            crate::boards::atom_box::audio_init(
+                $peripherals.i2c0,
+                $peripherals.pins.gpio48,
+                $peripherals.pins.gpio45,
+            );
+        }
         crate::boards::atom_box::lcd_init(
             $peripherals.pins.gpio1,
             $peripherals.pins.gpio2,
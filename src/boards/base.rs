@@ -7,8 +7,17 @@ use esp_idf_svc::{
     sys::EspError,
 };
 
+/// Pixel format of the panel, see `ui::ColorFormat`. `init_lcd` sets the panel
+/// up for its depth; Rgb888 needs `ColorByteOrder` to be `BigEndian` so bytes
+/// go out in RGB order.
+pub type ColorFormat = embedded_graphics::pixelcolor::Rgb565;
+/// Byte order of `ColorFormat` in the framebuffer.
+pub type ColorByteOrder = embedded_graphics::pixelcolor::raw::LittleEndian;
+
 const AUDIO_STACK_SIZE: usize = 15 * 1024;
 pub const AFE_AEC_OFFSET: usize = 256;
+/// Software gain for volume levels 0..=5, see `audio::scale_volume`.
+pub const VOLUME_CURVE: crate::audio::VolumeCurve = [0, 16, 32, 64, 128, 256];
 
 pub fn afe_config(afe_config: &mut esp_idf_svc::sys::esp_sr::afe_config_t) {
     afe_config.agc_init = true;
@@ -41,6 +50,9 @@ pub fn start_audio_workers(
         in_clk: sck.into(),
         din: din.into(),
         in_mclk: None,
+        dma_buffer_count: crate::audio::DEFAULT_DMA_BUFFER_COUNT,
+        frames_per_buffer: crate::audio::DEFAULT_FRAMES_PER_BUFFER,
+        stereo_out: false,
     };
 
     let r = std::thread::Builder::new()
@@ -112,10 +124,16 @@ pub fn init_spi(_spi: SPI3, mosi: Gpio47, clk: Gpio21) -> Result<(), EspError> {
     buscfg.sclk_io_num = clk.pin();
     buscfg.__bindgen_anon_3.quadwp_io_num = GPIO_NUM_NC;
     buscfg.__bindgen_anon_4.quadhd_io_num = GPIO_NUM_NC;
-    buscfg.max_transfer_sz = (DISPLAY_WIDTH * DISPLAY_HEIGHT * std::mem::size_of::<u16>()) as i32;
+    buscfg.max_transfer_sz = (DISPLAY_WIDTH * DISPLAY_HEIGHT * crate::ui::COLOR_WIDTH) as i32;
     esp!(unsafe { spi_bus_initialize(SPI3::device(), &buscfg, spi_common_dma_t_SPI_DMA_CH_AUTO,) })
 }
 
+const LCD_INIT_ATTEMPTS: u32 = 3;
+
+/// Brings up the ST7789 panel, re-running the reset/init sequence up to
+/// `LCD_INIT_ATTEMPTS` times. On failure the panel handle stays null and
+/// `flush_display` drops every frame, so the device can keep running
+/// headless: audio, buttons and the server connection work, there is no UI.
 pub fn init_lcd(cs: Gpio41, dc: Gpio40, rst: Gpio45) -> Result<(), EspError> {
     use esp_idf_svc::sys::*;
 
@@ -141,38 +159,78 @@ pub fn init_lcd(cs: Gpio41, dc: Gpio40, rst: Gpio45) -> Result<(), EspError> {
     panel_config.reset_gpio_num = rst.pin();
     panel_config.data_endian = lcd_rgb_data_endian_t_LCD_RGB_DATA_ENDIAN_LITTLE;
     panel_config.__bindgen_anon_1.rgb_ele_order = lcd_rgb_element_order_t_LCD_RGB_ELEMENT_ORDER_RGB;
-    panel_config.bits_per_pixel = 16;
+    // the ST7789 takes 3 bytes a pixel in its 18-bit mode
+    panel_config.bits_per_pixel = if crate::ui::COLOR_WIDTH == 2 { 16 } else { 18 };
 
     esp!(unsafe { esp_lcd_new_panel_st7789(panel_io, &panel_config, &mut panel) })?;
 
-    unsafe {
-        ESP_LCD_PANEL_HANDLE = panel;
-    }
-
-    const DISPLAY_MIRROR_X: bool = false;
-    const DISPLAY_MIRROR_Y: bool = false;
-    const DISPLAY_SWAP_XY: bool = false;
+    // built-in orientation, overridden by `DeviceConfig::display_orientation`
+    let orientation = crate::boards::display_orientation(crate::boards::DisplayOrientation {
+        mirror_x: false,
+        mirror_y: false,
+        swap_xy: false,
+    });
+    ::log::info!("LCD orientation: {:?}", orientation);
     const DISPLAY_INVERT_COLOR: bool = true;
 
-    ::log::info!("Reset LCD panel");
+    let mut attempt = 1;
+    loop {
+        ::log::info!("Reset LCD panel");
+        let ret = (|| unsafe {
+            esp!(esp_lcd_panel_reset(panel))?;
+            esp!(esp_lcd_panel_init(panel))?;
+            esp!(esp_lcd_panel_invert_color(panel, DISPLAY_INVERT_COLOR))?;
+            esp!(esp_lcd_panel_swap_xy(panel, orientation.swap_xy))?;
+            esp!(esp_lcd_panel_mirror(
+                panel,
+                orientation.mirror_x,
+                orientation.mirror_y
+            ))?;
+            esp!(esp_lcd_panel_disp_on_off(panel, true)) /* 启动屏幕 */
+        })();
+        match ret {
+            Ok(()) => break,
+            Err(e) if attempt < LCD_INIT_ATTEMPTS => {
+                ::log::warn!(
+                    "LCD panel init failed (attempt {attempt}/{LCD_INIT_ATTEMPTS}): {e:?}, retrying"
+                );
+                attempt += 1;
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            Err(e) => {
+                ::log::error!("LCD panel init failed after {LCD_INIT_ATTEMPTS} attempts: {e:?}");
+                unsafe {
+                    esp_lcd_panel_del(panel);
+                    esp_lcd_panel_io_del(panel_io);
+                }
+                return Err(e);
+            }
+        }
+    }
+
     unsafe {
-        esp!(esp_lcd_panel_reset(panel))?;
-        esp!(esp_lcd_panel_init(panel))?;
-        esp!(esp_lcd_panel_invert_color(panel, DISPLAY_INVERT_COLOR))?;
-        esp!(esp_lcd_panel_swap_xy(panel, DISPLAY_SWAP_XY))?;
-        esp!(esp_lcd_panel_mirror(
-            panel,
-            DISPLAY_MIRROR_X,
-            DISPLAY_MIRROR_Y
-        ))?;
-        esp!(esp_lcd_panel_disp_on_off(panel, true))?; /* 启动屏幕 */
+        ESP_LCD_PANEL_HANDLE = panel;
     }
 
     Ok(())
 }
 
+/// No battery gauge on this board.
+pub fn battery_percent() -> Option<u8> {
+    None
+}
+
+/// Whether `init_lcd` brought the panel up, see `flush_display`.
+pub fn display_ready() -> bool {
+    unsafe { !ESP_LCD_PANEL_HANDLE.is_null() }
+}
+
 pub fn flush_display(color_data: &[u8], x_start: i32, y_start: i32, x_end: i32, y_end: i32) -> i32 {
     unsafe {
+        if ESP_LCD_PANEL_HANDLE.is_null() {
+            // Headless, see `init_lcd`.
+            return 0;
+        }
         let e = esp_idf_svc::sys::esp_lcd_panel_draw_bitmap(
             ESP_LCD_PANEL_HANDLE,
             x_start,
@@ -188,19 +246,48 @@ pub fn flush_display(color_data: &[u8], x_start: i32, y_start: i32, x_end: i32,
     }
 }
 
+/// Safe-mode boot combo: K0 and VOL+ held together at power-on.
+#[macro_export]
+macro_rules! safe_mode_requested {
+    ($peripherals:ident, $k0:expr) => {{
+        $k0.is_low() && crate::boards::button_held(&mut $peripherals.pins.gpio38)
+    }};
+}
+
+/// K0 and VOL_DOWN held at boot run `self_test`.
+#[macro_export]
+macro_rules! self_test_requested {
+    ($peripherals:ident, $k0:expr) => {{
+        $k0.is_low() && crate::boards::button_held(&mut $peripherals.pins.gpio39)
+    }};
+}
+
+/// VOL+ and VOL_DOWN held at boot (without K0) run the echo test.
+#[macro_export]
+macro_rules! echo_test_requested {
+    ($peripherals:ident) => {{
+        crate::boards::button_held(&mut $peripherals.pins.gpio38)
+            && crate::boards::button_held(&mut $peripherals.pins.gpio39)
+    }};
+}
+
 #[macro_export]
 macro_rules! start_hal {
-    ($peripherals:ident, $evt_tx:ident) => {{
-        crate::boards::base::init_spi(
+    ($peripherals:ident, $evt_tx:ident, $safe_mode:expr) => {{
+        if let Err(e) = crate::boards::base::init_spi(
             $peripherals.spi3,
             $peripherals.pins.gpio47,
             $peripherals.pins.gpio21,
-        )?;
-        crate::boards::base::init_lcd(
-            $peripherals.pins.gpio41,
-            $peripherals.pins.gpio40,
-            $peripherals.pins.gpio45,
-        )?;
+        )
+        .and_then(|_| {
+            crate::boards::base::init_lcd(
+                $peripherals.pins.gpio41,
+                $peripherals.pins.gpio40,
+                $peripherals.pins.gpio45,
+            )
+        }) {
+            log::error!("Display init failed, running headless (no UI): {:?}", e);
+        }
         #[cfg(feature = "i2c")]
         {
             let config = esp_idf_svc::hal::i2c::config::Config::default()
@@ -213,7 +300,9 @@ macro_rules! start_hal {
                 i2c_tasks.push((crate::boards::init_mfrc522, crate::boards::mfrc522_loop));
             }
 
-            if let Err(e) = crate::boards::init_i2c(
+            if $safe_mode {
+                log::warn!("Safe mode, skipping I2C tasks");
+            } else if let Err(e) = crate::boards::init_i2c(
                 config,
                 $peripherals.i2c0,
                 $peripherals.pins.gpio14.into(),
@@ -227,11 +316,11 @@ macro_rules! start_hal {
             }
         }
     }
-    let _backlight = {
-        let mut backlight = crate::boards::backlight_init($peripherals.pins.gpio42.into()).unwrap();
-        crate::boards::set_backlight(&mut backlight, 70).unwrap();
-        backlight
-    };};
+    if let Err(e) = crate::boards::backlight_init($peripherals.pins.gpio42.into())
+        .and_then(crate::boards::start_backlight)
+    {
+        log::error!("Failed to start backlight: {:?}", e);
+    }};
 }
 
 #[macro_export]
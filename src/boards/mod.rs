@@ -18,6 +18,61 @@ pub mod cube2;
 #[cfg(feature = "cube2")]
 pub use cube2::*;
 
+/// Panel mirroring and X/Y swap applied by `init_lcd`, for a board mounted
+/// upside down or sideways.
+///
+/// The framebuffer is `DISPLAY_WIDTH` x `DISPLAY_HEIGHT` at compile time, so
+/// `swap_xy` only keeps it valid on square panels (the 240x240 boards).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DisplayOrientation {
+    pub mirror_x: bool,
+    pub mirror_y: bool,
+    pub swap_xy: bool,
+}
+
+impl DisplayOrientation {
+    /// Packs the flags into one byte: bit 0 mirror X, bit 1 mirror Y, bit 2 swap X/Y.
+    pub fn to_bits(self) -> u8 {
+        self.mirror_x as u8 | (self.mirror_y as u8) << 1 | (self.swap_xy as u8) << 2
+    }
+
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits & !0b111 != 0 {
+            return None;
+        }
+        Some(Self {
+            mirror_x: bits & 0b001 != 0,
+            mirror_y: bits & 0b010 != 0,
+            swap_xy: bits & 0b100 != 0,
+        })
+    }
+}
+
+#[test]
+fn test_display_orientation_bits() {
+    for bits in 0..8 {
+        assert_eq!(DisplayOrientation::from_bits(bits).unwrap().to_bits(), bits);
+    }
+    let o = DisplayOrientation::from_bits(0b110).unwrap();
+    assert!(!o.mirror_x && o.mirror_y && o.swap_xy);
+    assert_eq!(DisplayOrientation::from_bits(0b1000), None);
+    assert_eq!(DisplayOrientation::from_bits(0xFF), None);
+}
+
+/// Orientation from `DeviceConfig`, set before `start_hal!`. `None` keeps the
+/// board's built-in orientation.
+pub static DISPLAY_ORIENTATION: std::sync::Mutex<Option<DisplayOrientation>> =
+    std::sync::Mutex::new(None);
+
+/// The configured orientation, or `default` if none is set.
+pub fn display_orientation(default: DisplayOrientation) -> DisplayOrientation {
+    DISPLAY_ORIENTATION
+        .lock()
+        .ok()
+        .and_then(|o| *o)
+        .unwrap_or(default)
+}
+
 #[cfg(feature = "i2c")]
 pub type I2CInitFn = fn(&mut esp_idf_svc::hal::i2c::I2cDriver<'static>) -> anyhow::Result<()>;
 #[cfg(feature = "i2c")]
@@ -95,9 +150,19 @@ fn decode_ndef_in_mifare_ultralight<D: crate::peripheral::mfrc522::MfrcDriver>(
         ndef_buffer.extend_from_slice(&buff[..16]);
     }
 
-    let n = ndef_buffer[22] as usize;
+    // the data area starts at page 4
+    let message = crate::peripheral::mfrc522::ndef_uri::find_ndef_message(&ndef_buffer[16..])
+        .ok_or_else(|| {
+            log::error!("No NDEF message found on tag");
+            crate::peripheral::mfrc522::consts::PCDErrorCode::Error
+        })?;
 
-    let messages = ndef::Message::try_from(&ndef_buffer[23..23 + n]).map_err(|e| {
+    // URI records as written by `encode_ndef_uri_to_mifare_ultralight`
+    if let Some(uri) = crate::peripheral::mfrc522::ndef_uri::decode_uri_record(message) {
+        return Ok(vec![uri]);
+    }
+
+    let messages = ndef::Message::try_from(message).map_err(|e| {
         log::error!("Error parsing NDEF message: {:?}", e);
         crate::peripheral::mfrc522::consts::PCDErrorCode::Error
     })?;
@@ -112,6 +177,272 @@ fn decode_ndef_in_mifare_ultralight<D: crate::peripheral::mfrc522::MfrcDriver>(
     Ok(r)
 }
 
+/// Writes `url` as an NDEF URI record from page 4 of a MIFARE Ultralight tag.
+///
+/// The data area size comes from the capability container on page 3, capped
+/// at the 48 bytes `decode_ndef_in_mifare_ultralight` reads back. Longer URLs
+/// fail with `NoRoom` before anything is written.
+#[cfg(feature = "mfrc522")]
+fn encode_ndef_uri_to_mifare_ultralight<D: crate::peripheral::mfrc522::MfrcDriver>(
+    mfrc522: &mut crate::peripheral::mfrc522::MFRC522<D>,
+    url: &str,
+    timeout: esp_idf_svc::hal::delay::TickType_t,
+) -> Result<(), crate::peripheral::mfrc522::consts::PCDErrorCode> {
+    use crate::peripheral::mfrc522::consts::PCDErrorCode;
+
+    let tlv =
+        crate::peripheral::mfrc522::ndef_uri::encode_uri_tlv(url).ok_or(PCDErrorCode::NoRoom)?;
+
+    let mut buff = [0; 18];
+    let mut bytes_count = 18;
+    mfrc522.mifare_read(3, &mut buff, &mut bytes_count, timeout)?;
+    let capacity = (buff[2] as usize * 8).min(48);
+    if capacity == 0 {
+        log::error!("Tag has no NDEF capability container");
+        return Err(PCDErrorCode::Invalid);
+    }
+    if tlv.len() > capacity {
+        log::error!(
+            "NDEF message of {} bytes doesn't fit in {}",
+            tlv.len(),
+            capacity
+        );
+        return Err(PCDErrorCode::NoRoom);
+    }
+
+    for (i, chunk) in tlv.chunks(4).enumerate() {
+        let mut page = [0; 4];
+        page[..chunk.len()].copy_from_slice(chunk);
+        mfrc522.mifare_ultralight_write(4 + i as u8, &mut page, 4, timeout)?;
+    }
+
+    Ok(())
+}
+
+/// Transport key A of a blank MIFARE Classic tag.
+#[cfg(feature = "mfrc522")]
+const MIFARE_CLASSIC_DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+/// First MIFARE Classic block `mfrc522_loop` reads text from, see
+/// `read_mifare_classic_text`.
+#[cfg(feature = "mfrc522")]
+pub static NFC_CLASSIC_BLOCK: std::sync::atomic::AtomicU8 = std::sync::atomic::AtomicU8::new(4);
+
+/// The sector trailer (keys and access bits) of the sector holding `block`.
+/// Sectors have 4 blocks, except the 16-block sectors above block 127 on 4K
+/// tags.
+#[cfg(feature = "mfrc522")]
+fn mifare_classic_trailer(block: u8) -> u8 {
+    if block < 128 {
+        block | 0x03
+    } else {
+        block | 0x0F
+    }
+}
+
+/// Text stored as plain bytes in a block, up to the first NUL. Blank blocks
+/// and anything that isn't UTF-8 give `None`.
+#[cfg(feature = "mfrc522")]
+fn mifare_classic_text(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let text = std::str::from_utf8(&data[..end]).ok()?.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(feature = "mfrc522")]
+#[test]
+fn test_mifare_classic_blocks() {
+    assert_eq!(mifare_classic_trailer(0), 3);
+    assert_eq!(mifare_classic_trailer(4), 7);
+    assert_eq!(mifare_classic_trailer(62), 63);
+    assert_eq!(mifare_classic_trailer(128), 143);
+    assert_eq!(mifare_classic_trailer(250), 255);
+
+    let mut data = [0u8; 32];
+    data[..16].copy_from_slice(b"ws://10.0.0.2/ws");
+    assert_eq!(
+        mifare_classic_text(&data).as_deref(),
+        Some("ws://10.0.0.2/ws")
+    );
+    assert_eq!(mifare_classic_text(&[0; 16]), None);
+    assert_eq!(mifare_classic_text(&[0xFF; 16]), None);
+}
+
+/// Reads text from `NFC_CLASSIC_BLOCK` of a MIFARE Classic tag, continuing
+/// into the following data blocks of the same sector so a URL can span up to
+/// 48 bytes. Authenticates with the default key A; crypto is always stopped
+/// afterwards, even if authentication failed.
+#[cfg(feature = "mfrc522")]
+fn read_mifare_classic_text<D: crate::peripheral::mfrc522::MfrcDriver>(
+    mfrc522: &mut crate::peripheral::mfrc522::MFRC522<D>,
+    card: &crate::peripheral::mfrc522::consts::Uid,
+    timeout: esp_idf_svc::hal::delay::TickType_t,
+) -> Result<Option<String>, crate::peripheral::mfrc522::consts::PCDErrorCode> {
+    use crate::peripheral::mfrc522::consts::{PCDErrorCode, PICCCommand};
+
+    let block = NFC_CLASSIC_BLOCK.load(std::sync::atomic::Ordering::Relaxed);
+    let trailer = mifare_classic_trailer(block);
+    if block == 0 || block == trailer {
+        log::error!("MIFARE Classic block {} holds no data", block);
+        return Err(PCDErrorCode::Invalid);
+    }
+
+    let r = (|| -> Result<Option<String>, PCDErrorCode> {
+        mfrc522.pcd_authenticate(
+            PICCCommand::PICC_CMD_MF_AUTH_KEY_A,
+            block,
+            &MIFARE_CLASSIC_DEFAULT_KEY,
+            card,
+            timeout,
+        )?;
+
+        let mut data = vec![];
+        let mut buff = [0; 18];
+        for b in block..trailer {
+            let mut bytes_count = 18;
+            mfrc522.mifare_read(b, &mut buff, &mut bytes_count, timeout)?;
+            data.extend_from_slice(&buff[..16]);
+            if buff[..16].contains(&0) {
+                break;
+            }
+        }
+        Ok(mifare_classic_text(&data))
+    })();
+
+    _ = mfrc522.pcd_stop_crypto1(timeout);
+    r
+}
+
+/// URL to burn to the next Ultralight tag presented, see `request_ndef_uri_write`.
+#[cfg(feature = "mfrc522")]
+static PENDING_NDEF_URI: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+/// Has `mfrc522_loop` write `url` to the next MIFARE Ultralight tag instead of
+/// reading it. Fails if the URL can't be encoded in a single short record.
+#[cfg(feature = "mfrc522")]
+pub fn request_ndef_uri_write(url: String) -> anyhow::Result<()> {
+    if crate::peripheral::mfrc522::ndef_uri::encode_uri_tlv(&url).is_none() {
+        anyhow::bail!("URL too long for an NDEF tag");
+    }
+    *PENDING_NDEF_URI.lock().unwrap() = Some(url);
+    Ok(())
+}
+
+/// A card that is read again within this many milliseconds of last being seen
+/// is ignored, so a tag left on the reader doesn't send `ServerUrl` every loop.
+#[cfg(feature = "mfrc522")]
+pub static NFC_CARD_COOLDOWN_MS: std::sync::atomic::AtomicU32 =
+    std::sync::atomic::AtomicU32::new(5000);
+
+/// MFRC522 receiver gain level set by `init_mfrc522`, 0-7 for 18-48 dB
+/// (`RxGain` in `RFCfgReg`). The chip resets to 4 (33 dB).
+#[cfg(feature = "mfrc522")]
+pub static NFC_ANTENNA_GAIN: std::sync::atomic::AtomicU8 = std::sync::atomic::AtomicU8::new(7);
+
+/// UID and time of the last card read, see `is_repeat_card`.
+#[cfg(feature = "mfrc522")]
+static LAST_CARD: std::sync::Mutex<Option<(u128, std::time::Instant)>> =
+    std::sync::Mutex::new(None);
+
+/// Records `uid` as seen at `now` and returns whether it is the same card as
+/// last time, seen less than `cooldown` ago. A card that stays on the reader
+/// keeps refreshing its timestamp, so it only counts as new again after it has
+/// been away for `cooldown`.
+#[cfg(feature = "mfrc522")]
+fn is_repeat_card(
+    last: &mut Option<(u128, std::time::Instant)>,
+    uid: u128,
+    now: std::time::Instant,
+    cooldown: std::time::Duration,
+) -> bool {
+    let repeat = matches!(
+        last,
+        Some((last_uid, seen)) if *last_uid == uid && now.duration_since(*seen) < cooldown
+    );
+    *last = Some((uid, now));
+    repeat
+}
+
+/// Polls in a row a card has to be missing before it counts as removed, so a
+/// single missed answer doesn't end a badge-in.
+#[cfg(feature = "mfrc522")]
+const CARD_REMOVED_POLLS: u8 = 2;
+
+/// Polls the last card has been missing for, `None` while no card is on the
+/// reader. See `card_removed`.
+#[cfg(feature = "mfrc522")]
+static CARD_MISSES: std::sync::Mutex<Option<u8>> = std::sync::Mutex::new(None);
+
+/// Updates `misses` with whether a card answered this poll and returns whether
+/// the card counts as removed as of this poll.
+#[cfg(feature = "mfrc522")]
+fn card_removed(misses: &mut Option<u8>, present: bool) -> bool {
+    match *misses {
+        _ if present => {
+            *misses = Some(0);
+            false
+        }
+        None => false,
+        Some(n) if n + 1 >= CARD_REMOVED_POLLS => {
+            *misses = None;
+            true
+        }
+        Some(n) => {
+            *misses = Some(n + 1);
+            false
+        }
+    }
+}
+
+#[cfg(feature = "mfrc522")]
+#[test]
+fn test_card_removed() {
+    let mut misses = None;
+    assert!(!card_removed(&mut misses, false));
+    assert!(!card_removed(&mut misses, true));
+    assert!(!card_removed(&mut misses, true));
+    // one missed poll isn't enough
+    assert!(!card_removed(&mut misses, false));
+    assert!(!card_removed(&mut misses, true));
+    assert!(!card_removed(&mut misses, false));
+    assert!(card_removed(&mut misses, false));
+    // reported once
+    assert!(!card_removed(&mut misses, false));
+    assert_eq!(misses, None);
+}
+
+#[cfg(feature = "mfrc522")]
+#[test]
+fn test_is_repeat_card() {
+    let cooldown = std::time::Duration::from_secs(5);
+    let t0 = std::time::Instant::now();
+    let at = |secs: u64| t0 + std::time::Duration::from_secs(secs);
+    let mut last = None;
+
+    assert!(!is_repeat_card(&mut last, 1, at(0), cooldown));
+    // left on the reader
+    assert!(is_repeat_card(&mut last, 1, at(1), cooldown));
+    assert!(is_repeat_card(&mut last, 1, at(5), cooldown));
+    // a different card is always new
+    assert!(!is_repeat_card(&mut last, 2, at(6), cooldown));
+    assert!(!is_repeat_card(&mut last, 1, at(7), cooldown));
+    // back after being away for the cooldown
+    assert!(!is_repeat_card(&mut last, 1, at(12), cooldown));
+}
+
+/// `VersionReg` of the MFRC522 as read by `init_mfrc522`, 0 until it was read.
+#[cfg(feature = "mfrc522")]
+static MFRC522_VERSION: std::sync::atomic::AtomicU8 = std::sync::atomic::AtomicU8::new(0);
+
+/// The MFRC522 chip version, once `init_mfrc522` has run and read it.
+#[cfg(feature = "mfrc522")]
+pub fn mfrc522_version() -> Option<crate::peripheral::mfrc522::consts::PCDVersion> {
+    match MFRC522_VERSION.load(std::sync::atomic::Ordering::Relaxed) {
+        0 => None,
+        v => Some(crate::peripheral::mfrc522::consts::PCDVersion::from_byte(v)),
+    }
+}
+
 #[cfg(feature = "mfrc522")]
 pub fn init_mfrc522(i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>) -> anyhow::Result<()> {
     let d = crate::peripheral::mfrc522::drivers::I2CDriver::new(i2c, 0x28);
@@ -121,6 +452,26 @@ pub fn init_mfrc522(i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>) -> anyh
         return Err(anyhow::anyhow!("Error initializing MFRC522: {:?}", e));
     }
 
+    match mfrc522.pcd_get_version(esp_idf_svc::hal::delay::TickType::new_millis(1000).0) {
+        Ok(version) => {
+            log::info!("MFRC522 version: {:?}", version);
+            MFRC522_VERSION.store(version as u8, std::sync::atomic::Ordering::Relaxed);
+        }
+        Err(e) => log::warn!("Failed to read MFRC522 version: {:?}", e),
+    }
+
+    let gain = NFC_ANTENNA_GAIN
+        .load(std::sync::atomic::Ordering::Relaxed)
+        .min(7);
+    let timeout = esp_idf_svc::hal::delay::TickType::new_millis(1000).0;
+    match mfrc522
+        .pcd_set_antenna_gain(gain << 4, timeout)
+        .and_then(|_| mfrc522.pcd_get_antenna_gain(timeout))
+    {
+        Ok(mask) => log::info!("MFRC522 antenna gain: {} (wanted {})", mask >> 4, gain),
+        Err(e) => log::warn!("Failed to set MFRC522 antenna gain: {:?}", e),
+    }
+
     if mfrc522.pcd_is_init(esp_idf_svc::hal::delay::TickType::new_millis(1000).0) {
         log::info!("MFRC522 initialized successfully");
         Ok(())
@@ -142,16 +493,95 @@ pub fn mfrc522_loop(
     let d = crate::peripheral::mfrc522::drivers::I2CDriver::new(i2c, 0x28);
     let mut mfrc522 = crate::peripheral::mfrc522::MFRC522::new(d);
 
-    match mfrc522.picc_is_new_card_present(timeout) {
+    // a card that was read is halted and only answers a wake-up
+    let mut card_misses = CARD_MISSES.lock().unwrap();
+    let poll = if card_misses.is_some() {
+        mfrc522.picc_is_card_present(timeout)
+    } else {
+        mfrc522.picc_is_new_card_present(timeout)
+    };
+    let present = !matches!(
+        poll,
+        Err(crate::peripheral::mfrc522::consts::PCDErrorCode::Timeout)
+    );
+    if card_removed(&mut card_misses, present) {
+        log::info!("Card removed");
+        evt_tx
+            .blocking_send(crate::app::Event::Event(crate::app::Event::CARD_REMOVED))
+            .unwrap_or_else(|e| {
+                log::error!("Failed to send CardRemoved event: {:?}", e);
+            });
+    }
+    drop(card_misses);
+
+    match poll {
         Ok(_) => {
             match mfrc522.get_card(crate::peripheral::mfrc522::consts::UidSize::Four, timeout) {
                 Ok(card) => {
-                    log::info!("Card UID: {}", card.get_number());
+                    let uid = card.get_number();
+                    let pending_write = PENDING_NDEF_URI.lock().unwrap().is_some();
+                    if let Ok(uid) = uid {
+                        let cooldown = std::time::Duration::from_millis(
+                            NFC_CARD_COOLDOWN_MS.load(std::sync::atomic::Ordering::Relaxed) as u64,
+                        );
+                        let mut last_card = LAST_CARD.lock().unwrap();
+                        if is_repeat_card(&mut last_card, uid, std::time::Instant::now(), cooldown)
+                            && !pending_write
+                        {
+                            log::debug!("Card {} still present, ignoring", uid);
+                            _ = mfrc522.picc_halta(timeout);
+                            return Ok(());
+                        }
+                    }
+
+                    match uid {
+                        Ok(uid) => log::info!("Card UID: {}", uid),
+                        Err(e) => log::warn!("Card UID unreadable: {:?}", e),
+                    }
                     let picc_type = PICCType::from_sak(card.sak);
 
                     log::info!("PICC Type: {:?}", picc_type);
 
+                    if matches!(
+                        picc_type,
+                        PICCType::PiccTypeMifare1K | PICCType::PiccTypeMifare4K
+                    ) {
+                        match read_mifare_classic_text(&mut mfrc522, &card, timeout) {
+                            Ok(Some(text)) => {
+                                log::info!("MIFARE Classic text: {}", text);
+                                evt_tx
+                                    .blocking_send(crate::app::Event::ServerUrl(text))
+                                    .unwrap_or_else(|e| {
+                                        log::error!("Failed to send ServerUrl event: {:?}", e);
+                                    });
+                            }
+                            Ok(None) => log::info!("No text on MIFARE Classic tag"),
+                            Err(e) => log::warn!("Error reading MIFARE Classic tag: {:?}", e),
+                        }
+                        _ = mfrc522.picc_halta(timeout);
+                        return Ok(());
+                    }
+
                     if !matches!(picc_type, PICCType::PiccTypeMifareUL) {
+                        // halted like the others, so removal is detected the same way
+                        _ = mfrc522.picc_halta(timeout);
+                        return Ok(());
+                    }
+
+                    let pending = PENDING_NDEF_URI.lock().unwrap().take();
+                    if let Some(url) = pending {
+                        match encode_ndef_uri_to_mifare_ultralight(&mut mfrc522, &url, timeout) {
+                            Ok(()) => log::info!("Wrote NDEF URI to tag: {}", url),
+                            Err(crate::peripheral::mfrc522::consts::PCDErrorCode::NoRoom) => {
+                                log::error!("Tag too small for {}", url);
+                            }
+                            Err(e) => {
+                                // keep it for the next tag
+                                log::error!("Error writing NDEF URI: {:?}", e);
+                                *PENDING_NDEF_URI.lock().unwrap() = Some(url);
+                            }
+                        }
+                        _ = mfrc522.picc_halta(timeout);
                         return Ok(());
                     }
 
@@ -188,6 +618,165 @@ pub fn mfrc522_loop(
     Ok(())
 }
 
+/// What the EXIO status LED shows, set from `main_work` with `set_status_led`.
+#[cfg(feature = "exio")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusLed {
+    Off = 0,
+    Pulse = 1,
+    On = 2,
+}
+
+#[cfg(feature = "exio")]
+static STATUS_LED: std::sync::atomic::AtomicU8 =
+    std::sync::atomic::AtomicU8::new(StatusLed::Off as u8);
+
+#[cfg(feature = "exio")]
+const STATUS_LED_ADDRESS: u8 = 0x24;
+#[cfg(feature = "exio")]
+const STATUS_LED_PULSE_PERIOD_MS: u128 = 2000;
+
+#[cfg(feature = "exio")]
+pub fn set_status_led(mode: StatusLed) {
+    STATUS_LED.store(mode as u8, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// PWM duty for `mode`, `elapsed_ms` since the I2C worker started. `Pulse` is a
+/// triangle wave sampled at the I2C loop rate, so it blinks when that is slow.
+#[cfg(feature = "exio")]
+fn status_led_duty(mode: StatusLed, elapsed_ms: u128) -> u8 {
+    match mode {
+        StatusLed::Off => 0,
+        StatusLed::On => 255,
+        StatusLed::Pulse => {
+            let half = STATUS_LED_PULSE_PERIOD_MS / 2;
+            let t = elapsed_ms % STATUS_LED_PULSE_PERIOD_MS;
+            let level = if t < half {
+                t
+            } else {
+                STATUS_LED_PULSE_PERIOD_MS - t
+            };
+            (level * 255 / half) as u8
+        }
+    }
+}
+
+#[cfg(feature = "exio")]
+#[test]
+fn test_status_led_duty() {
+    assert_eq!(status_led_duty(StatusLed::Off, 500), 0);
+    assert_eq!(status_led_duty(StatusLed::On, 500), 255);
+    assert_eq!(status_led_duty(StatusLed::Pulse, 0), 0);
+    assert_eq!(status_led_duty(StatusLed::Pulse, 500), 127);
+    assert_eq!(status_led_duty(StatusLed::Pulse, 1000), 255);
+    assert_eq!(status_led_duty(StatusLed::Pulse, 1500), 127);
+    assert_eq!(status_led_duty(StatusLed::Pulse, 2000), 0);
+}
+
+/// Sets up the status LED on EXIO `pin`, for boards that have one (see their
+/// `STATUS_LED_PIN`).
+#[cfg(feature = "exio")]
+pub fn status_led_init(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    pin: crate::peripheral::exio::emakefun_exio::GpioPin,
+) -> anyhow::Result<()> {
+    use crate::peripheral::exio::emakefun_exio::*;
+    set_gpio_mode(i2c, STATUS_LED_ADDRESS, pin, GpioMode::Pwm)?;
+    set_pwm_frequency(i2c, STATUS_LED_ADDRESS, 1000)?;
+    set_pwm_duty(i2c, STATUS_LED_ADDRESS, pin, 0)?;
+    Ok(())
+}
+
+#[cfg(feature = "exio")]
+pub fn status_led_loop(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    pin: crate::peripheral::exio::emakefun_exio::GpioPin,
+) -> anyhow::Result<()> {
+    use crate::peripheral::exio::emakefun_exio::*;
+
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    static LAST_DUTY: std::sync::atomic::AtomicU16 = std::sync::atomic::AtomicU16::new(u16::MAX);
+
+    let mode = match STATUS_LED.load(std::sync::atomic::Ordering::Relaxed) {
+        1 => StatusLed::Pulse,
+        2 => StatusLed::On,
+        _ => StatusLed::Off,
+    };
+    let elapsed = START.get_or_init(std::time::Instant::now).elapsed();
+    let duty = status_led_duty(mode, elapsed.as_millis());
+
+    // skip the I2C write when nothing changed
+    if LAST_DUTY.swap(duty as u16, std::sync::atomic::Ordering::Relaxed) != duty as u16 {
+        set_pwm_duty(i2c, STATUS_LED_ADDRESS, pin, duty)?;
+    }
+    Ok(())
+}
+
+/// Battery voltage in millivolts from the last `battery_loop` sample, 0 until
+/// the first one succeeds.
+#[cfg(feature = "exio")]
+static BATTERY_MV: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[cfg(feature = "exio")]
+pub fn battery_mv() -> Option<u32> {
+    match BATTERY_MV.load(std::sync::atomic::Ordering::Relaxed) {
+        0 => None,
+        mv => Some(mv),
+    }
+}
+
+/// Rough LiPo state of charge from its voltage, linear between 3.3V and 4.2V.
+#[cfg(feature = "exio")]
+pub fn battery_percent_from_mv(mv: u32) -> u8 {
+    const EMPTY_MV: u32 = 3300;
+    const FULL_MV: u32 = 4200;
+    ((mv.clamp(EMPTY_MV, FULL_MV) - EMPTY_MV) * 100 / (FULL_MV - EMPTY_MV)) as u8
+}
+
+#[cfg(feature = "exio")]
+#[test]
+fn test_battery_percent_from_mv() {
+    assert_eq!(battery_percent_from_mv(0), 0);
+    assert_eq!(battery_percent_from_mv(3300), 0);
+    assert_eq!(battery_percent_from_mv(3750), 50);
+    assert_eq!(battery_percent_from_mv(4200), 100);
+    assert_eq!(battery_percent_from_mv(5000), 100);
+}
+
+/// Samples the battery on EXIO `pin`, which sees it through a 1:`divider_ratio`
+/// voltage divider, for boards that have one (see their `BATTERY_PIN`).
+#[cfg(feature = "exio")]
+pub fn battery_loop(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    pin: crate::peripheral::exio::emakefun_exio::GpioPin,
+    divider_ratio: u32,
+) -> anyhow::Result<()> {
+    use crate::peripheral::exio::emakefun_exio::*;
+
+    let pin_mv = read_voltage_mv(i2c, 0x24, pin)?;
+    BATTERY_MV.store(
+        pin_mv as u32 * divider_ratio,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    Ok(())
+}
+
+/// Whether an active-low button on `pin` is held right now, for boot-time
+/// combos such as `safe_mode_requested!`. The pin is released afterwards.
+#[allow(unused)]
+pub fn button_held<P: esp_idf_svc::hal::gpio::InputPin + esp_idf_svc::hal::gpio::OutputPin>(
+    pin: impl esp_idf_svc::hal::peripheral::Peripheral<P = P>,
+) -> bool {
+    let held = || -> Result<bool, esp_idf_svc::sys::EspError> {
+        let mut btn = esp_idf_svc::hal::gpio::PinDriver::input(pin)?;
+        btn.set_pull(esp_idf_svc::hal::gpio::Pull::Up)?;
+        // let the pull-up settle before sampling
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        Ok(btn.is_low())
+    };
+    held().unwrap_or(false)
+}
+
 #[allow(unused)]
 pub fn backlight_init(
     bl_pin: esp_idf_svc::hal::gpio::AnyIOPin,
@@ -218,6 +807,68 @@ pub fn set_backlight<'d>(
     Ok(())
 }
 
+/// Level (0..=100) the `backlight` thread ramps towards, see `set_backlight_level`.
+static BACKLIGHT_LEVEL: std::sync::atomic::AtomicU8 = std::sync::atomic::AtomicU8::new(70);
+
+/// The backlight moves this many levels every `BACKLIGHT_RAMP_INTERVAL`, so a
+/// full-range change takes about a second.
+const BACKLIGHT_RAMP_STEP: u8 = 2;
+const BACKLIGHT_RAMP_INTERVAL: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// Sets the backlight level the `backlight` thread ramps to. Before
+/// `start_backlight` it is the level the backlight comes up at.
+pub fn set_backlight_level(level: u8) {
+    BACKLIGHT_LEVEL.store(level.min(100), std::sync::atomic::Ordering::Relaxed);
+}
+
+/// One ramp step from `current` towards `target`, without overshooting.
+fn backlight_ramp(current: u8, target: u8, step: u8) -> u8 {
+    if current < target {
+        current.saturating_add(step).min(target)
+    } else {
+        current.saturating_sub(step).max(target)
+    }
+}
+
+#[test]
+fn test_backlight_ramp() {
+    assert_eq!(backlight_ramp(70, 70, 2), 70);
+    assert_eq!(backlight_ramp(10, 70, 2), 12);
+    assert_eq!(backlight_ramp(69, 70, 2), 70);
+    assert_eq!(backlight_ramp(70, 10, 2), 68);
+    assert_eq!(backlight_ramp(11, 10, 2), 10);
+    assert_eq!(backlight_ramp(1, 0, 2), 0);
+    assert_eq!(backlight_ramp(255, 100, 2), 253);
+}
+
+/// Takes ownership of the backlight driver, keeping it alive, and starts the
+/// thread that follows `set_backlight_level`.
+#[allow(unused)]
+pub fn start_backlight(
+    mut ledc_driver: esp_idf_svc::hal::ledc::LedcDriver<'static>,
+) -> anyhow::Result<()> {
+    let mut current = BACKLIGHT_LEVEL.load(std::sync::atomic::Ordering::Relaxed);
+    set_backlight(&mut ledc_driver, current)?;
+
+    std::thread::Builder::new()
+        .name("backlight".to_string())
+        .stack_size(4 * 1024)
+        .spawn(move || loop {
+            let target = BACKLIGHT_LEVEL.load(std::sync::atomic::Ordering::Relaxed);
+            if current == target {
+                std::thread::sleep(BACKLIGHT_RAMP_INTERVAL * 5);
+                continue;
+            }
+            current = backlight_ramp(current, target, BACKLIGHT_RAMP_STEP);
+            if let Err(e) = set_backlight(&mut ledc_driver, current) {
+                log::warn!("Failed to set backlight: {:?}", e);
+            }
+            std::thread::sleep(BACKLIGHT_RAMP_INTERVAL);
+        })?;
+
+    Ok(())
+}
+
 #[cfg(not(feature = "custom_ui"))]
 pub mod ui {
     use super::*;
@@ -225,22 +876,21 @@ pub mod ui {
     use embedded_graphics::{
         framebuffer::{buffer_size, Framebuffer},
         image::GetPixel,
-        pixelcolor::raw::{LittleEndian, RawU16},
         prelude::*,
-        primitives::{PrimitiveStyleBuilder, Rectangle},
+        primitives::Rectangle,
         text::{Alignment, Text},
         Drawable,
     };
     use u8g2_fonts::U8g2TextStyle;
 
-    use crate::ui::{ColorFormat, DisplayTargetDrive, DynamicImage, ImageArea};
+    use crate::ui::{ColorFormat, ColorRaw, DisplayTargetDrive, DynamicImage, ImageArea};
 
     pub type DisplayBuffer = FrameBuffer;
 
     type Framebuffer_ = Framebuffer<
         ColorFormat,
-        RawU16,
-        LittleEndian,
+        ColorRaw,
+        ColorByteOrder,
         DISPLAY_WIDTH,
         DISPLAY_HEIGHT,
         { buffer_size::<ColorFormat>(DISPLAY_WIDTH, DISPLAY_HEIGHT) },
@@ -273,6 +923,38 @@ pub mod ui {
     pub struct FrameBuffer {
         buffers: Box<Framebuffer_>,
         background_buffers: Box<Framebuffer_>,
+        /// Rows sent by the next `flush`, set by `ChatUI::render_to_target`.
+        /// `None` sends the whole screen.
+        flush_rows: Option<std::ops::Range<i32>>,
+    }
+
+    impl FrameBuffer {
+        /// Limits the next `flush` to `rows`, which must cover everything drawn
+        /// differently from the last flush. An empty range skips the SPI write.
+        pub fn set_flush_rows(&mut self, rows: std::ops::Range<i32>) {
+            self.flush_rows = Some(rows);
+        }
+    }
+
+    /// Extends `rows` to also cover the rows of `area`.
+    fn union_rows(
+        rows: Option<std::ops::Range<i32>>,
+        area: Rectangle,
+    ) -> Option<std::ops::Range<i32>> {
+        let top = area.top_left.y;
+        let bottom = top + area.size.height as i32;
+        Some(match rows {
+            Some(rows) => rows.start.min(top)..rows.end.max(bottom),
+            None => top..bottom,
+        })
+    }
+
+    #[test]
+    fn test_union_rows() {
+        let band = |y, h| Rectangle::new(Point::new(0, y), Size::new(240, h));
+        assert_eq!(union_rows(None, band(0, 32)), Some(0..32));
+        assert_eq!(union_rows(Some(0..32), band(72, 96)), Some(0..168));
+        assert_eq!(union_rows(Some(72..168), band(32, 208)), Some(32..240));
     }
 
     impl Dimensions for FrameBuffer {
@@ -310,6 +992,7 @@ pub mod ui {
             let mut s = Self {
                 buffers: Box::new(Framebuffer::new()),
                 background_buffers: Box::new(Framebuffer::new()),
+                flush_rows: None,
             };
 
             s.buffers.clear(color).unwrap();
@@ -325,15 +1008,19 @@ pub mod ui {
         }
 
         fn flush(&mut self) -> anyhow::Result<()> {
-            let bounding_box = self.bounding_box();
-            let x_start = bounding_box.top_left.x as i32;
-            let y_start = bounding_box.top_left.y as i32;
-            let x_end = bounding_box.top_left.x + bounding_box.size.width as i32;
-            let y_end = bounding_box.top_left.y + bounding_box.size.height as i32;
-
-            let e = flush_display(self.buffers.data(), x_start, y_start, x_end, y_end);
-            if e != 0 {
-                return Err(anyhow::anyhow!("Failed to flush display: error code {}", e));
+            let rows = self.flush_rows.take().unwrap_or(0..DISPLAY_HEIGHT as i32);
+            let y_start = rows.start.clamp(0, DISPLAY_HEIGHT as i32);
+            let y_end = rows.end.clamp(y_start, DISPLAY_HEIGHT as i32);
+
+            if y_start < y_end {
+                // whole rows are contiguous in the buffer
+                let row_bytes = self.buffers.data().len() / DISPLAY_HEIGHT;
+                let data =
+                    &self.buffers.data()[y_start as usize * row_bytes..y_end as usize * row_bytes];
+                let e = flush_display(data, 0, y_start, DISPLAY_WIDTH as i32, y_end);
+                if e != 0 {
+                    return Err(anyhow::anyhow!("Failed to flush display: error code {}", e));
+                }
             }
 
             self.buffers.clone_from(&self.background_buffers);
@@ -345,32 +1032,71 @@ pub mod ui {
             self.background_buffers.clone_from(&self.buffers);
             Ok(())
         }
+
+        fn force_flush(&mut self) -> anyhow::Result<()> {
+            self.flush_rows = None;
+            self.flush()
+        }
     }
 
-    const AVATAR_SIZE: u32 = 96;
+    /// Overflowing content starts scrolling this long after it is set.
+    const CONTENT_SCROLL_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+    /// Roughly one line every two seconds, close to TTS reading pace.
+    const CONTENT_SCROLL_PX_PER_SEC: u32 = 10;
+
     pub struct ChatUI<const N: usize> {
         state_text: String,
         state_text_pixels: Vec<Pixel<ColorFormat>>,
+        interrupt_indicator: bool,
+        /// `battery_bucket` of the last level set, `None` hides the indicator.
+        battery_bucket: Option<u8>,
+        /// `wifi_bars` of the last RSSI set, 0 while disconnected. `None`
+        /// until the first `set_wifi`, which keeps the indicator hidden.
+        wifi_bars: Option<u8>,
+        status_icon: Option<crate::ui::StatusIcon>,
 
         asr_text: String,
         asr_text_pixels: Vec<Pixel<ColorFormat>>,
 
         content: String,
         content_pixels: Vec<Pixel<ColorFormat>>,
+        /// How far the rendered text reaches below the content area, in pixels.
+        content_overflow: u32,
+        content_scroll: u32,
+        content_set_at: std::time::Instant,
 
         avatar: DynamicImage<N>,
+        avatar_area: Rectangle,
+        /// Image shown in place of the avatar, see `set_emotion`.
+        emotion: Option<(&'static [u8], DynamicImage<1>)>,
+
+        /// Areas changed since the last `render_to_target`, which only flushes
+        /// their rows. The state area is dirty while its pixels are cleared.
+        content_dirty: bool,
+        header_dirty: bool,
     }
 
     impl<const N: usize> ChatUI<N> {
-        pub fn new(avatar: DynamicImage<N>) -> Self {
+        pub fn new(avatar: DynamicImage<N>, avatar_area: Rectangle) -> Self {
             Self {
                 state_text: String::new(),
                 state_text_pixels: Vec::with_capacity(DISPLAY_WIDTH * 32),
+                interrupt_indicator: false,
+                battery_bucket: None,
+                wifi_bars: None,
+                status_icon: None,
                 asr_text: String::new(),
                 asr_text_pixels: Vec::with_capacity(DISPLAY_WIDTH * 32),
                 content: String::new(),
                 content_pixels: Vec::with_capacity(DISPLAY_WIDTH * DISPLAY_HEIGHT / 4),
+                content_overflow: 0,
+                content_scroll: 0,
+                content_set_at: std::time::Instant::now(),
                 avatar: avatar,
+                avatar_area,
+                emotion: None,
+                content_dirty: true,
+                header_dirty: true,
             }
         }
 
@@ -381,12 +1107,55 @@ pub mod ui {
             }
         }
 
+        pub fn set_interrupt_indicator(&mut self, on: bool) {
+            if self.interrupt_indicator != on {
+                self.interrupt_indicator = on;
+                self.state_text_pixels.clear();
+            }
+        }
+
+        /// Sets the battery level shown in the state area. Returns true only when
+        /// the displayed level changed and needs a render.
+        pub fn set_battery(&mut self, percent: Option<u8>) -> bool {
+            let bucket = percent.map(crate::ui::battery_bucket);
+            if self.battery_bucket == bucket {
+                return false;
+            }
+            self.battery_bucket = bucket;
+            self.state_text_pixels.clear();
+            true
+        }
+
+        /// Shows `icon` next to the state text, `None` hides it.
+        pub fn set_status_icon(&mut self, icon: Option<crate::ui::StatusIcon>) {
+            if self.status_icon != icon {
+                self.status_icon = icon;
+                self.state_text_pixels.clear();
+            }
+        }
+
+        /// Sets the WiFi signal shown in the state area from the AP's RSSI,
+        /// `None` meaning not connected, which shows zero bars in red rather
+        /// than hiding the indicator. Returns true only when the number of
+        /// bars changed and needs a render.
+        pub fn set_wifi(&mut self, rssi: Option<i8>) -> bool {
+            let bars = Some(crate::ui::wifi_bars(rssi));
+            if self.wifi_bars == bars {
+                return false;
+            }
+            self.wifi_bars = bars;
+            self.state_text_pixels.clear();
+            true
+        }
+
         pub fn set_asr(&mut self, text: String) {
             if self.asr_text != text {
                 self.asr_text = text;
                 self.content.clear();
                 self.asr_text_pixels.clear();
                 self.content_pixels.clear(); // Clear to trigger re-render with combined text
+                self.content_dirty = true;
+                self.reset_content_scroll();
             }
         }
 
@@ -394,12 +1163,94 @@ pub mod ui {
             if self.content != text {
                 self.content = text;
                 self.content_pixels.clear();
+                self.content_dirty = true;
+                self.reset_content_scroll();
             }
         }
 
+        /// Marks every area as changed, so the next render redraws and flushes
+        /// all of them after something else drew over the screen.
+        pub fn invalidate(&mut self) {
+            self.state_text_pixels.clear();
+            self.content_dirty = true;
+            self.header_dirty = true;
+        }
+
+        fn reset_content_scroll(&mut self) {
+            self.content_scroll = 0;
+            self.content_set_at = std::time::Instant::now();
+        }
+
+        /// Advances the content window when the text doesn't fit, so the end of a
+        /// long response comes into view while it is shown. Call it when
+        /// `until_scroll` says; returns true when the content moved and needs a
+        /// render.
+        pub fn scroll_content(&mut self) -> bool {
+            self.scroll_content_at(std::time::Instant::now())
+        }
+
+        fn scroll_content_at(&mut self, now: std::time::Instant) -> bool {
+            if self.content_overflow == 0 {
+                return false;
+            }
+
+            let elapsed = now
+                .saturating_duration_since(self.content_set_at)
+                .saturating_sub(CONTENT_SCROLL_DELAY);
+            let scroll = ((elapsed.as_millis() * CONTENT_SCROLL_PX_PER_SEC as u128 / 1000) as u32)
+                .min(self.content_overflow);
+            if scroll == self.content_scroll {
+                return false;
+            }
+
+            self.content_scroll = scroll;
+            self.content_dirty = true;
+            true
+        }
+
+        /// How long until `scroll_content` moves the content by the next pixel,
+        /// `None` when it fits or has scrolled to its end.
+        pub fn until_scroll(&self) -> Option<std::time::Duration> {
+            self.until_scroll_at(std::time::Instant::now())
+        }
+
+        fn until_scroll_at(&self, now: std::time::Instant) -> Option<std::time::Duration> {
+            if self.content_scroll >= self.content_overflow {
+                return None;
+            }
+            let next_ms = ((self.content_scroll + 1) * 1000).div_ceil(CONTENT_SCROLL_PX_PER_SEC);
+            let next = self.content_set_at
+                + CONTENT_SCROLL_DELAY
+                + std::time::Duration::from_millis(next_ms as u64);
+            Some(next.saturating_duration_since(now))
+        }
+
+        /// Shows the PNG `emotion` in place of the avatar, or the avatar again
+        /// for `None`. Returns true when the header changed and needs a render.
+        /// Nothing changes on layouts without an avatar.
+        pub fn set_emotion(&mut self, emotion: Option<&'static [u8]>) -> bool {
+            if self.avatar.image_data.is_empty() {
+                return false;
+            }
+            let current = self.emotion.as_ref().map(|(png, _)| png.as_ptr());
+            if current == emotion.map(|png| png.as_ptr()) {
+                return false;
+            }
+
+            self.emotion = emotion.and_then(|png| {
+                DynamicImage::new_from_png(self.avatar_area, png)
+                    .map_err(|e| log::warn!("Failed to decode emotion image: {:?}", e))
+                    .ok()
+                    .map(|img| (png, img))
+            });
+            self.header_dirty = true;
+            true
+        }
+
         pub fn set_avatar_index(&mut self, index: usize) -> bool {
             if !self.avatar.image_data.is_empty() {
                 self.avatar.set_index(index);
+                self.header_dirty = true;
                 true
             } else {
                 false
@@ -409,11 +1260,23 @@ pub mod ui {
         pub fn render_to_target(&mut self, target: &mut FrameBuffer) -> anyhow::Result<()> {
             let bounding_box = target.bounding_box();
 
-            self.avatar.render(target)?;
+            match &self.emotion {
+                Some((_, img)) => img.render(target)?,
+                None => self.avatar.render(target)?,
+            }
 
             let (state_area_box, content_area_box) = Self::layout(bounding_box);
 
+            let mut dirty_rows = None;
+            if std::mem::take(&mut self.header_dirty) {
+                dirty_rows = union_rows(dirty_rows, self.avatar_area);
+            }
+            if std::mem::take(&mut self.content_dirty) {
+                dirty_rows = union_rows(dirty_rows, content_area_box);
+            }
+
             if self.state_text_pixels.is_empty() {
+                dirty_rows = union_rows(dirty_rows, state_area_box);
                 let mut pixel_target = PixelsTarget {
                     pixels: &mut self.state_text_pixels,
                     bounding_box,
@@ -421,13 +1284,22 @@ pub mod ui {
                 Text::with_alignment(
                     &self.state_text,
                     state_area_box.center(),
-                    U8g2TextStyle::new(
-                        u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312a,
-                        ColorFormat::CSS_LIGHT_CYAN,
-                    ),
+                    crate::ui::state_text_style(),
                     Alignment::Center,
                 )
                 .draw(&mut pixel_target)?;
+                if self.interrupt_indicator {
+                    crate::ui::draw_interrupt_indicator(&mut pixel_target, state_area_box)?;
+                }
+                if let Some(icon) = self.status_icon {
+                    crate::ui::draw_status_icon(&mut pixel_target, state_area_box, icon)?;
+                }
+                if let Some(bars) = self.wifi_bars {
+                    crate::ui::draw_wifi_indicator(&mut pixel_target, state_area_box, bars)?;
+                }
+                if let Some(bucket) = self.battery_bucket {
+                    crate::ui::draw_battery_indicator(&mut pixel_target, state_area_box, bucket)?;
+                }
             }
             target.draw_iter(self.state_text_pixels.iter().cloned())?;
 
@@ -455,32 +1327,47 @@ pub mod ui {
                 embedded_text::TextBox::with_textbox_style(
                     &combined_text,
                     content_area_box,
-                    crate::ui::MyTextStyle(
-                        U8g2TextStyle::new(
-                            u8g2_fonts::fonts::u8g2_font_wqy16_t_gb2312,
-                            ColorFormat::CSS_WHEAT,
-                        ),
-                        3,
-                    ),
+                    crate::ui::content_text_style(),
                     textbox_style,
                 )
                 .draw(&mut pixel_target)?;
+
+                let area_bottom = content_area_box.top_left.y + content_area_box.size.height as i32;
+                let text_bottom = self.content_pixels.iter().map(|p| p.0.y + 1).max();
+                self.content_overflow =
+                    text_bottom.map(|y| (y - area_bottom).max(0)).unwrap_or(0) as u32;
+                self.content_scroll = self.content_scroll.min(self.content_overflow);
+            }
+
+            if self.content_scroll == 0 {
+                target.draw_iter(self.content_pixels.iter().cloned())?;
+            } else {
+                let offset = Point::new(0, self.content_scroll as i32);
+                let top = content_area_box.top_left.y;
+                target.draw_iter(
+                    self.content_pixels
+                        .iter()
+                        .map(|p| Pixel(p.0 - offset, p.1))
+                        .filter(|p| p.0.y >= top),
+                )?;
             }
-            target.draw_iter(self.content_pixels.iter().cloned())?;
+
+            target.set_flush_rows(dirty_rows.unwrap_or(0..0));
 
             Ok(())
         }
 
         pub fn layout(bounding_box: Rectangle) -> (Rectangle, Rectangle) {
+            let bar = crate::ui::STATE_BAR_HEIGHT;
             let state_area_box = Rectangle::new(
                 bounding_box.top_left,
-                Size::new(bounding_box.size.width, 32),
+                Size::new(bounding_box.size.width, bar),
             );
 
-            let content_height = bounding_box.size.height - 32;
+            let content_height = bounding_box.size.height - bar;
 
             let content_area_box = Rectangle::new(
-                bounding_box.top_left + Point::new(0, 32),
+                bounding_box.top_left + Point::new(0, bar as i32),
                 Size::new(bounding_box.size.width, content_height),
             );
 
@@ -488,37 +1375,78 @@ pub mod ui {
         }
     }
 
+    #[test]
+    fn test_content_scroll_tick() {
+        let mut ui = ChatUI::<1>::new(DynamicImage::empty(), Rectangle::zero());
+        assert_eq!(ui.until_scroll(), None);
+
+        let start = std::time::Instant::now();
+        ui.content_set_at = start;
+        ui.content_overflow = 25;
+        assert!(!ui.scroll_content_at(start + std::time::Duration::from_secs(1)));
+
+        // driven by the tick alone, the content reaches its end a pixel at a time
+        let mut now = start;
+        let mut ticks = 0;
+        while let Some(wait) = ui.until_scroll_at(now) {
+            now += wait;
+            assert!(ui.scroll_content_at(now));
+            ticks += 1;
+        }
+        assert_eq!(ticks, 25);
+        assert_eq!(ui.content_scroll, 25);
+        assert_eq!(
+            now - start,
+            CONTENT_SCROLL_DELAY + std::time::Duration::from_millis(2500)
+        );
+    }
+
+    #[test]
+    fn test_set_wifi() {
+        let mut ui = ChatUI::<1>::new(DynamicImage::empty(), Rectangle::zero());
+        assert_eq!(ui.wifi_bars, None);
+
+        assert!(ui.set_wifi(Some(-60)));
+        assert_eq!(ui.wifi_bars, Some(3));
+        assert!(!ui.set_wifi(Some(-62)));
+
+        // disconnected still shows the indicator, with no bars
+        assert!(ui.set_wifi(None));
+        assert_eq!(ui.wifi_bars, Some(0));
+        assert!(!ui.set_wifi(None));
+    }
+
+    #[test]
+    fn test_chat_layout() {
+        let screen = Rectangle::new(
+            Point::zero(),
+            Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32),
+        );
+        let (state, content) = ChatUI::<1>::layout(screen);
+        assert_eq!(state, Rectangle::new(Point::new(0, 0), Size::new(240, 32)));
+        assert_eq!(
+            content,
+            Rectangle::new(Point::new(0, 32), Size::new(240, 208))
+        );
+    }
+
     pub fn new_chat_ui<const N: usize>(
         target: &mut FrameBuffer,
         avatar_gif: &[u8],
     ) -> anyhow::Result<ChatUI<N>> {
         let bounding_box = target.bounding_box();
 
+        let avatar = crate::ui::avatar_size(bounding_box.size);
         let header_area_box = Rectangle::new(
-            bounding_box.center()
-                - Point {
-                    x: AVATAR_SIZE as i32 / 2,
-                    y: AVATAR_SIZE as i32 / 2,
-                },
-            Size::new(AVATAR_SIZE, AVATAR_SIZE),
+            bounding_box.center() - Point::new(avatar as i32 / 2, avatar as i32 / 2),
+            Size::new(avatar, avatar),
         );
 
         let (state_area_box, content_area_box) = ChatUI::<N>::layout(bounding_box);
-        let state_style = PrimitiveStyleBuilder::new()
-            .stroke_color(ColorFormat::CSS_DARK_BLUE)
-            .stroke_width(1)
-            .fill_color(ColorFormat::CSS_DARK_BLUE)
-            .build();
-
-        let pixels = crate::ui::get_background_pixels(target, state_area_box, state_style, 0.5);
+        let pixels = crate::ui::state_background_pixels(target, state_area_box);
         target.draw_iter(pixels)?;
 
-        let content_style = PrimitiveStyleBuilder::new()
-            .stroke_color(ColorFormat::CSS_BLACK)
-            .stroke_width(5)
-            .fill_color(ColorFormat::CSS_BLACK)
-            .build();
-        let pixels = crate::ui::get_background_pixels(target, content_area_box, content_style, 0.5);
+        let pixels = crate::ui::content_background_pixels(target, content_area_box);
         target.draw_iter(pixels)?;
 
         target.background_buffers.clone_from(&target.buffers);
@@ -529,7 +1457,7 @@ pub mod ui {
             DynamicImage::new_from_gif(header_area_box, avatar_gif).unwrap_or(DynamicImage::empty())
         };
 
-        Ok(ChatUI::new(avatar))
+        Ok(ChatUI::new(avatar, header_area_box))
     }
 
     pub struct ConfiguresUI {
@@ -12,11 +12,50 @@ const RESET_ID: BleUuid = uuid128!("f0e1d2c3-b4a5-6789-0abc-def123456789");
 const AFE_LINEAR_GAIN_ID: BleUuid = uuid128!("a1b2c3d4-e5f6-4789-0abc-def123456789");
 const AGC_TARGET_LEVEL_ID: BleUuid = uuid128!("b2c3d4e5-f6a7-4890-1bcd-ef2345678901");
 const AGC_COMPRESSION_GAIN_ID: BleUuid = uuid128!("c3d4e5f6-a7b8-4901-2cde-f34567890123");
+const HIGH_CONTRAST_ID: BleUuid = uuid128!("d4e5f6a7-b8c9-4a12-3def-456789012345");
+const ANALYTICS_ID: BleUuid = uuid128!("e5f6a7b8-c9d0-4b23-4ef0-567890123456");
+const ROLE_ID: BleUuid = uuid128!("f6a7b8c9-d0e1-4c34-5f01-678901234567");
+const STATIC_IP_ID: BleUuid = uuid128!("a7b8c9d0-e1f2-4d45-6012-789012345678");
+const WAKE_SOUND_ID: BleUuid = uuid128!("d0e1f2a3-b4c5-4a78-9345-012345678901");
+const DISPLAY_ORIENTATION_ID: BleUuid = uuid128!("c9d0e1f2-a3b4-4f67-8234-901234567890");
+const WIFI_NETWORKS_ID: BleUuid = uuid128!("e1f2a3b4-c5d6-4b89-a456-123456789012");
+const WIFI_TEST_ID: BleUuid = uuid128!("f2a3b4c5-d6e7-4c9a-b567-234567890123");
+const CONFIG_SERVER_ID: BleUuid = uuid128!("a3b4c5d6-e7f8-4dab-8678-345678901234");
+#[cfg(feature = "mfrc522")]
+const NFC_URL_ID: BleUuid = uuid128!("b8c9d0e1-f2a3-4e56-7123-890123456789");
 
+/// Applies `f` to a copy of the config and saves it, updating the in-memory
+/// setting only once the save succeeded.
+fn update_config(
+    setting: &mut (super::Setting, esp_idf_svc::nvs::EspDefaultNvs),
+    f: impl FnOnce(&mut crate::config::DeviceConfig),
+) -> anyhow::Result<()> {
+    let mut config = setting.0.config.clone();
+    f(&mut config);
+    config.save(&mut setting.1)?;
+    setting.0.config = config;
+    Ok(())
+}
+
+/// Last WiFi test result, read from `WIFI_TEST_ID` as JSON, e.g.
+/// `{"status":"failed","error":"..."}`.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum WifiTest {
+    Idle,
+    Testing,
+    Ok,
+    Failed { error: String },
+}
+
+/// `wifi` is the unused station interface of the setup mode, for
+/// `WIFI_TEST_ID`.
 pub fn bt(
     device_id: &str,
     setting: Arc<Mutex<(super::Setting, esp_idf_svc::nvs::EspDefaultNvs)>>,
     evt_tx: tokio::sync::mpsc::Sender<crate::app::Event>,
+    wifi: esp_idf_svc::wifi::EspWifi<'static>,
+    sysloop: esp_idf_svc::eventloop::EspSystemEventLoop,
 ) -> anyhow::Result<()> {
     let ble_device = esp32_nimble::BLEDevice::take();
     let ble_advertising = ble_device.get_advertising();
@@ -52,7 +91,7 @@ pub fn bt(
         .on_read(move |c, _| {
             log::info!("Read from SSID characteristic");
             let setting = setting1.lock().unwrap();
-            c.set_value(setting.0.ssid.as_bytes());
+            c.set_value(setting.0.config.ssid.as_bytes());
         })
         .on_write(move |args| {
             log::info!(
@@ -63,10 +102,8 @@ pub fn bt(
             if let Ok(new_ssid) = String::from_utf8(args.recv_data().to_vec()) {
                 log::info!("New SSID: {}", new_ssid);
                 let mut setting = setting2.lock().unwrap();
-                if let Err(e) = setting.1.set_str("ssid", &new_ssid) {
+                if let Err(e) = update_config(&mut setting, |c| c.ssid = new_ssid) {
                     log::error!("Failed to save SSID to NVS: {:?}", e);
-                } else {
-                    setting.0.ssid = new_ssid;
                 }
             } else {
                 log::error!("Failed to parse new SSID from bytes.");
@@ -83,7 +120,7 @@ pub fn bt(
         .on_read(move |c, _| {
             log::info!("Read from pass characteristic");
             let setting = setting1.lock().unwrap();
-            c.set_value(setting.0.pass.as_bytes());
+            c.set_value(setting.0.config.pass.as_bytes());
         })
         .on_write(move |args| {
             log::info!(
@@ -94,10 +131,8 @@ pub fn bt(
             if let Ok(new_pass) = String::from_utf8(args.recv_data().to_vec()) {
                 log::info!("New pass: {}", new_pass);
                 let mut setting = setting2.lock().unwrap();
-                if let Err(e) = setting.1.set_str("pass", &new_pass) {
+                if let Err(e) = update_config(&mut setting, |c| c.pass = new_pass) {
                     log::error!("Failed to save pass to NVS: {:?}", e);
-                } else {
-                    setting.0.pass = new_pass;
                 }
             } else {
                 log::error!("Failed to parse new pass from bytes.");
@@ -108,6 +143,7 @@ pub fn bt(
     let setting_ = setting.clone();
     let setting_gif = setting.clone();
     let setting_avatar = setting.clone();
+    let setting_wake_sound = setting.clone();
     let setting_afe = setting.clone(); // Extra clone for AFE characteristics
 
     let server_url_characteristic = service.lock().create_characteristic(
@@ -119,7 +155,7 @@ pub fn bt(
         .on_read(move |c, _| {
             log::info!("Read from server URL characteristic");
             let setting = setting.lock().unwrap();
-            c.set_value(setting.0.server_url.as_bytes());
+            c.set_value(setting.0.config.server_url.as_bytes());
         })
         .on_write(move |args| {
             log::info!(
@@ -128,12 +164,15 @@ pub fn bt(
                 args.recv_data()
             );
             if let Ok(new_server_url) = String::from_utf8(args.recv_data().to_vec()) {
+                if let Err(e) = crate::network::validate_server_url(&new_server_url) {
+                    log::error!("Rejected server URL: {:?}", e);
+                    args.reject();
+                    return;
+                }
                 log::info!("New server URL: {}", new_server_url);
                 let mut setting = setting_.lock().unwrap();
-                if let Err(e) = setting.1.set_str("server_url", &new_server_url) {
+                if let Err(e) = update_config(&mut setting, |c| c.server_url = new_server_url) {
                     log::error!("Failed to save server URL to NVS: {:?}", e);
-                } else {
-                    setting.0.server_url = new_server_url;
                 }
             } else {
                 log::error!("Failed to parse new server URL from bytes.");
@@ -153,8 +192,13 @@ pub fn bt(
             if gif_chunk.len() < 512 {
                 setting.0.background_gif.1 = true; // Mark as valid
             }
-            if setting.0.background_gif.0.len() > 1024 * 1024 {
-                log::warn!("Background GIF size exceeds 1024KB, resetting to default.");
+            let max_kb = setting.0.config.background_gif_max_kb as usize;
+            if setting.0.background_gif.0.len() > max_kb * 1024 {
+                log::error!(
+                    "Background GIF rejected: {} bytes received, the limit is {}KB",
+                    setting.0.background_gif.0.len(),
+                    max_kb
+                );
                 setting.0.background_gif.0.clear();
                 setting.0.background_gif.1 = false;
                 args.reject();
@@ -198,6 +242,37 @@ pub fn bt(
         }
     });
 
+    // Wake sound characteristic, a 16-bit PCM WAV in 512-byte chunks; a
+    // shorter chunk ends it and an empty write restores the built-in sound
+    let wake_sound_characteristic = service
+        .lock()
+        .create_characteristic(WAKE_SOUND_ID, NimbleProperties::WRITE);
+    wake_sound_characteristic.lock().on_write(move |args| {
+        let chunk = args.recv_data();
+        let mut setting = setting_wake_sound.lock().unwrap();
+        if chunk.is_empty() {
+            log::info!("Clearing wake sound to default.");
+            setting.0.wake_sound.0.clear();
+            setting.0.wake_sound.1 = true;
+            return;
+        }
+
+        log::info!("New wake sound chunk received, size: {}", chunk.len());
+        setting.0.wake_sound.0.extend_from_slice(chunk);
+        if chunk.len() < 512 {
+            setting.0.wake_sound.1 = true;
+        }
+        if setting.0.wake_sound.0.len() > crate::audio::WAKE_SOUND_MAX_SIZE {
+            log::error!(
+                "Wake sound rejected, larger than {} bytes",
+                crate::audio::WAKE_SOUND_MAX_SIZE
+            );
+            setting.0.wake_sound.0.clear();
+            setting.0.wake_sound.1 = false;
+            args.reject();
+        }
+    });
+
     let reset_characteristic = service
         .lock()
         .create_characteristic(RESET_ID, NimbleProperties::WRITE);
@@ -224,7 +299,7 @@ pub fn bt(
         .on_read(move |c, _| {
             log::info!("Read from AFE linear gain characteristic");
             let setting = setting1.lock().unwrap();
-            let afe_line_gain_str = format!("{}", setting.0.afe_linear_gain);
+            let afe_line_gain_str = format!("{}", setting.0.config.afe_linear_gain);
             c.set_value(afe_line_gain_str.as_bytes());
         })
         .on_write(move |args| {
@@ -237,11 +312,9 @@ pub fn bt(
             if let Some(gain) = gain {
                 log::info!("New AFE linear gain: {}", gain);
                 let mut setting = setting2.lock().unwrap();
-                if let Err(e) = setting.1.set_blob("afe_linear_gain", &gain.to_le_bytes()) {
+                if let Err(e) = update_config(&mut setting, |c| c.afe_linear_gain = gain) {
                     log::error!("Failed to save AFE linear gain to NVS: {:?}", e);
                     args.reject();
-                } else {
-                    setting.0.afe_linear_gain = gain;
                 }
             } else {
                 log::error!("Failed to parse new AFE linear gain from bytes.");
@@ -261,7 +334,14 @@ pub fn bt(
         .on_read(move |c, _| {
             log::info!("Read from AGC target level characteristic");
             let setting = setting1.lock().unwrap();
-            c.set_value(setting.0.agc_target_level_dbfs.to_le_bytes().as_ref());
+            c.set_value(
+                setting
+                    .0
+                    .config
+                    .agc_target_level_dbfs
+                    .to_le_bytes()
+                    .as_ref(),
+            );
         })
         .on_write(move |args| {
             let data = args.recv_data();
@@ -269,11 +349,9 @@ pub fn bt(
                 let level = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
                 log::info!("New AGC target level: {}", level);
                 let mut setting = setting2.lock().unwrap();
-                if let Err(e) = setting.1.set_i32("agc_tl_dbfs", level) {
+                if let Err(e) = update_config(&mut setting, |c| c.agc_target_level_dbfs = level) {
                     log::error!("Failed to save AGC target level to NVS: {:?}", e);
                     args.reject();
-                } else {
-                    setting.0.agc_target_level_dbfs = level;
                 }
             } else {
                 log::error!("Failed to parse new AGC target level from bytes.");
@@ -293,7 +371,14 @@ pub fn bt(
         .on_read(move |c, _| {
             log::info!("Read from AGC compression gain characteristic");
             let setting = setting1.lock().unwrap();
-            c.set_value(setting.0.agc_compression_gain_db.to_le_bytes().as_ref());
+            c.set_value(
+                setting
+                    .0
+                    .config
+                    .agc_compression_gain_db
+                    .to_le_bytes()
+                    .as_ref(),
+            );
         })
         .on_write(move |args| {
             let data = args.recv_data();
@@ -301,11 +386,9 @@ pub fn bt(
                 let gain = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
                 log::info!("New AGC compression gain: {}", gain);
                 let mut setting = setting2.lock().unwrap();
-                if let Err(e) = setting.1.set_i32("agc_cg_db", gain) {
+                if let Err(e) = update_config(&mut setting, |c| c.agc_compression_gain_db = gain) {
                     log::error!("Failed to save AGC compression gain to NVS: {:?}", e);
                     args.reject();
-                } else {
-                    setting.0.agc_compression_gain_db = gain;
                 }
             } else {
                 log::error!("Failed to parse new AGC compression gain from bytes.");
@@ -313,6 +396,334 @@ pub fn bt(
             }
         });
 
+    // High-contrast UI characteristic, a single byte: 0 = off, 1 = on
+    let setting1 = setting_afe.clone();
+    let setting2 = setting_afe.clone();
+    let high_contrast_characteristic = service.lock().create_characteristic(
+        HIGH_CONTRAST_ID,
+        NimbleProperties::READ | NimbleProperties::WRITE,
+    );
+    high_contrast_characteristic
+        .lock()
+        .on_read(move |c, _| {
+            log::info!("Read from high contrast characteristic");
+            let setting = setting1.lock().unwrap();
+            c.set_value(&[setting.0.config.high_contrast as u8]);
+        })
+        .on_write(move |args| {
+            let data = args.recv_data();
+            if data.len() == 1 && data[0] <= 1 {
+                let enabled = data[0] == 1;
+                log::info!("New high contrast mode: {}", enabled);
+                let mut setting = setting2.lock().unwrap();
+                if let Err(e) = update_config(&mut setting, |c| c.high_contrast = enabled) {
+                    log::error!("Failed to save high contrast mode to NVS: {:?}", e);
+                    args.reject();
+                }
+            } else {
+                log::error!("Failed to parse new high contrast mode from bytes.");
+                args.reject();
+            }
+        });
+
+    // Display orientation characteristic, a single byte: bit 0 mirror X, bit 1
+    // mirror Y, bit 2 swap X/Y, or 0xFF for the board default. Applied on reboot.
+    let setting1 = setting_afe.clone();
+    let setting2 = setting_afe.clone();
+    let display_orientation_characteristic = service.lock().create_characteristic(
+        DISPLAY_ORIENTATION_ID,
+        NimbleProperties::READ | NimbleProperties::WRITE,
+    );
+    display_orientation_characteristic
+        .lock()
+        .on_read(move |c, _| {
+            log::info!("Read from display orientation characteristic");
+            let setting = setting1.lock().unwrap();
+            let bits = setting
+                .0
+                .config
+                .display_orientation
+                .map_or(0xFF, |o| o.to_bits());
+            c.set_value(&[bits]);
+        })
+        .on_write(move |args| {
+            let data = args.recv_data();
+            let orientation = match data {
+                [0xFF] => Some(None),
+                [bits] => crate::boards::DisplayOrientation::from_bits(*bits).map(Some),
+                _ => None,
+            };
+            if let Some(orientation) = orientation {
+                log::info!("New display orientation: {:?}", orientation);
+                let mut setting = setting2.lock().unwrap();
+                if let Err(e) = update_config(&mut setting, |c| c.display_orientation = orientation)
+                {
+                    log::error!("Failed to save display orientation to NVS: {:?}", e);
+                    args.reject();
+                }
+            } else {
+                log::error!("Failed to parse new display orientation from bytes.");
+                args.reject();
+            }
+        });
+
+    // Analytics opt-in characteristic, a single byte: 0 = off, 1 = on
+    let setting1 = setting_afe.clone();
+    let setting2 = setting_afe.clone();
+    let analytics_characteristic = service.lock().create_characteristic(
+        ANALYTICS_ID,
+        NimbleProperties::READ | NimbleProperties::WRITE,
+    );
+    analytics_characteristic
+        .lock()
+        .on_read(move |c, _| {
+            log::info!("Read from analytics characteristic");
+            let setting = setting1.lock().unwrap();
+            c.set_value(&[setting.0.config.analytics_enabled as u8]);
+        })
+        .on_write(move |args| {
+            let data = args.recv_data();
+            if data.len() == 1 && data[0] <= 1 {
+                let enabled = data[0] == 1;
+                log::info!("New analytics opt-in: {}", enabled);
+                let mut setting = setting2.lock().unwrap();
+                if let Err(e) = update_config(&mut setting, |c| c.analytics_enabled = enabled) {
+                    log::error!("Failed to save analytics opt-in to NVS: {:?}", e);
+                    args.reject();
+                }
+            } else {
+                log::error!("Failed to parse new analytics opt-in from bytes.");
+                args.reject();
+            }
+        });
+
+    // Server role/persona characteristic, UTF-8, empty for none
+    let setting1 = setting_afe.clone();
+    let setting2 = setting_afe.clone();
+    let role_characteristic = service
+        .lock()
+        .create_characteristic(ROLE_ID, NimbleProperties::READ | NimbleProperties::WRITE);
+    role_characteristic
+        .lock()
+        .on_read(move |c, _| {
+            log::info!("Read from role characteristic");
+            let setting = setting1.lock().unwrap();
+            c.set_value(setting.0.config.role.as_bytes());
+        })
+        .on_write(move |args| {
+            if let Ok(new_role) = String::from_utf8(args.recv_data().to_vec()) {
+                log::info!("New role: {:?}", new_role);
+                let mut setting = setting2.lock().unwrap();
+                if let Err(e) = update_config(&mut setting, |c| c.role = new_role) {
+                    log::error!("Failed to save role to NVS: {:?}", e);
+                    args.reject();
+                }
+            } else {
+                log::error!("Failed to parse new role from bytes.");
+                args.reject();
+            }
+        });
+
+    // Static IP characteristic, UTF-8 "ip,gateway,netmask" dotted quads, empty for DHCP
+    let setting1 = setting_afe.clone();
+    let setting2 = setting_afe.clone();
+    let static_ip_characteristic = service.lock().create_characteristic(
+        STATIC_IP_ID,
+        NimbleProperties::READ | NimbleProperties::WRITE,
+    );
+    static_ip_characteristic
+        .lock()
+        .on_read(move |c, _| {
+            log::info!("Read from static IP characteristic");
+            let setting = setting1.lock().unwrap();
+            let config = &setting.0.config;
+            let value = if config.static_ip.is_empty() {
+                String::new()
+            } else {
+                format!("{},{},{}", config.static_ip, config.gateway, config.netmask)
+            };
+            c.set_value(value.as_bytes());
+        })
+        .on_write(move |args| {
+            let Ok(value) = String::from_utf8(args.recv_data().to_vec()) else {
+                log::error!("Failed to parse static IP from bytes.");
+                args.reject();
+                return;
+            };
+
+            let mut parts = value.trim().splitn(3, ',').map(str::trim);
+            let ip = parts.next().unwrap_or_default().to_string();
+            let gateway = parts.next().unwrap_or_default().to_string();
+            let netmask = parts.next().unwrap_or_default().to_string();
+            if let Err(e) = crate::network::StaticIp::parse(&ip, &gateway, &netmask) {
+                log::error!("Rejected static IP {:?}: {:?}", value, e);
+                args.reject();
+                return;
+            }
+
+            log::info!("New static IP: {:?}", value);
+            let mut setting = setting2.lock().unwrap();
+            if let Err(e) = update_config(&mut setting, |c| {
+                c.static_ip = ip;
+                c.gateway = gateway;
+                c.netmask = netmask;
+            }) {
+                log::error!("Failed to save static IP to NVS: {:?}", e);
+                args.reject();
+            }
+        });
+
+    // Extra WiFi networks characteristic, JSON `[{"ssid": "..", "pass": ".."}]`
+    // tried after (or before, if stronger) the SSID/password above
+    let setting1 = setting_afe.clone();
+    let setting2 = setting_afe.clone();
+    let wifi_networks_characteristic = service.lock().create_characteristic(
+        WIFI_NETWORKS_ID,
+        NimbleProperties::READ | NimbleProperties::WRITE,
+    );
+    wifi_networks_characteristic
+        .lock()
+        .on_read(move |c, _| {
+            log::info!("Read from WiFi networks characteristic");
+            let setting = setting1.lock().unwrap();
+            let value = serde_json::to_vec(&setting.0.config.extra_networks).unwrap_or_default();
+            c.set_value(&value);
+        })
+        .on_write(move |args| {
+            let networks =
+                match serde_json::from_slice::<Vec<crate::config::WifiNetwork>>(args.recv_data()) {
+                    Ok(networks) => networks,
+                    Err(e) => {
+                        log::error!("Failed to parse WiFi networks: {:?}", e);
+                        args.reject();
+                        return;
+                    }
+                };
+            if let Err(e) = crate::config::DeviceConfig::validate_extra_networks(&networks) {
+                log::error!("Rejected WiFi networks: {:?}", e);
+                args.reject();
+                return;
+            }
+
+            log::info!("New WiFi networks: {} extra", networks.len());
+            let mut setting = setting2.lock().unwrap();
+            if let Err(e) = update_config(&mut setting, |c| c.extra_networks = networks) {
+                log::error!("Failed to save WiFi networks to NVS: {:?}", e);
+                args.reject();
+            }
+        });
+
+    // WiFi test characteristic: write JSON `{"ssid": "..", "pass": ".."}` to try
+    // joining that network once without saving it, then read the result. The
+    // radio is shared with BLE, so the link may slow down during the test.
+    let wifi = Arc::new(Mutex::new(wifi));
+    let wifi_test = Arc::new(Mutex::new(WifiTest::Idle));
+    let wifi_test1 = wifi_test.clone();
+    let wifi_test_characteristic = service.lock().create_characteristic(
+        WIFI_TEST_ID,
+        NimbleProperties::READ | NimbleProperties::WRITE,
+    );
+    wifi_test_characteristic
+        .lock()
+        .on_read(move |c, _| {
+            let value = serde_json::to_vec(&*wifi_test1.lock().unwrap()).unwrap_or_default();
+            c.set_value(&value);
+        })
+        .on_write(move |args| {
+            let network =
+                match serde_json::from_slice::<crate::config::WifiNetwork>(args.recv_data()) {
+                    Ok(network) if !network.ssid.is_empty() => network,
+                    r => {
+                        log::error!("Invalid WiFi test request: {:?}", r.err());
+                        args.reject();
+                        return;
+                    }
+                };
+            {
+                let mut status = wifi_test.lock().unwrap();
+                if matches!(*status, WifiTest::Testing) {
+                    log::warn!("WiFi test already running");
+                    args.reject();
+                    return;
+                }
+                *status = WifiTest::Testing;
+            }
+
+            // connecting takes seconds, don't hold up the BLE host task
+            let (wifi, wifi_test, sysloop) = (wifi.clone(), wifi_test.clone(), sysloop.clone());
+            let spawned = std::thread::Builder::new()
+                .name("wifi_test".to_string())
+                .stack_size(6 * 1024)
+                .spawn(move || {
+                    let r = crate::network::test_wifi(
+                        &mut wifi.lock().unwrap(),
+                        sysloop,
+                        &network.ssid,
+                        &network.pass,
+                    );
+                    log::info!("WiFi test of {}: {:?}", network.ssid, r);
+                    *wifi_test.lock().unwrap() = match r {
+                        Ok(()) => WifiTest::Ok,
+                        Err(e) => WifiTest::Failed {
+                            error: e.to_string(),
+                        },
+                    };
+                });
+            if let Err(e) = spawned {
+                log::error!("Failed to start WiFi test: {:?}", e);
+                *wifi_test.lock().unwrap() = WifiTest::Failed {
+                    error: e.to_string(),
+                };
+            }
+        });
+
+    // Config server characteristic: write the bearer token (UTF-8, 8 to 64
+    // bytes) to turn the HTTP config server on, or nothing to turn it off.
+    // Write-only, the token is never read back. Applied on reboot.
+    let setting1 = setting_afe.clone();
+    let config_server_characteristic = service
+        .lock()
+        .create_characteristic(CONFIG_SERVER_ID, NimbleProperties::WRITE);
+    config_server_characteristic.lock().on_write(move |args| {
+        let token = match String::from_utf8(args.recv_data().to_vec()) {
+            Ok(token) if token.is_empty() || (8..=64).contains(&token.len()) => token,
+            _ => {
+                log::error!("Failed to parse config server token from bytes.");
+                args.reject();
+                return;
+            }
+        };
+        log::info!("Config server enabled: {}", !token.is_empty());
+        let mut setting = setting1.lock().unwrap();
+        if let Err(e) = update_config(&mut setting, |c| {
+            c.config_server = !token.is_empty();
+            c.config_server_token = token;
+        }) {
+            log::error!("Failed to save config server token to NVS: {:?}", e);
+            args.reject();
+        }
+    });
+
+    // Burns a server URL (UTF-8) to the next MIFARE Ultralight tag held to the reader
+    #[cfg(feature = "mfrc522")]
+    {
+        let nfc_url_characteristic = service
+            .lock()
+            .create_characteristic(NFC_URL_ID, NimbleProperties::WRITE);
+        nfc_url_characteristic.lock().on_write(move |args| {
+            let Ok(url) = String::from_utf8(args.recv_data().to_vec()) else {
+                log::error!("Failed to parse NFC URL from bytes.");
+                args.reject();
+                return;
+            };
+            log::info!("NFC URL to write: {:?}", url);
+            if let Err(e) = crate::boards::request_ndef_uri_write(url) {
+                log::error!("Rejected NFC URL: {:?}", e);
+                args.reject();
+            }
+        });
+    }
+
     ble_advertising.lock().set_data(
         BLEAdvertisementData::new()
             .name(&format!("EchoKit-{}", device_id))
@@ -0,0 +1,700 @@
+use serde::{Deserialize, Serialize};
+
+/// NVS key holding the JSON-encoded `DeviceConfig`.
+pub const CONFIG_KEY: &str = "config";
+
+/// Bump when a field changes meaning, and handle the old value in `DeviceConfig::upgraded`.
+/// Version 0 is the original layout with one NVS key per setting.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Per-key settings from schema version 0, migrated into `CONFIG_KEY` on first boot.
+const LEGACY_KEYS: &[&str] = &[
+    "ssid",
+    "pass",
+    "server_url",
+    "afe_linear_gain",
+    "agc_tl_dbfs",
+    "agc_cg_db",
+];
+
+/// How many networks `DeviceConfig::wifi_networks` returns, `ssid` included.
+pub const MAX_WIFI_NETWORKS: usize = 3;
+
+/// How long `save_on_change` waits after a change before writing it.
+pub const SAVE_DEBOUNCE: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// A WiFi network to try besides the primary `ssid`/`pass`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WifiNetwork {
+    pub ssid: String,
+    pub pass: String,
+}
+
+/// All user settings, stored together as a single NVS blob so an update is
+/// written in one go.
+///
+/// The boot `state` flag and the background/avatar GIFs stay under their own
+/// keys: the flag is rewritten on every boot and the GIFs are too large to
+/// round-trip through JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    pub version: u32,
+
+    pub ssid: String,
+    pub pass: String,
+    /// More networks for a device that moves between places, see
+    /// `wifi_networks`.
+    pub extra_networks: Vec<WifiNetwork>,
+    pub server_url: String,
+    /// Optional persona on the server, see `ws::Server`. Empty means none.
+    pub role: String,
+    /// Static IPv4 address, gateway and netmask as dotted quads, see
+    /// `network::StaticIp`. All empty means DHCP.
+    pub static_ip: String,
+    pub gateway: String,
+    pub netmask: String,
+
+    // AFE parameters
+    pub afe_linear_gain: f32,
+    pub agc_target_level_dbfs: i32,
+    pub agc_compression_gain_db: i32,
+    pub vad_mode: u8,
+    pub vad_min_noise_ms: i32,
+    /// WakeNet model name for the `wakeword` feature, empty for the default.
+    pub wakenet_model: String,
+
+    // UI parameters
+    pub gif_max_fps: u32,
+    pub high_contrast: bool,
+    /// Overrides the board's display mirroring/rotation, `None` keeps it.
+    pub display_orientation: Option<crate::boards::DisplayOrientation>,
+    /// Backlight level (0..=100) while in use, and after
+    /// `backlight_dim_after_secs` Idle with no events. 0 secs never dims.
+    pub backlight_level: u8,
+    pub backlight_dim_level: u8,
+    pub backlight_dim_after_secs: u32,
+    /// See `app::AppConfig::power_save_after`, 0 (the default) turns it off.
+    pub power_save_after_secs: u32,
+    /// Keep playing the background GIF while Idle instead of stopping at its
+    /// last frame, see `ui::GifLoop`.
+    pub idle_gif_loop: bool,
+    /// Largest background GIF accepted over BLE, in KB.
+    pub background_gif_max_kb: u32,
+    /// Local time offset from UTC for the idle clock, in minutes.
+    pub utc_offset_minutes: i32,
+    /// NTP servers, up to `sntp::MAX_SERVERS`. Empty uses the public pool.
+    pub ntp_servers: Vec<String>,
+    /// Quiet hours in local time, 0..=23, see `app::AppConfig::quiet_hours`.
+    /// Equal hours (the default) or an hour out of range turn them off.
+    pub quiet_hours_start: u8,
+    pub quiet_hours_end: u8,
+
+    // App parameters
+    pub keep_connection_on_idle: bool,
+    pub max_reconnect_failures: u32,
+    pub reconnect_failure_window_secs: u32,
+    /// See `app::AppConfig::response_timeout`.
+    pub response_timeout_secs: u32,
+    /// WebSocket keepalive ping after this many seconds without a frame from
+    /// the server, see `ws::Server`. 0 disables it.
+    pub ws_ping_interval_secs: u32,
+    /// Give up on a server handshake after this many seconds, see
+    /// `ws::Server::connect_timeout`.
+    pub server_connect_timeout_secs: u32,
+    /// Frames from the server larger than this many KiB are dropped unread,
+    /// see `ws::MAX_PAYLOAD_LEN`.
+    pub ws_max_payload_kb: u32,
+    /// Voice interrupt toggled with a long K0 press, kept across reboots.
+    pub allow_interrupt: bool,
+    /// Playback volume 1..=5, kept across reboots.
+    pub volume: u8,
+    /// Connect to the server while the audio workers start, see `main`.
+    pub preconnect_server: bool,
+    pub min_submit_ms: u32,
+    pub min_submit_amplitude: u16,
+    /// See `app::AppConfig::submit_chunk_secs`.
+    pub submit_chunk_ms: u32,
+    /// Voice interrupt thresholds, see `app::AppConfig::min_interrupt_secs`.
+    pub min_interrupt_ms: u32,
+    pub min_interrupt_amplitude: u16,
+    /// Window for a K0 double press (`app::Event::K0_DOUBLE`), 0 disables it.
+    /// Single presses are reported only after the window has passed.
+    pub k0_double_press_ms: u32,
+    /// Hold K0 to talk instead of VAD, see `app::AppConfig::push_to_talk`.
+    pub push_to_talk: bool,
+
+    // Analytics, opt-in
+    pub analytics_enabled: bool,
+    pub analytics_interval_secs: u32,
+    /// Heap/RSSI/uptime samples, see `telemetry`. 0 disables them.
+    pub telemetry_interval_secs: u32,
+    /// Liveness pings, see `telemetry::start_heartbeat`. 0 disables them.
+    pub heartbeat_interval_secs: u32,
+
+    /// How long an NFC tag has to be away before it is read again
+    /// (`mfrc522` feature), in milliseconds.
+    pub nfc_card_cooldown_ms: u32,
+    /// MFRC522 receiver gain, 0-7 for 18-48 dB. Raise it if tags only read
+    /// when held right on the reader.
+    pub nfc_antenna_gain: u8,
+    /// First block read for text from MIFARE Classic tags, see
+    /// `boards::NFC_CLASSIC_BLOCK`.
+    pub nfc_classic_block: u8,
+
+    /// Keep serving the settings over HTTP on the LAN once connected, see
+    /// `config_server`. Off by default to save RAM.
+    pub config_server: bool,
+    /// Bearer token `config_server` requires on every request. The server
+    /// doesn't start without one. Set over BLE, never shown by `redacted`.
+    pub config_server_token: String,
+
+    /// Button events to forward to the server, see
+    /// `app::AppConfig::forward_buttons`.
+    pub forward_buttons: Vec<String>,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        static DEFAULT_SERVER_URL: Option<&str> = std::option_env!("DEFAULT_SERVER_URL");
+
+        let app_config = crate::app::AppConfig::default();
+
+        Self {
+            version: SCHEMA_VERSION,
+            ssid: String::new(),
+            pass: String::new(),
+            extra_networks: Vec::new(),
+            server_url: DEFAULT_SERVER_URL.unwrap_or_default().to_string(),
+            role: String::new(),
+            static_ip: String::new(),
+            gateway: String::new(),
+            netmask: String::new(),
+            afe_linear_gain: unsafe { crate::audio::AFE_LINEAR_GAIN },
+            agc_target_level_dbfs: unsafe { crate::audio::AGC_TARGET_LEVEL_DBFS },
+            agc_compression_gain_db: unsafe { crate::audio::AGC_COMPRESSION_GAIN_DB },
+            vad_mode: crate::audio::AfeTuning::DEFAULT.vad_mode,
+            vad_min_noise_ms: crate::audio::AfeTuning::DEFAULT.vad_min_noise_ms,
+            wakenet_model: String::new(),
+            gif_max_fps: unsafe { crate::ui::GIF_MAX_FPS },
+            high_contrast: unsafe { crate::ui::HIGH_CONTRAST },
+            display_orientation: None,
+            backlight_level: app_config.backlight_level,
+            backlight_dim_level: app_config.backlight_dim_level,
+            backlight_dim_after_secs: app_config
+                .backlight_dim_after
+                .map_or(0, |d| d.as_secs() as u32),
+            power_save_after_secs: app_config
+                .power_save_after
+                .map_or(0, |d| d.as_secs() as u32),
+            idle_gif_loop: false,
+            background_gif_max_kb: 1024,
+            utc_offset_minutes: app_config.utc_offset_minutes,
+            ntp_servers: Vec::new(),
+            quiet_hours_start: app_config.quiet_hours.map_or(0, |(start, _)| start),
+            quiet_hours_end: app_config.quiet_hours.map_or(0, |(_, end)| end),
+            keep_connection_on_idle: app_config.keep_connection_on_idle,
+            max_reconnect_failures: app_config.max_reconnect_failures,
+            reconnect_failure_window_secs: app_config.reconnect_failure_window.as_secs() as u32,
+            response_timeout_secs: app_config.response_timeout.as_secs() as u32,
+            ws_ping_interval_secs: 30,
+            server_connect_timeout_secs: crate::ws::DEFAULT_CONNECT_TIMEOUT.as_secs() as u32,
+            ws_max_payload_kb: 256,
+            allow_interrupt: false,
+            volume: 3,
+            preconnect_server: false,
+            min_submit_ms: (app_config.min_submit_secs * 1000.0) as u32,
+            submit_chunk_ms: (app_config.submit_chunk_secs * 1000.0) as u32,
+            min_submit_amplitude: app_config.min_submit_amplitude,
+            min_interrupt_ms: (app_config.min_interrupt_secs * 1000.0) as u32,
+            min_interrupt_amplitude: app_config.min_interrupt_amplitude,
+            k0_double_press_ms: 0,
+            push_to_talk: app_config.push_to_talk,
+            analytics_enabled: false,
+            analytics_interval_secs: 10 * 60,
+            telemetry_interval_secs: 0,
+            heartbeat_interval_secs: 0,
+            nfc_card_cooldown_ms: 5000,
+            nfc_antenna_gain: 7,
+            nfc_classic_block: 4,
+            config_server: false,
+            config_server_token: String::new(),
+            forward_buttons: app_config.forward_buttons,
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Loads the config blob, migrating from the per-key layout if there is none.
+    ///
+    /// After a successful migration the config is saved and the legacy keys are
+    /// removed, so the migration only runs once. A blob that can't be read or
+    /// parsed is left alone and the defaults are used for this boot, so a
+    /// firmware that understands it can still pick it up.
+    pub fn load<S: ConfigStore>(store: &mut S) -> anyhow::Result<Self> {
+        match store.read_blob(CONFIG_KEY) {
+            Ok(Some(data)) => match serde_json::from_slice::<Self>(&data) {
+                Ok(config) => return Ok(config.upgraded()),
+                Err(e) => {
+                    log::error!("Failed to parse config, using defaults: {}", e);
+                    return Ok(Self::default());
+                }
+            },
+            Ok(None) => log::info!("No config found, migrating legacy keys"),
+            Err(e) => {
+                log::error!("Failed to read config, using defaults: {:?}", e);
+                return Ok(Self::default());
+            }
+        }
+
+        let config = Self::from_legacy(store);
+        config.save(store)?;
+
+        for key in LEGACY_KEYS {
+            if let Err(e) = store.erase(key) {
+                log::warn!("Failed to remove legacy key {}: {:?}", key, e);
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn save<S: ConfigStore>(&self, store: &mut S) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize config: {}", e))?;
+        store.write_blob(CONFIG_KEY, &data)
+    }
+
+    /// Removes the config blob and any leftover legacy keys, so the next `load`
+    /// starts from defaults.
+    pub fn erase<S: ConfigStore>(store: &mut S) -> anyhow::Result<()> {
+        store.erase(CONFIG_KEY)?;
+        for key in LEGACY_KEYS {
+            store.erase(key)?;
+        }
+        Ok(())
+    }
+
+    /// Checks `networks` fits `extra_networks`: at most `MAX_WIFI_NETWORKS - 1`
+    /// of them, each with an SSID and password the WiFi config can hold.
+    pub fn validate_extra_networks(networks: &[WifiNetwork]) -> anyhow::Result<()> {
+        if networks.len() >= MAX_WIFI_NETWORKS {
+            return Err(anyhow::anyhow!(
+                "At most {} extra WiFi networks",
+                MAX_WIFI_NETWORKS - 1
+            ));
+        }
+        for n in networks {
+            if n.ssid.is_empty() || n.ssid.len() > 32 {
+                return Err(anyhow::anyhow!("Invalid WiFi name {:?}", n.ssid));
+            }
+            if n.pass.len() > 64 {
+                return Err(anyhow::anyhow!("WiFi password too long for {}", n.ssid));
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves every config published on `rx`. After a change it waits `debounce`
+    /// (`SAVE_DEBOUNCE` in `main`) before writing, so a burst of changes costs a
+    /// single NVS write.
+    pub async fn save_on_change<S: ConfigStore>(
+        mut rx: tokio::sync::watch::Receiver<Self>,
+        mut store: S,
+        debounce: std::time::Duration,
+    ) {
+        while rx.changed().await.is_ok() {
+            tokio::time::sleep(debounce).await;
+            let config = rx.borrow_and_update().clone();
+            if let Err(e) = config.save(&mut store) {
+                log::error!("Failed to save config: {:?}", e);
+            }
+        }
+    }
+
+    /// A copy with the fields in the JSON object `patch` replaced, for partial
+    /// updates. Fails if `patch` isn't an object or a field has the wrong type.
+    ///
+    /// Empty secrets keep their current value (the password of a network whose
+    /// SSID is unchanged, the `config_server_token`), so the output of
+    /// `redacted` can be posted back without wiping them.
+    pub fn merged(&self, patch: &[u8]) -> anyhow::Result<Self> {
+        let patch: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(patch)
+            .map_err(|e| anyhow::anyhow!("Config update is not a JSON object: {}", e))?;
+        let mut value = serde_json::to_value(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize config: {}", e))?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.extend(patch);
+        }
+        let mut merged: Self = serde_json::from_value(value)
+            .map_err(|e| anyhow::anyhow!("Invalid config update: {}", e))?;
+
+        if merged.pass.is_empty() && merged.ssid == self.ssid {
+            merged.pass = self.pass.clone();
+        }
+        for network in &mut merged.extra_networks {
+            if network.pass.is_empty() {
+                if let Some(old) = self.extra_networks.iter().find(|n| n.ssid == network.ssid) {
+                    network.pass = old.pass.clone();
+                }
+            }
+        }
+        if merged.config_server_token.is_empty() {
+            merged.config_server_token = self.config_server_token.clone();
+        }
+        Ok(merged)
+    }
+
+    /// A copy without the WiFi passwords and the config server token, for
+    /// showing the config to others.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        config.pass.clear();
+        for network in &mut config.extra_networks {
+            network.pass.clear();
+        }
+        config.config_server_token.clear();
+        config
+    }
+
+    /// `ssid` followed by `extra_networks` as (SSID, password) pairs, without
+    /// empty SSIDs or repeats and at most `MAX_WIFI_NETWORKS` of them.
+    pub fn wifi_networks(&self) -> Vec<(&str, &str)> {
+        let mut networks: Vec<(&str, &str)> = Vec::new();
+        let all = std::iter::once((self.ssid.as_str(), self.pass.as_str())).chain(
+            self.extra_networks
+                .iter()
+                .map(|n| (n.ssid.as_str(), n.pass.as_str())),
+        );
+        for (ssid, pass) in all {
+            if !ssid.is_empty() && !networks.iter().any(|(s, _)| *s == ssid) {
+                networks.push((ssid, pass));
+            }
+        }
+        networks.truncate(MAX_WIFI_NETWORKS);
+        networks
+    }
+
+    /// `quiet_hours_start`/`quiet_hours_end` as `app::AppConfig::quiet_hours`.
+    pub fn quiet_hours(&self) -> Option<(u8, u8)> {
+        let (start, end) = (self.quiet_hours_start, self.quiet_hours_end);
+        (start != end && start < 24 && end < 24).then_some((start, end))
+    }
+
+    /// Reads the schema version 0 layout, falling back to defaults for missing keys.
+    fn from_legacy<S: ConfigStore>(store: &S) -> Self {
+        fn read<T>(key: &str, r: anyhow::Result<Option<T>>) -> Option<T> {
+            r.map_err(|e| log::error!("Failed to get {}: {:?}", key, e))
+                .ok()
+                .flatten()
+        }
+
+        let default = Self::default();
+
+        Self {
+            version: SCHEMA_VERSION,
+            ssid: read("ssid", store.read_str("ssid")).unwrap_or(default.ssid),
+            pass: read("pass", store.read_str("pass")).unwrap_or(default.pass),
+            server_url: read("server_url", store.read_str("server_url"))
+                .unwrap_or(default.server_url),
+            afe_linear_gain: read("afe_linear_gain", store.read_blob("afe_linear_gain"))
+                .filter(|b| b.len() == 4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .unwrap_or(default.afe_linear_gain),
+            agc_target_level_dbfs: read("agc_tl_dbfs", store.read_i32("agc_tl_dbfs"))
+                .unwrap_or(default.agc_target_level_dbfs),
+            agc_compression_gain_db: read("agc_cg_db", store.read_i32("agc_cg_db"))
+                .unwrap_or(default.agc_compression_gain_db),
+            ..default
+        }
+    }
+
+    /// Brings a config saved by an older firmware up to `SCHEMA_VERSION`.
+    fn upgraded(mut self) -> Self {
+        if self.version > SCHEMA_VERSION {
+            log::warn!(
+                "Config schema {} is newer than {}, unknown fields are ignored",
+                self.version,
+                SCHEMA_VERSION
+            );
+        }
+        self.version = SCHEMA_VERSION;
+        self
+    }
+}
+
+/// The subset of NVS used by `DeviceConfig`, so loading and migration can run
+/// against an in-memory store in tests.
+pub trait ConfigStore {
+    fn read_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    fn write_blob(&mut self, key: &str, data: &[u8]) -> anyhow::Result<()>;
+    fn read_str(&self, key: &str) -> anyhow::Result<Option<String>>;
+    fn read_i32(&self, key: &str) -> anyhow::Result<Option<i32>>;
+    fn erase(&mut self, key: &str) -> anyhow::Result<()>;
+}
+
+impl ConfigStore for esp_idf_svc::nvs::EspDefaultNvs {
+    fn read_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(len) = self.blob_len(key)? else {
+            return Ok(None);
+        };
+        let mut buf = vec![0; len];
+        Ok(self.get_blob(key, &mut buf)?.map(|b| b.to_vec()))
+    }
+
+    fn write_blob(&mut self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        self.set_blob(key, data)?;
+        Ok(())
+    }
+
+    fn read_str(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut buf = [0; 256];
+        Ok(self.get_str(key, &mut buf)?.map(|s| s.to_string()))
+    }
+
+    fn read_i32(&self, key: &str) -> anyhow::Result<Option<i32>> {
+        Ok(self.get_i32(key)?)
+    }
+
+    fn erase(&mut self, key: &str) -> anyhow::Result<()> {
+        self.remove(key)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct MemStore(std::collections::HashMap<String, Vec<u8>>);
+
+#[cfg(test)]
+impl ConfigStore for MemStore {
+    fn read_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.0.get(key).cloned())
+    }
+
+    fn write_blob(&mut self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        self.0.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn read_str(&self, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .0
+            .get(key)
+            .map(|v| String::from_utf8_lossy(v).to_string()))
+    }
+
+    fn read_i32(&self, key: &str) -> anyhow::Result<Option<i32>> {
+        Ok(self
+            .0
+            .get(key)
+            .map(|v| i32::from_le_bytes([v[0], v[1], v[2], v[3]])))
+    }
+
+    fn erase(&mut self, key: &str) -> anyhow::Result<()> {
+        self.0.remove(key);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_device_config_serde() {
+    let config = DeviceConfig {
+        ssid: "echokit".to_string(),
+        pass: "secret".to_string(),
+        server_url: "ws://192.168.1.2:8080/ws".to_string(),
+        afe_linear_gain: 2.0,
+        high_contrast: true,
+        max_reconnect_failures: 5,
+        ..Default::default()
+    };
+
+    let data = serde_json::to_vec(&config).unwrap();
+    let config2: DeviceConfig = serde_json::from_slice(&data).unwrap();
+    assert_eq!(config, config2);
+
+    // fields added by later firmware fall back to their defaults
+    let config3: DeviceConfig = serde_json::from_str(r#"{"ssid":"echokit"}"#).unwrap();
+    assert_eq!(config3.ssid, "echokit");
+    assert_eq!(config3.gif_max_fps, DeviceConfig::default().gif_max_fps);
+}
+
+#[test]
+fn test_device_config_migrate_legacy() {
+    let mut store = MemStore::default();
+    store.write_blob("ssid", b"echokit").unwrap();
+    store.write_blob("pass", b"secret").unwrap();
+    store
+        .write_blob("server_url", b"ws://192.168.1.2:8080/ws")
+        .unwrap();
+    store
+        .write_blob("afe_linear_gain", &2.5f32.to_le_bytes())
+        .unwrap();
+    store.write_blob("agc_cg_db", &9i32.to_le_bytes()).unwrap();
+
+    let config = DeviceConfig::load(&mut store).unwrap();
+    assert_eq!(config.version, SCHEMA_VERSION);
+    assert_eq!(config.ssid, "echokit");
+    assert_eq!(config.pass, "secret");
+    assert_eq!(config.server_url, "ws://192.168.1.2:8080/ws");
+    assert_eq!(config.afe_linear_gain, 2.5);
+    assert_eq!(config.agc_compression_gain_db, 9);
+    assert_eq!(
+        config.agc_target_level_dbfs,
+        DeviceConfig::default().agc_target_level_dbfs
+    );
+
+    for key in LEGACY_KEYS {
+        assert!(store.read_blob(key).unwrap().is_none(), "{key} not removed");
+    }
+
+    // second boot reads the saved blob instead of migrating again
+    let config2 = DeviceConfig::load(&mut store).unwrap();
+    assert_eq!(config, config2);
+}
+
+#[test]
+fn test_device_config_load_unparsable() {
+    let mut store = MemStore::default();
+    store.write_blob(CONFIG_KEY, b"{not json").unwrap();
+    store.write_blob("ssid", b"echokit").unwrap();
+
+    let config = DeviceConfig::load(&mut store).unwrap();
+    assert_eq!(config, DeviceConfig::default());
+    // neither the blob nor the legacy keys are touched
+    assert_eq!(store.read_blob(CONFIG_KEY).unwrap().unwrap(), b"{not json");
+    assert!(store.read_blob("ssid").unwrap().is_some());
+}
+
+#[test]
+fn test_wifi_networks() {
+    let network = |ssid: &str| WifiNetwork {
+        ssid: ssid.to_string(),
+        pass: format!("{}-pass", ssid),
+    };
+    let mut config = DeviceConfig {
+        ssid: "home".to_string(),
+        pass: "home-pass".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(config.wifi_networks(), [("home", "home-pass")]);
+
+    config.extra_networks = vec![network("office"), network(""), network("home")];
+    assert_eq!(
+        config.wifi_networks(),
+        [("home", "home-pass"), ("office", "office-pass")]
+    );
+
+    config.extra_networks = vec![network("a"), network("b"), network("c")];
+    assert_eq!(config.wifi_networks().len(), MAX_WIFI_NETWORKS);
+
+    config.ssid.clear();
+    assert_eq!(config.wifi_networks()[0], ("a", "a-pass"));
+}
+
+#[test]
+fn test_quiet_hours() {
+    let hours = |start, end| DeviceConfig {
+        quiet_hours_start: start,
+        quiet_hours_end: end,
+        ..Default::default()
+    };
+    assert_eq!(DeviceConfig::default().quiet_hours(), None);
+    assert_eq!(hours(22, 7).quiet_hours(), Some((22, 7)));
+    assert_eq!(hours(0, 6).quiet_hours(), Some((0, 6)));
+    assert_eq!(hours(8, 8).quiet_hours(), None);
+    assert_eq!(hours(22, 24).quiet_hours(), None);
+}
+
+#[test]
+fn test_device_config_merged() {
+    let config = DeviceConfig {
+        ssid: "echokit".to_string(),
+        pass: "secret".to_string(),
+        ..Default::default()
+    };
+
+    let merged = config
+        .merged(br#"{"server_url":"ws://10.0.0.2/ws","volume":5}"#)
+        .unwrap();
+    assert_eq!(merged.server_url, "ws://10.0.0.2/ws");
+    assert_eq!(merged.volume, 5);
+    assert_eq!(merged.ssid, "echokit");
+    assert_eq!(merged.pass, "secret");
+    assert_eq!(config.merged(b"{}").unwrap(), config);
+
+    assert!(config.merged(br#"{"volume":"loud"}"#).is_err());
+    assert!(config.merged(b"[1, 2]").is_err());
+    assert!(config.merged(b"").is_err());
+
+    let redacted = config.redacted();
+    assert_eq!(redacted.pass, "");
+    assert_eq!(redacted.ssid, "echokit");
+}
+
+#[test]
+fn test_device_config_merged_keeps_secrets() {
+    let config = DeviceConfig {
+        ssid: "echokit".to_string(),
+        pass: "secret".to_string(),
+        extra_networks: vec![WifiNetwork {
+            ssid: "office".to_string(),
+            pass: "office-secret".to_string(),
+        }],
+        config_server_token: "token".to_string(),
+        ..Default::default()
+    };
+
+    // the redacted config posted back unchanged
+    let body = serde_json::to_vec(&config.redacted()).unwrap();
+    assert_eq!(config.merged(&body).unwrap(), config);
+
+    // a new network with no password is open, not given the old one
+    let merged = config
+        .merged(br#"{"ssid":"guest","pass":"","extra_networks":[{"ssid":"cafe","pass":""}]}"#)
+        .unwrap();
+    assert_eq!(merged.pass, "");
+    assert_eq!(merged.extra_networks[0].pass, "");
+}
+
+#[test]
+fn test_validate_extra_networks() {
+    let network = |ssid: &str, pass: &str| WifiNetwork {
+        ssid: ssid.to_string(),
+        pass: pass.to_string(),
+    };
+    assert!(DeviceConfig::validate_extra_networks(&[]).is_ok());
+    assert!(DeviceConfig::validate_extra_networks(&[network("a", ""), network("b", "x")]).is_ok());
+    assert!(DeviceConfig::validate_extra_networks(&[
+        network("a", ""),
+        network("b", ""),
+        network("c", "")
+    ])
+    .is_err());
+    assert!(DeviceConfig::validate_extra_networks(&[network("", "x")]).is_err());
+    assert!(DeviceConfig::validate_extra_networks(&[network(&"s".repeat(33), "")]).is_err());
+    assert!(DeviceConfig::validate_extra_networks(&[network("a", &"p".repeat(65))]).is_err());
+}
+
+#[test]
+fn test_device_config_erase() {
+    let mut store = MemStore::default();
+    let config = DeviceConfig {
+        ssid: "echokit".to_string(),
+        server_url: "ws://192.168.1.2:8080/ws".to_string(),
+        ..Default::default()
+    };
+    config.save(&mut store).unwrap();
+    store.write_blob("pass", b"secret").unwrap();
+    store.write_blob("avatar_gif", b"GIF89a").unwrap();
+
+    DeviceConfig::erase(&mut store).unwrap();
+    assert!(store.read_blob(CONFIG_KEY).unwrap().is_none());
+    assert!(store.read_blob("pass").unwrap().is_none());
+    // keys outside the config are left to the caller
+    assert!(store.read_blob("avatar_gif").unwrap().is_some());
+
+    let config = DeviceConfig::load(&mut store).unwrap();
+    assert!(config.ssid.is_empty());
+    assert_eq!(config.server_url, DeviceConfig::default().server_url);
+}
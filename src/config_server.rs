@@ -0,0 +1,163 @@
+//! Optional HTTP server for changing the settings from the LAN after
+//! provisioning, enabled by `DeviceConfig::config_server`.
+//!
+//! Every request needs `Authorization: Bearer <DeviceConfig::config_server_token>`,
+//! others get 401.
+//!
+//! `GET /config` returns the config as JSON without the WiFi passwords.
+//! `POST /config` takes a JSON object with the fields to change (empty
+//! passwords keep the current ones), publishes the result to the running
+//! config, which `DeviceConfig::save_on_change` writes to NVS, and then reboots
+//! so it takes effect, like saving over BLE does.
+//! `GET /logs` returns the recent turn log (`telemetry::turn_log`) as a JSON
+//! array of strings, oldest first.
+
+use esp_idf_svc::{
+    http::{
+        server::{Configuration, EspHttpServer},
+        Headers, Method,
+    },
+    io::{Read, Write},
+};
+
+use crate::config::DeviceConfig;
+
+pub const PORT: u16 = 80;
+
+/// Larger requests are rejected; the config JSON is well under this.
+const MAX_BODY_LEN: usize = 4096;
+
+/// Time for the response to go out and `save_on_change` to write the update
+/// before rebooting.
+const REBOOT_DELAY: std::time::Duration =
+    crate::config::SAVE_DEBOUNCE.saturating_add(std::time::Duration::from_secs(2));
+
+/// Whether the `Authorization` header carries `token`. The token is compared
+/// in constant time, so response timing doesn't leak how much of it matched.
+fn authorized(header: Option<&str>, token: &str) -> bool {
+    let Some(given) = header.and_then(|h| h.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    !token.is_empty()
+        && given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[test]
+fn test_authorized() {
+    assert!(authorized(Some("Bearer s3cret"), "s3cret"));
+    assert!(!authorized(Some("Bearer s3creT"), "s3cret"));
+    assert!(!authorized(Some("Bearer s3cre"), "s3cret"));
+    assert!(!authorized(Some("Bearer s3cret2"), "s3cret"));
+    assert!(!authorized(Some("s3cret"), "s3cret"));
+    assert!(!authorized(None, "s3cret"));
+    assert!(!authorized(Some("Bearer "), ""));
+}
+
+/// Starts the server on `PORT`. `config` is the running config: requests see
+/// changes made at runtime, and updates are published to it. Fails without a
+/// `config_server_token`.
+pub fn start(
+    config: tokio::sync::watch::Sender<DeviceConfig>,
+) -> anyhow::Result<EspHttpServer<'static>> {
+    if config.borrow().config_server_token.is_empty() {
+        anyhow::bail!("No config_server_token set");
+    }
+
+    let mut server = EspHttpServer::new(&Configuration {
+        http_port: PORT,
+        stack_size: 8 * 1024,
+        ..Default::default()
+    })?;
+
+    let config_get = config.clone();
+    server.fn_handler("/config", Method::Get, move |req| -> anyhow::Result<()> {
+        if !authorized(
+            req.header("Authorization"),
+            &config_get.borrow().config_server_token,
+        ) {
+            req.into_status_response(401)?;
+            return Ok(());
+        }
+        let body = serde_json::to_vec(&config_get.borrow().redacted())?;
+        req.into_response(200, None, &[("Content-Type", "application/json")])?
+            .write_all(&body)?;
+        Ok(())
+    })?;
+
+    let config_logs = config.clone();
+    server.fn_handler("/logs", Method::Get, move |req| -> anyhow::Result<()> {
+        if !authorized(
+            req.header("Authorization"),
+            &config_logs.borrow().config_server_token,
+        ) {
+            req.into_status_response(401)?;
+            return Ok(());
+        }
+        let body = serde_json::to_vec(&crate::telemetry::turn_log())?;
+        req.into_response(200, None, &[("Content-Type", "application/json")])?
+            .write_all(&body)?;
+        Ok(())
+    })?;
+
+    server.fn_handler(
+        "/config",
+        Method::Post,
+        move |mut req| -> anyhow::Result<()> {
+            if !authorized(
+                req.header("Authorization"),
+                &config.borrow().config_server_token,
+            ) {
+                req.into_status_response(401)?;
+                return Ok(());
+            }
+            let len = req.content_len().unwrap_or(0) as usize;
+            if len > MAX_BODY_LEN {
+                req.into_status_response(413)?;
+                return Ok(());
+            }
+            let mut body = vec![0; len];
+            let mut read = 0;
+            while read < len {
+                match req.read(&mut body[read..])? {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            body.truncate(read);
+
+            let updated = config.borrow().merged(&body).and_then(|updated| {
+                crate::network::validate_server_url(&updated.server_url)?;
+                DeviceConfig::validate_extra_networks(&updated.extra_networks)?;
+                Ok(updated)
+            });
+            let updated = match updated {
+                Ok(updated) => updated,
+                Err(e) => {
+                    log::warn!("Rejected config update: {:?}", e);
+                    req.into_status_response(400)?
+                        .write_all(e.to_string().as_bytes())?;
+                    return Ok(());
+                }
+            };
+
+            config.send_replace(updated);
+
+            log::info!("Config updated over HTTP, rebooting");
+            req.into_ok_response()?;
+            std::thread::spawn(|| {
+                std::thread::sleep(REBOOT_DELAY);
+                unsafe { esp_idf_svc::sys::esp_restart() }
+            });
+            Ok(())
+        },
+    )?;
+
+    log::info!("Config server listening on port {}", PORT);
+    Ok(server)
+}
@@ -1,19 +1,27 @@
 use std::sync::{Arc, Mutex};
 
 use embedded_graphics::{
-    prelude::{Dimensions, RgbColor, WebColors},
+    prelude::{Dimensions, RgbColor, Size, WebColors},
     Drawable,
 };
 use esp_idf_svc::eventloop::EspSystemEventLoop;
 
 use crate::ui::DisplayTargetDrive;
 
+mod analytics;
 mod app;
 mod audio;
 mod bt;
 mod codec;
+mod config;
+mod config_server;
 mod network;
+mod ota;
+mod power;
 mod protocol;
+mod self_test;
+mod sntp;
+mod telemetry;
 mod ui;
 mod ws;
 
@@ -23,58 +31,25 @@ mod peripheral;
 
 #[derive(Debug, Clone)]
 struct Setting {
-    ssid: String,
-    pass: String,
-    server_url: String,
+    config: config::DeviceConfig,
     background_gif: (Vec<u8>, bool), // (data, ended)
     avatar_gif: (Vec<u8>, bool),     // (data, ended)
+    wake_sound: (Vec<u8>, bool),     // (WAV data, ended), empty for `audio::WAKE_WAV`
     state: u8,                       // if 1, enter setup mode
-    // AFE parameters
-    afe_linear_gain: f32,
-    agc_target_level_dbfs: i32,
-    agc_compression_gain_db: i32,
 }
 
 impl Setting {
-    fn load_from_nvs(nvs: &esp_idf_svc::nvs::EspDefaultNvs) -> anyhow::Result<Self> {
-        let mut str_buf = [0; 128];
-
-        let ssid = nvs
-            .get_str("ssid", &mut str_buf)
-            .map_err(|e| log::error!("Failed to get ssid: {:?}", e))
-            .ok()
-            .flatten()
-            .unwrap_or_default()
-            .to_string();
-
-        let pass = nvs
-            .get_str("pass", &mut str_buf)
-            .map_err(|e| log::error!("Failed to get pass: {:?}", e))
-            .ok()
-            .flatten()
-            .unwrap_or_default()
-            .to_string();
-
-        static DEFAULT_SERVER_URL: Option<&str> = std::option_env!("DEFAULT_SERVER_URL");
-        log::info!("DEFAULT_SERVER_URL: {:?}", DEFAULT_SERVER_URL);
-
-        let server_url = nvs
-            .get_str("server_url", &mut str_buf)
-            .map_err(|e| log::error!("Failed to get server_url: {:?}", e))
+    fn load_from_nvs(nvs: &mut esp_idf_svc::nvs::EspDefaultNvs) -> anyhow::Result<Self> {
+        let config = config::DeviceConfig::load(nvs)?;
+
+        // sized from the stored blob; if the size can't be read, use the default
+        // rather than guessing a buffer size
+        let background_gif_size = nvs
+            .blob_len("background_gif")
+            .map_err(|e| log::error!("Failed to get background_gif size: {:?}", e))
             .ok()
-            .flatten()
-            .or(DEFAULT_SERVER_URL)
-            .unwrap_or_default()
-            .to_string();
-
-        let background_gif = if nvs.contains("background_gif")? {
-            let background_gif_size = nvs
-                .blob_len("background_gif")
-                .map_err(|e| log::error!("Failed to get background_gif size: {:?}", e))
-                .ok()
-                .flatten()
-                .unwrap_or(1024 * 1024);
-
+            .flatten();
+        let background_gif = if let Some(background_gif_size) = background_gif_size {
             let mut gif_buf = vec![0; background_gif_size];
             let gif_buf_ = nvs
                 .get_blob("background_gif", &mut gif_buf)?
@@ -119,91 +94,135 @@ impl Setting {
             Vec::new()
         };
 
-        let state = nvs.get_u8("state")?.unwrap_or(0);
-
-        let mut afe_linear_gain_buf = [0u8; 4];
-        let afe_linear_gain = nvs
-            .get_blob("afe_linear_gain", &mut afe_linear_gain_buf)
-            .map_err(|e| {
-                log::error!("Failed to get afe_linear_gain: {:?}", e);
-            })
-            .ok()
-            .flatten()
-            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
-            .unwrap_or(unsafe { audio::AFE_LINEAR_GAIN });
-
-        let agc_target_level_dbfs = nvs
-            .get_i32("agc_tl_dbfs")
-            .map_err(|e| {
-                log::error!("Failed to get agc_target_level_dbfs: {:?}", e);
-            })
-            .ok()
-            .flatten()
-            .unwrap_or(unsafe { audio::AGC_TARGET_LEVEL_DBFS });
+        let wake_sound = match nvs.blob_len("wake_sound") {
+            Ok(Some(len)) => {
+                let mut buf = vec![0; len];
+                let data = nvs.get_blob("wake_sound", &mut buf)?.unwrap_or(&[]);
+                data.to_vec()
+            }
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                log::error!("Failed to get wake_sound size: {:?}", e);
+                Vec::new()
+            }
+        };
 
-        let agc_compression_gain_db = nvs
-            .get_i32("agc_cg_db")
-            .map_err(|e| {
-                log::error!("Failed to get agc_compression_gain_db: {:?}", e);
-            })
-            .ok()
-            .flatten()
-            .unwrap_or(unsafe { audio::AGC_COMPRESSION_GAIN_DB });
+        let state = nvs.get_u8("state")?.unwrap_or(0);
 
         Ok(Setting {
-            ssid,
-            pass,
-            server_url,
+            config,
             background_gif: (background_gif, false),
             avatar_gif: (avatar_gif, false),
+            wake_sound: (wake_sound, false),
             state,
-            afe_linear_gain,
-            agc_target_level_dbfs,
-            agc_compression_gain_db,
         })
     }
 
     fn need_init(&self) -> bool {
         self.state == 1
-            || self.ssid.is_empty()
-            || self.pass.is_empty()
-            || self.server_url.is_empty()
+            || self.config.ssid.is_empty()
+            || self.config.pass.is_empty()
+            || self.config.server_url.is_empty()
     }
 }
 
+/// How long K0 has to be held at boot to trigger a factory reset.
+const FACTORY_RESET_HOLD: std::time::Duration = std::time::Duration::from_secs(5);
+/// NVS keys outside `DeviceConfig` that a factory reset also clears.
+const FACTORY_RESET_KEYS: &[&str] = &["background_gif", "avatar_gif", "wake_sound", "state"];
+
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
     esp_idf_svc::log::EspLogger::initialize_default();
-    let peripherals = esp_idf_svc::hal::prelude::Peripherals::take().unwrap();
+    let mut peripherals = esp_idf_svc::hal::prelude::Peripherals::take().unwrap();
     let sysloop = EspSystemEventLoop::take()?;
     let _fs = esp_idf_svc::io::vfs::MountedEventfs::mount(20)?;
     let partition = esp_idf_svc::nvs::EspDefaultNvsPartition::take()?;
-    let nvs = esp_idf_svc::nvs::EspDefaultNvs::new(partition, "setting", true)?;
+    let mut nvs = esp_idf_svc::nvs::EspDefaultNvs::new(partition, "setting", true)?;
 
-    let mut setting = Setting::load_from_nvs(&nvs)?;
+    let mut setting = Setting::load_from_nvs(&mut nvs)?;
     nvs.set_u8("state", 0).unwrap();
 
-    log::info!("SSID: {:?}", setting.ssid);
-    log::info!("PASS: {:?}", setting.pass);
-    log::info!("Server URL: {:?}", setting.server_url);
+    log::info!("SSID: {:?}", setting.config.ssid);
+    log::info!("PASS: {:?}", setting.config.pass);
+    log::info!("Server URL: {:?}", setting.config.server_url);
 
     log_heap();
 
     let (evt_tx, mut evt_rx) = tokio::sync::mpsc::channel(64);
     let (tx1, rx1) = tokio::sync::mpsc::unbounded_channel();
 
-    crate::start_hal!(peripherals, evt_tx);
-
-    let mut framebuffer = Box::new(boards::ui::DisplayBuffer::new(ui::ColorFormat::WHITE));
-    framebuffer.flush()?;
-
-    crate::ui::display_gif(framebuffer.as_mut(), &setting.background_gif.0).unwrap();
-
     // Configures the button
     let mut button = esp_idf_svc::hal::gpio::PinDriver::input(peripherals.pins.gpio0)?;
     button.set_pull(esp_idf_svc::hal::gpio::Pull::Up)?;
     button.set_interrupt_type(esp_idf_svc::hal::gpio::InterruptType::AnyEdge)?;
 
+    // Safe mode recovers a unit that crashes during peripheral init. It skips the
+    // I2C tasks (MFRC522, EXIO), the box codec init, the extra_server URL wait and
+    // never starts the audio workers. The display still comes up with a
+    // diagnostics screen, and K0 continues into BLE provisioning (WiFi and server
+    // settings), which reboots when done. Server-pushed OTA updates need the
+    // full firmware running, so a unit stuck here is updated over USB.
+    let safe_mode = crate::safe_mode_requested!(peripherals, button);
+    if safe_mode {
+        log::warn!("Booting in safe mode");
+    }
+    let self_test = !safe_mode && crate::self_test_requested!(peripherals, button);
+    if self_test {
+        log::warn!("Booting into the self-test");
+    }
+    let echo_test = !safe_mode && !self_test && crate::echo_test_requested!(peripherals);
+    if echo_test {
+        log::warn!("Booting into the echo test");
+    }
+
+    #[cfg(feature = "mfrc522")]
+    boards::NFC_CARD_COOLDOWN_MS.store(
+        setting.config.nfc_card_cooldown_ms,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    #[cfg(feature = "mfrc522")]
+    boards::NFC_ANTENNA_GAIN.store(
+        setting.config.nfc_antenna_gain,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    #[cfg(feature = "mfrc522")]
+    boards::NFC_CLASSIC_BLOCK.store(
+        setting.config.nfc_classic_block,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+
+    *boards::DISPLAY_ORIENTATION.lock().unwrap() = setting.config.display_orientation;
+    boards::set_backlight_level(setting.config.backlight_level);
+
+    crate::start_hal!(peripherals, evt_tx, safe_mode);
+
+    let mut framebuffer = Box::new(boards::ui::DisplayBuffer::new(ui::ColorFormat::WHITE));
+    framebuffer.flush()?;
+
+    unsafe {
+        ui::GIF_MAX_FPS = setting.config.gif_max_fps;
+        ui::HIGH_CONTRAST = setting.config.high_contrast;
+    }
+    // cut short by K0, so the held-K0 prompts below come up right away
+    let skipped =
+        match crate::ui::display_gif_until(framebuffer.as_mut(), &setting.background_gif.0, || {
+            button.is_low()
+        }) {
+            Ok(skipped) => skipped,
+            Err(e) => {
+                // a stored GIF that doesn't decode mustn't stop the boot
+                log::error!("Background GIF failed, using the default: {:?}", e);
+                setting.background_gif.0 = ui::DEFAULT_BACKGROUND.to_vec();
+                crate::ui::display_gif_until(framebuffer.as_mut(), ui::DEFAULT_BACKGROUND, || {
+                    button.is_low()
+                })?
+            }
+        };
+    if skipped {
+        log::info!("Background GIF skipped, K0 pressed");
+    }
+
     let b = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?;
@@ -214,35 +233,223 @@ fn main() -> anyhow::Result<()> {
 
     #[cfg(feature = "extra_server")]
     {
-        chat_ui.set_state("Initializing...".to_string());
-        chat_ui.set_text("Loading Server URL...".to_string());
+        if !safe_mode {
+            chat_ui.set_state("Initializing...".to_string());
+            chat_ui.set_text("Loading Server URL...".to_string());
+
+            chat_ui.render_to_target(framebuffer.as_mut())?;
+            framebuffer.flush()?;
+
+            while let Some(event) = evt_rx.blocking_recv() {
+                if let app::Event::ServerUrl(url) = event {
+                    log::info!("Received ServerUrl event: {}", url);
+                    if !url.is_empty() {
+                        setting.config.server_url = url;
+                    }
+                    break;
+                }
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            chat_ui.set_text(format!(
+                "Server URL: {}\nContinuing...",
+                setting.config.server_url
+            ));
+            chat_ui.render_to_target(framebuffer.as_mut())?;
+            framebuffer.flush()?;
+            std::thread::sleep(std::time::Duration::from_millis(2000));
+        }
+    }
 
+    if safe_mode {
+        let mut mac = [0u8; 6];
+        unsafe {
+            esp_idf_svc::sys::esp_read_mac(
+                mac.as_mut_ptr(),
+                esp_idf_svc::sys::esp_mac_type_t_ESP_MAC_WIFI_STA,
+            );
+        }
+        let free_heap = unsafe { esp_idf_svc::sys::esp_get_free_heap_size() };
+
+        chat_ui.set_state("Safe mode".to_string());
+        chat_ui.set_text(format!(
+            "Firmware {}\nID {:02x}{:02x}{:02x}{:02x}{:02x}{:02x}\nSSID {}\nFree heap {}KB\nPress K0 to open settings",
+            env!("CARGO_PKG_VERSION"),
+            mac[0],
+            mac[1],
+            mac[2],
+            mac[3],
+            mac[4],
+            mac[5],
+            setting.config.ssid,
+            free_heap / 1024
+        ));
         chat_ui.render_to_target(framebuffer.as_mut())?;
         framebuffer.flush()?;
+        b.block_on(button.wait_for_falling_edge()).unwrap();
+    }
 
-        while let Some(event) = evt_rx.blocking_recv() {
-            if let app::Event::ServerUrl(url) = event {
-                log::info!("Received ServerUrl event: {}", url);
-                if !url.is_empty() {
-                    setting.server_url = url;
-                }
-                break;
+    if self_test {
+        let mut checks = Vec::new();
+        let show = |chat_ui: &mut boards::ui::ChatUI<6>,
+                    framebuffer: &mut boards::ui::DisplayBuffer,
+                    checks: &[self_test::Check],
+                    state: &str|
+         -> anyhow::Result<()> {
+            chat_ui.set_state(state.to_string());
+            chat_ui.set_text(self_test::format_report(checks));
+            chat_ui.render_to_target(framebuffer)?;
+            framebuffer.flush()
+        };
+        show(&mut chat_ui, framebuffer.as_mut(), &checks, "Self-test...")?;
+
+        let display = framebuffer.flush().and_then(|_| {
+            if boards::display_ready() {
+                Ok(String::new())
+            } else {
+                Err(anyhow::anyhow!("panel init failed"))
+            }
+        });
+        checks.push(self_test::Check::new("Display", display));
+        show(&mut chat_ui, framebuffer.as_mut(), &checks, "Self-test...")?;
+
+        #[cfg(feature = "mfrc522")]
+        {
+            // the I2C task reads the version in the background
+            let start = std::time::Instant::now();
+            while boards::mfrc522_version().is_none()
+                && start.elapsed() < std::time::Duration::from_secs(3)
+            {
+                std::thread::sleep(std::time::Duration::from_millis(100));
             }
+            let nfc = boards::mfrc522_version()
+                .map(|v| format!("{:?}", v))
+                .ok_or_else(|| anyhow::anyhow!("no response"));
+            checks.push(self_test::Check::new("MFRC522", nfc));
+            show(&mut chat_ui, framebuffer.as_mut(), &checks, "Self-test...")?;
         }
 
-        std::thread::sleep(std::time::Duration::from_millis(500));
-        chat_ui.set_text(format!("Server URL: {}\nContinuing...", setting.server_url));
+        let wifi = network::scan(peripherals.modem, sysloop.clone())
+            .and_then(|found| self_test::wifi_check(&setting.config.ssid, &found));
+        checks.push(self_test::Check::new("WiFi", wifi));
+        show(&mut chat_ui, framebuffer.as_mut(), &checks, "Self-test...")?;
+
+        crate::start_audio_workers!(peripherals, rx1, evt_tx.clone(), &b);
+
+        let played = Arc::new(tokio::sync::Notify::new());
+        let speaker = tx1
+            .send(audio::AudioEvent::Hello(played.clone()))
+            .map_err(|_| anyhow::anyhow!("player not running"))
+            .and_then(|_| {
+                b.block_on(tokio::time::timeout(
+                    std::time::Duration::from_secs(5),
+                    played.notified(),
+                ))
+                .map_err(|_| anyhow::anyhow!("timeout"))
+            })
+            .map(|_| String::new());
+        checks.push(self_test::Check::new("Speaker", speaker));
+        show(&mut chat_ui, framebuffer.as_mut(), &checks, "Self-test...")?;
+
+        // stream mic audio regardless of VAD for a moment
+        audio::VAD_ACTIVE.store(true, std::sync::atomic::Ordering::Relaxed);
+        let mut samples = Vec::new();
+        b.block_on(async {
+            let _ = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+                while let Some(evt) = evt_rx.recv().await {
+                    if let app::Event::MicAudioChunk(data) = evt {
+                        samples.extend_from_slice(&data);
+                    }
+                }
+            })
+            .await;
+        });
+        audio::VAD_ACTIVE.store(false, std::sync::atomic::Ordering::Relaxed);
+        checks.push(self_test::Check::new("Mic", self_test::mic_check(&samples)));
+
+        let failed = checks.iter().filter(|c| c.result.is_err()).count();
+        log::info!("Self-test done, {} of {} failed", failed, checks.len());
+        show(
+            &mut chat_ui,
+            framebuffer.as_mut(),
+            &checks,
+            &format!("Self-test: {} failed, K0 restarts", failed),
+        )?;
+
+        b.block_on(button.wait_for_falling_edge()).unwrap();
+        unsafe { esp_idf_svc::sys::esp_restart() }
+    }
+
+    // Needs no WiFi or server, and K0 restarts into normal operation.
+    if echo_test {
+        crate::start_audio_workers!(peripherals, rx1, evt_tx.clone(), &b);
+
+        chat_ui.set_text("Speak, then listen for the echo.\nK0 returns to normal.".to_string());
+        b.block_on(async {
+            tokio::select! {
+                r = audio::echo_test(&tx1, &mut evt_rx, |state| {
+                    chat_ui.set_state(state.to_string());
+                    let _ = chat_ui.render_to_target(framebuffer.as_mut());
+                    let _ = framebuffer.flush();
+                }) => {
+                    if let Err(e) = r {
+                        log::error!("Echo test stopped: {:?}", e);
+                    }
+                }
+                _ = button.wait_for_falling_edge() => {}
+            }
+            // released, so the reboot doesn't see K0 held
+            let _ = button.wait_for_high().await;
+        });
+
+        log::info!("Leaving the echo test");
+        unsafe { esp_idf_svc::sys::esp_restart() }
+    }
+
+    // K0 held at boot opens the settings; keep holding for FACTORY_RESET_HOLD to
+    // wipe the config and GIFs instead and reboot into provisioning.
+    let k0_held = !safe_mode && button.is_low();
+    if k0_held {
+        chat_ui.set_state("K0 held".to_string());
+        chat_ui.set_text(format!(
+            "Release K0 to open settings\nKeep holding {}s to factory reset",
+            FACTORY_RESET_HOLD.as_secs()
+        ));
         chat_ui.render_to_target(framebuffer.as_mut())?;
         framebuffer.flush()?;
-        std::thread::sleep(std::time::Duration::from_millis(2000));
+
+        let held_since = std::time::Instant::now();
+        while button.is_low() && held_since.elapsed() < FACTORY_RESET_HOLD {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        if button.is_low() {
+            log::warn!("K0 held for {:?}, factory reset", FACTORY_RESET_HOLD);
+            chat_ui.set_state("Factory reset".to_string());
+            chat_ui.set_text("Clearing settings and restarting...".to_string());
+            chat_ui.render_to_target(framebuffer.as_mut())?;
+            framebuffer.flush()?;
+
+            if let Err(e) = config::DeviceConfig::erase(&mut nvs) {
+                log::error!("Failed to erase config: {:?}", e);
+            }
+            for key in FACTORY_RESET_KEYS {
+                if let Err(e) = nvs.remove(key) {
+                    log::error!("Failed to remove {}: {:?}", key, e);
+                }
+            }
+
+            std::thread::sleep(std::time::Duration::from_secs(2));
+            unsafe { esp_idf_svc::sys::esp_restart() }
+        }
     }
 
-    let need_init = button.is_low() || setting.need_init();
+    let need_init = safe_mode || k0_held || setting.need_init();
 
     if need_init {
         // let mut config_ui = ui::new_config_ui(start_ui, "https://echokit.dev/setup/")?;
 
-        let esp_wifi = esp_idf_svc::wifi::EspWifi::new(peripherals.modem, sysloop, None)?;
+        let esp_wifi = esp_idf_svc::wifi::EspWifi::new(peripherals.modem, sysloop.clone(), None)?;
         let mac = esp_wifi.sta_netif().get_mac()?;
         let dev_id = format!(
             "{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
@@ -253,7 +460,7 @@ fn main() -> anyhow::Result<()> {
         setting.avatar_gif.0.clear();
         let setting = Arc::new(Mutex::new((setting, nvs)));
 
-        bt::bt(&dev_id, setting.clone(), evt_tx).unwrap();
+        bt::bt(&dev_id, setting.clone(), evt_tx, esp_wifi, sysloop).unwrap();
         log_heap();
 
         let version = env!("CARGO_PKG_VERSION");
@@ -264,8 +471,9 @@ fn main() -> anyhow::Result<()> {
         config_ui.draw(framebuffer.as_mut())?;
         framebuffer.flush()?;
 
+        // safe mode doesn't touch the audio hardware
         #[cfg(feature = "boards")]
-        {
+        if !safe_mode {
             let dout = peripherals.pins.gpio7;
             let bclk = peripherals.pins.gpio15;
             let lrclk = peripherals.pins.gpio16;
@@ -303,19 +511,27 @@ fn main() -> anyhow::Result<()> {
                 let mut new_gif = Vec::new();
                 std::mem::swap(&mut setting.0.background_gif.0, &mut new_gif);
 
-                crate::ui::display_gif(framebuffer.as_mut(), &new_gif).unwrap();
-                log::info!("Background GIF set from NVS");
+                let r = crate::ui::validate_gif(&new_gif, framebuffer.bounding_box().size)
+                    .and_then(|_| crate::ui::display_gif(framebuffer.as_mut(), &new_gif));
+                if let Err(e) = r {
+                    log::error!("Invalid background GIF, not saved: {:?}", e);
+                    config_ui.set_info(format!("Invalid background GIF: {}", e));
+                    config_ui.draw(framebuffer.as_mut())?;
+                    framebuffer.flush()?;
+                } else {
+                    log::info!("Background GIF set from NVS");
 
-                config_ui.set_info("Background GIF set OK".to_string());
-                config_ui.draw(framebuffer.as_mut())?;
-                framebuffer.flush()?;
+                    config_ui.set_info("Background GIF set OK".to_string());
+                    config_ui.draw(framebuffer.as_mut())?;
+                    framebuffer.flush()?;
 
-                setting
-                    .1
-                    .set_blob("background_gif", &new_gif)
-                    .map_err(|e| log::error!("Failed to save background GIF to NVS: {:?}", e))
-                    .unwrap();
-                log::info!("Background GIF saved to NVS");
+                    setting
+                        .1
+                        .set_blob("background_gif", &new_gif)
+                        .map_err(|e| log::error!("Failed to save background GIF to NVS: {:?}", e))
+                        .unwrap();
+                    log::info!("Background GIF saved to NVS");
+                }
             }
 
             if setting.0.avatar_gif.1 {
@@ -337,20 +553,50 @@ fn main() -> anyhow::Result<()> {
                     let mut new_gif = Vec::new();
                     std::mem::swap(&mut setting.0.avatar_gif.0, &mut new_gif);
 
-                    crate::ui::display_gif(framebuffer.as_mut(), &new_gif).unwrap();
-                    log::info!("Avatar GIF set from NVS");
-
-                    config_ui.set_info("Avatar GIF set OK".to_string());
-                    config_ui.draw(framebuffer.as_mut())?;
-                    framebuffer.flush()?;
+                    let avatar = crate::ui::avatar_size(framebuffer.bounding_box().size);
+                    let r = crate::ui::validate_gif(&new_gif, Size::new(avatar, avatar))
+                        .and_then(|_| crate::ui::display_gif(framebuffer.as_mut(), &new_gif));
+                    if let Err(e) = r {
+                        log::error!("Invalid avatar GIF, not saved: {:?}", e);
+                        config_ui.set_info(format!("Invalid avatar GIF: {}", e));
+                        config_ui.draw(framebuffer.as_mut())?;
+                        framebuffer.flush()?;
+                    } else {
+                        log::info!("Avatar GIF set from NVS");
+
+                        config_ui.set_info("Avatar GIF set OK".to_string());
+                        config_ui.draw(framebuffer.as_mut())?;
+                        framebuffer.flush()?;
+
+                        setting
+                            .1
+                            .set_blob("avatar_gif", &new_gif)
+                            .map_err(|e| log::error!("Failed to save avatar GIF to NVS: {:?}", e))
+                            .unwrap();
+                        log::info!("Avatar GIF saved to NVS");
+                    }
+                }
+            }
 
-                    setting
-                        .1
-                        .set_blob("avatar_gif", &new_gif)
-                        .map_err(|e| log::error!("Failed to save avatar GIF to NVS: {:?}", e))
-                        .unwrap();
-                    log::info!("Avatar GIF saved to NVS");
+            if setting.0.wake_sound.1 {
+                let new_sound = std::mem::take(&mut setting.0.wake_sound.0);
+                if new_sound.is_empty() {
+                    config_ui.set_info("Wake sound reset to default".to_string());
+                    if let Err(e) = setting.1.remove("wake_sound") {
+                        log::error!("Failed to clear wake sound from NVS: {:?}", e);
+                    }
+                } else if let Err(e) = audio::wav_to_16k_mono(&new_sound) {
+                    log::error!("Invalid wake sound, not saved: {:?}", e);
+                    config_ui.set_info("Invalid wake sound".to_string());
+                } else if let Err(e) = setting.1.set_blob("wake_sound", &new_sound) {
+                    log::error!("Failed to save wake sound to NVS: {:?}", e);
+                    config_ui.set_info("Failed to save wake sound".to_string());
+                } else {
+                    log::info!("Wake sound saved to NVS");
+                    config_ui.set_info("Wake sound set OK".to_string());
                 }
+                config_ui.draw(framebuffer.as_mut())?;
+                framebuffer.flush()?;
             }
         }
 
@@ -358,22 +604,87 @@ fn main() -> anyhow::Result<()> {
     }
 
     unsafe {
-        audio::AFE_LINEAR_GAIN = setting.afe_linear_gain;
-        audio::AGC_TARGET_LEVEL_DBFS = setting.agc_target_level_dbfs;
-        audio::AGC_COMPRESSION_GAIN_DB = setting.agc_compression_gain_db;
+        audio::AFE_LINEAR_GAIN = setting.config.afe_linear_gain;
+        audio::AGC_TARGET_LEVEL_DBFS = setting.config.agc_target_level_dbfs;
+        audio::AGC_COMPRESSION_GAIN_DB = setting.config.agc_compression_gain_db;
     }
+    *audio::AFE_TUNING.lock().unwrap() = audio::AfeTuning {
+        vad_mode: setting.config.vad_mode.min(4),
+        vad_min_noise_ms: setting.config.vad_min_noise_ms,
+        wakenet_model: (!setting.config.wakenet_model.is_empty())
+            .then(|| setting.config.wakenet_model.clone()),
+    };
+
+    #[cfg(feature = "passthrough")]
+    {
+        chat_ui.set_state("Passthrough?".to_string());
+        chat_ui.set_text("Press K0 within 2s to loop the mic to the speaker".to_string());
+        chat_ui.render_to_target(framebuffer.as_mut())?;
+        framebuffer.flush()?;
 
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(2);
+        let mut pressed = false;
+        while std::time::Instant::now() < deadline {
+            if button.is_low() {
+                pressed = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        if pressed {
+            log::info!("Entering passthrough mode");
+            audio::PASSTHROUGH.store(true, std::sync::atomic::Ordering::Relaxed);
+
+            chat_ui.set_state("Passthrough".to_string());
+            chat_ui.set_text("Mic is looped to the speaker.\nRestart to exit.".to_string());
+            chat_ui.render_to_target(framebuffer.as_mut())?;
+            framebuffer.flush()?;
+
+            crate::start_audio_workers!(peripherals, rx1, evt_tx.clone(), &b);
+
+            loop {
+                std::thread::sleep(std::time::Duration::from_secs(60));
+            }
+        }
+    }
+
+    chat_ui.set_status_icon(Some(ui::StatusIcon::Connecting));
     chat_ui.set_state("Connecting to wifi...".to_string());
     chat_ui.render_to_target(framebuffer.as_mut())?;
     framebuffer.flush()?;
 
+    let static_ip = network::StaticIp::parse(
+        &setting.config.static_ip,
+        &setting.config.gateway,
+        &setting.config.netmask,
+    )
+    .unwrap_or_else(|e| {
+        log::error!("Ignoring static IP config, using DHCP: {:?}", e);
+        None
+    });
     let _wifi = network::wifi(
-        &setting.ssid,
-        &setting.pass,
+        &setting.config.wifi_networks(),
+        static_ip,
         peripherals.modem,
         sysloop.clone(),
+        |ssid, attempt| {
+            if attempt > 1 {
+                chat_ui.set_state(format!(
+                    "Connecting to {} ({}/{})...",
+                    ssid,
+                    attempt,
+                    network::WIFI_CONNECT_ATTEMPTS
+                ));
+            } else {
+                chat_ui.set_state(format!("Connecting to {}...", ssid));
+            }
+            let _ = chat_ui.render_to_target(framebuffer.as_mut());
+            let _ = framebuffer.flush();
+        },
     );
     if _wifi.is_err() {
+        chat_ui.set_status_icon(Some(ui::StatusIcon::Error));
         chat_ui.set_state("Failed to connect to wifi".to_string());
         chat_ui.set_text("Press K0 to open settings".to_string());
         chat_ui.render_to_target(framebuffer.as_mut())?;
@@ -384,30 +695,144 @@ fn main() -> anyhow::Result<()> {
         unsafe { esp_idf_svc::sys::esp_restart() }
     }
 
-    let wifi = _wifi.unwrap();
+    let (wifi, wifi_ssid) = _wifi.unwrap();
+    if let Err(e) = network::start_reconnect_watch() {
+        log::error!("Failed to start wifi reconnect watch: {:?}", e);
+    }
     log_heap();
 
+    let ntp_servers: Vec<&str> = setting
+        .config
+        .ntp_servers
+        .iter()
+        .map(|s| s.as_str())
+        .collect();
+    let _sntp = sntp::sync_time_with_servers(&ntp_servers)
+        .map_err(|e| log::error!("Failed to start SNTP: {:?}", e))
+        .ok();
+    if _sntp.is_some() {
+        // only logs the outcome; boot doesn't wait for the clock
+        let _ = std::thread::Builder::new()
+            .name("sntp_wait".to_string())
+            .stack_size(4 * 1024)
+            .spawn(|| {
+                if let Err(e) = sntp::wait_for_sync(sntp::SYNC_MAX_WAIT) {
+                    log::warn!("{:?}, continuing without synced time", e);
+                }
+            });
+    }
+
     let mac = wifi.sta_netif().get_mac().unwrap();
     let dev_id = format!(
         "{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
         mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
     );
 
+    let mut _mdns = network::start_mdns(
+        &network::mdns_hostname(&mac),
+        &format!("EchoKit-{}", dev_id),
+    )
+    .map_err(|e| log::error!("Failed to start mDNS: {:?}", e))
+    .ok();
+
+    // With `preconnect_server` the handshake is spawned as soon as WiFi is up and
+    // the audio workers are started before waiting on it, so codec and AFE bring-up
+    // (on their own threads) overlap with the connect. Either way the server is
+    // connected exactly once, and a failure takes the same error path below.
+    ws::MAX_PAYLOAD_LEN.store(
+        setting.config.ws_max_payload_kb as usize * 1024,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    let ping_interval = (setting.config.ws_ping_interval_secs > 0)
+        .then(|| std::time::Duration::from_secs(setting.config.ws_ping_interval_secs as u64));
+    let connect_timeout =
+        std::time::Duration::from_secs(setting.config.server_connect_timeout_secs.max(1) as u64);
+    let preconnect = setting.config.preconnect_server.then(|| {
+        b.spawn(ws::Server::new(
+            dev_id.clone(),
+            setting.config.server_url.clone(),
+            setting.config.role.clone(),
+            ping_interval,
+            connect_timeout,
+        ))
+    });
+    let mut start_audio = Some(|| -> anyhow::Result<()> {
+        crate::start_audio_workers!(peripherals, rx1, evt_tx.clone(), &b);
+        Ok(())
+    });
+
+    if setting.config.analytics_enabled {
+        let interval =
+            std::time::Duration::from_secs(setting.config.analytics_interval_secs.max(60) as u64);
+        if let Err(e) = analytics::start(dev_id.clone(), &setting.config.server_url, interval) {
+            log::error!("Failed to start analytics: {:?}", e);
+        }
+    }
+
+    if setting.config.telemetry_interval_secs > 0 {
+        let interval =
+            std::time::Duration::from_secs(setting.config.telemetry_interval_secs.max(60) as u64);
+        if let Err(e) = telemetry::start(dev_id.clone(), &setting.config.server_url, interval) {
+            log::error!("Failed to start telemetry: {:?}", e);
+        }
+    }
+
+    if setting.config.heartbeat_interval_secs > 0 {
+        let interval =
+            std::time::Duration::from_secs(setting.config.heartbeat_interval_secs.max(10) as u64);
+        if let Err(e) =
+            telemetry::start_heartbeat(dev_id.clone(), &setting.config.server_url, interval)
+        {
+            log::error!("Failed to start heartbeat: {:?}", e);
+        }
+    }
+
+    chat_ui.set_status_icon(Some(ui::StatusIcon::Connecting));
     chat_ui.set_state("Connecting to server...".to_string());
-    chat_ui.set_text("".to_string());
+    chat_ui.set_text(format!("WiFi: {}", wifi_ssid));
     chat_ui.render_to_target(framebuffer.as_mut())?;
     framebuffer.flush()?;
 
     log_heap();
 
-    chat_ui.set_state("Failed to connect to server".to_string());
-    chat_ui.set_text(format!(
-        "Please check your server URL: {}\nPress K0 to open settings",
-        setting.server_url
-    ));
-    let server = b.block_on(ws::Server::new(dev_id, setting.server_url));
-    if server.is_err() {
-        log::info!("Failed to connect to server: {:?}", server.err());
+    let server = match preconnect {
+        Some(connecting) => {
+            if let Some(start_audio) = start_audio.take() {
+                start_audio()?;
+            }
+            b.block_on(connecting)
+                .unwrap_or_else(|e| Err(anyhow::anyhow!("Connect task failed: {}", e)))
+        }
+        None => b.block_on(ws::Server::new(
+            dev_id.clone(),
+            setting.config.server_url.clone(),
+            setting.config.role.clone(),
+            ping_interval,
+            connect_timeout,
+        )),
+    };
+    if let Err(e) = &server {
+        log::info!("Failed to connect to server: {:?}", e);
+        let (state, hint) = match ws::ConnectFailure::of(e) {
+            ws::ConnectFailure::TimedOut => (
+                "Server not responding",
+                "Check the server is running and reachable from this WiFi",
+            ),
+            ws::ConnectFailure::Refused => (
+                "Server refused the connection",
+                "Check the port in the server URL",
+            ),
+            ws::ConnectFailure::Other => (
+                "Failed to connect to server",
+                "Please check your server URL",
+            ),
+        };
+        chat_ui.set_state(state.to_string());
+        chat_ui.set_text(format!(
+            "{}: {}\nPress K0 to open settings",
+            hint, setting.config.server_url
+        ));
+        chat_ui.set_status_icon(Some(ui::StatusIcon::Error));
         chat_ui.render_to_target(framebuffer.as_mut())?;
         framebuffer.flush()?;
         b.block_on(button.wait_for_falling_edge()).unwrap();
@@ -416,12 +841,152 @@ fn main() -> anyhow::Result<()> {
     }
 
     let server = server.unwrap();
+    chat_ui.set_status_icon(Some(ui::StatusIcon::Connected));
+
+    // off the main thread, so a slow server doesn't hold up the chat screen
+    let server_url = setting.config.server_url.clone();
+    let report = std::thread::Builder::new()
+        .name("fw_report".to_string())
+        .stack_size(8 * 1024)
+        .spawn(move || {
+            if let Err(e) = ota::report_firmware_version(&server_url, &dev_id, &mac) {
+                log::warn!("Failed to report the firmware version: {:?}", e);
+            }
+        });
+    if let Err(e) = report {
+        log::error!("Failed to start the version report: {:?}", e);
+    }
 
-    crate::start_audio_workers!(peripherals, rx1, evt_tx.clone(), &b);
+    if let Some(start_audio) = start_audio.take() {
+        start_audio()?;
+    }
+
+    // a stored wake sound replaces the built-in beep, falling back to it if invalid
+    let wake_sound = std::mem::take(&mut setting.wake_sound.0);
+    let custom_hello = !wake_sound.is_empty()
+        && match audio::wav_to_16k_mono(&wake_sound) {
+            Ok(pcm) => tx1
+                .send(audio::AudioEvent::SetHello(protocol::encode_pcm_le(&pcm)))
+                .is_ok(),
+            Err(e) => {
+                log::error!("Invalid wake sound, using the default: {:?}", e);
+                false
+            }
+        };
 
-    let ws_task = app::main_work(server, tx1, evt_rx, &mut framebuffer, &mut chat_ui);
+    // pausing the mic would leave nothing listening for the wake word
+    let power_save_after = if setting.config.power_save_after_secs == 0 {
+        None
+    } else if cfg!(feature = "wakeword") {
+        log::warn!("Power save is not available with the wake word");
+        None
+    } else {
+        Some(std::time::Duration::from_secs(
+            setting.config.power_save_after_secs.max(10) as u64,
+        ))
+    };
+    let app_config = app::AppConfig {
+        keep_connection_on_idle: setting.config.keep_connection_on_idle,
+        max_reconnect_failures: setting.config.max_reconnect_failures,
+        reconnect_failure_window: std::time::Duration::from_secs(
+            setting.config.reconnect_failure_window_secs as u64,
+        ),
+        response_timeout: std::time::Duration::from_secs(
+            setting.config.response_timeout_secs.max(1) as u64,
+        ),
+        min_submit_secs: setting.config.min_submit_ms as f32 / 1000.0,
+        submit_chunk_secs: setting.config.submit_chunk_ms as f32 / 1000.0,
+        min_submit_amplitude: setting.config.min_submit_amplitude,
+        min_interrupt_secs: setting.config.min_interrupt_ms as f32 / 1000.0,
+        min_interrupt_amplitude: setting.config.min_interrupt_amplitude,
+        utc_offset_minutes: setting.config.utc_offset_minutes,
+        quiet_hours: setting.config.quiet_hours(),
+        backlight_level: setting.config.backlight_level,
+        backlight_dim_level: setting.config.backlight_dim_level,
+        backlight_dim_after: (setting.config.backlight_dim_after_secs > 0).then(|| {
+            std::time::Duration::from_secs(setting.config.backlight_dim_after_secs as u64)
+        }),
+        push_to_talk: setting.config.push_to_talk,
+        custom_hello,
+        forward_buttons: setting.config.forward_buttons.clone(),
+        power_save_after,
+    };
+
+    let idle_gif = if setting.config.idle_gif_loop {
+        ui::GifLoop::new(std::mem::take(&mut setting.background_gif.0))
+            .map_err(|e| log::warn!("Idle GIF disabled: {:?}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    // Preferences changed at runtime (e.g. voice interrupt) are written back in
+    // the background, a few seconds after the last change.
+    let (prefs_tx, prefs_rx) = tokio::sync::watch::channel(setting.config.clone());
+    let _config_server = if setting.config.config_server {
+        let server = config_server::start(prefs_tx.clone())
+            .map_err(|e| log::error!("Failed to start config server: {:?}", e))
+            .ok();
+        if let (Some(_), Some(mdns)) = (&server, &mut _mdns) {
+            if let Err(e) = mdns.add_service(
+                None,
+                "_http",
+                "_tcp",
+                config_server::PORT,
+                &[("path", "/config")],
+            ) {
+                log::warn!("Failed to advertise config server: {:?}", e);
+            }
+        }
+        server
+    } else {
+        None
+    };
+    b.spawn(config::DeviceConfig::save_on_change(
+        prefs_rx,
+        nvs,
+        config::SAVE_DEBOUNCE,
+    ));
 
+    let ws_task = app::main_work(
+        app_config,
+        server,
+        tx1,
+        evt_rx,
+        &mut framebuffer,
+        &mut chat_ui,
+        prefs_tx,
+        idle_gif,
+    );
+
+    let double_press_window =
+        std::time::Duration::from_millis(setting.config.k0_double_press_ms as u64);
+    let push_to_talk = setting.config.push_to_talk;
     b.spawn(async move {
+        // push-to-talk only reports press and release, there are no tap/hold events
+        if push_to_talk {
+            loop {
+                let _ = button.wait_for_falling_edge().await;
+                if evt_tx
+                    .send(app::Event::Event(app::Event::K0_DOWN))
+                    .await
+                    .is_err()
+                {
+                    log::error!("Failed to send K0 event");
+                    return;
+                }
+                let _ = button.wait_for_rising_edge().await;
+                if evt_tx
+                    .send(app::Event::Event(app::Event::K0_UP))
+                    .await
+                    .is_err()
+                {
+                    log::error!("Failed to send K0 event");
+                    return;
+                }
+            }
+        }
+
         loop {
             let _ = button.wait_for_falling_edge().await;
             log::info!("Button k0 pressed {:?}", button.get_level());
@@ -433,11 +998,22 @@ fn main() -> anyhow::Result<()> {
             .await;
             match r {
                 Ok(_) => {
-                    if evt_tx
-                        .send(app::Event::Event(app::Event::K0))
-                        .await
-                        .is_err()
+                    // A second press within the window makes it a double press;
+                    // a window of 0 keeps single presses immediate.
+                    let mut evt = app::Event::K0;
+                    if !double_press_window.is_zero()
+                        && tokio::time::timeout(double_press_window, button.wait_for_falling_edge())
+                            .await
+                            .is_ok()
                     {
+                        let _ = tokio::time::timeout(
+                            std::time::Duration::from_secs(1),
+                            button.wait_for_rising_edge(),
+                        )
+                        .await;
+                        evt = app::Event::K0_DOUBLE;
+                    }
+                    if evt_tx.send(app::Event::Event(evt)).await.is_err() {
                         log::error!("Failed to send K0 event");
                         break;
                     }
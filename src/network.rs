@@ -2,61 +2,425 @@ use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
     hal::peripheral,
     http::{client::EspHttpConnection, Method},
+    ipv4::{self, Ipv4Addr},
+    mdns::EspMdns,
+    netif::{EspNetif, NetifConfiguration, NetifStack},
     wifi::{AuthMethod, BlockingWifi, EspWifi},
 };
 use log::info;
 
+/// A fixed IPv4 configuration used instead of DHCP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticIp {
+    pub ip: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    /// Netmask as a prefix length, e.g. 24 for 255.255.255.0.
+    pub prefix: u8,
+}
+
+impl StaticIp {
+    /// Parses the dotted-quad strings from `DeviceConfig`. Returns `Ok(None)`
+    /// when all three are empty, i.e. DHCP.
+    pub fn parse(ip: &str, gateway: &str, netmask: &str) -> anyhow::Result<Option<Self>> {
+        if ip.is_empty() && gateway.is_empty() && netmask.is_empty() {
+            return Ok(None);
+        }
+
+        let parse = |name: &str, value: &str| -> anyhow::Result<Ipv4Addr> {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid {}: {:?}", name, value))
+        };
+
+        let ip = parse("IP address", ip)?;
+        let gateway = parse("gateway", gateway)?;
+        let mask = u32::from(parse("netmask", netmask)?);
+        // a netmask is a run of ones followed by zeros only
+        if mask.leading_ones() + mask.trailing_zeros() != 32 {
+            anyhow::bail!("Invalid netmask: {:?}", netmask);
+        }
+
+        Ok(Some(Self {
+            ip,
+            gateway,
+            prefix: mask.leading_ones() as u8,
+        }))
+    }
+
+    fn netif(&self) -> anyhow::Result<EspNetif> {
+        let ip_configuration =
+            ipv4::Configuration::Client(ipv4::ClientConfiguration::Fixed(ipv4::ClientSettings {
+                ip: self.ip,
+                subnet: ipv4::Subnet {
+                    gateway: self.gateway,
+                    mask: ipv4::Mask(self.prefix),
+                },
+                // most home and office routers also serve DNS
+                dns: Some(self.gateway),
+                secondary_dns: None,
+            }));
+
+        Ok(EspNetif::new_with_conf(&NetifConfiguration {
+            ip_configuration: Some(ip_configuration),
+            ..NetifConfiguration::wifi_default_client()
+        })?)
+    }
+}
+
+#[test]
+fn test_static_ip_parse() {
+    assert_eq!(StaticIp::parse("", "", "").unwrap(), None);
+    assert_eq!(
+        StaticIp::parse("192.168.1.50", "192.168.1.1", "255.255.255.0").unwrap(),
+        Some(StaticIp {
+            ip: Ipv4Addr::new(192, 168, 1, 50),
+            gateway: Ipv4Addr::new(192, 168, 1, 1),
+            prefix: 24,
+        })
+    );
+    assert_eq!(
+        StaticIp::parse("10.0.0.2", "10.0.0.1", "255.255.240.0")
+            .unwrap()
+            .map(|s| s.prefix),
+        Some(20)
+    );
+
+    assert!(StaticIp::parse("192.168.1.50", "", "").is_err());
+    assert!(StaticIp::parse("192.168.1", "192.168.1.1", "255.255.255.0").is_err());
+    assert!(StaticIp::parse("192.168.1.256", "192.168.1.1", "255.255.255.0").is_err());
+    assert!(StaticIp::parse("192.168.1.50", "192.168.1.1", "255.0.255.0").is_err());
+}
+
+/// How many times `wifi` tries to connect before giving up.
+pub const WIFI_CONNECT_ATTEMPTS: u32 = 4;
+
+/// How long to wait after failed connect attempt `attempt` (1-based): 1s,
+/// doubling up to 16s.
+fn connect_backoff(attempt: u32) -> std::time::Duration {
+    std::time::Duration::from_secs(1 << attempt.saturating_sub(1).min(4))
+}
+
+#[test]
+fn test_connect_backoff() {
+    let secs = |attempt| connect_backoff(attempt).as_secs();
+    assert_eq!(secs(0), 1);
+    assert_eq!(secs(1), 1);
+    assert_eq!(secs(2), 2);
+    assert_eq!(secs(3), 4);
+    assert_eq!(secs(5), 16);
+    assert_eq!(secs(100), 16);
+}
+
+/// Puts the `known` (SSID, password) pairs seen in `scanned` (SSID, RSSI)
+/// first, strongest first, followed by the rest in their configured order.
+pub fn order_networks<'a>(
+    known: &[(&'a str, &'a str)],
+    scanned: &[(String, i8)],
+) -> Vec<(&'a str, &'a str)> {
+    let rssi = |ssid: &str| {
+        scanned
+            .iter()
+            .filter(|(s, _)| s == ssid)
+            .map(|(_, rssi)| *rssi)
+            .max()
+    };
+    let mut networks = known.to_vec();
+    // `None` sorts before any RSSI, so reverse to get seen networks first
+    networks.sort_by_key(|(ssid, _)| std::cmp::Reverse(rssi(ssid)));
+    networks
+}
+
+#[test]
+fn test_order_networks() {
+    let known = [("home", "1"), ("office", "2"), ("cafe", "3")];
+    let scanned = |aps: &[(&str, i8)]| {
+        aps.iter()
+            .map(|(s, rssi)| (s.to_string(), *rssi))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(order_networks(&known, &[]), known);
+    assert_eq!(
+        order_networks(&known, &scanned(&[("office", -60), ("other", -30)])),
+        [("office", "2"), ("home", "1"), ("cafe", "3")]
+    );
+    assert_eq!(
+        order_networks(
+            &known,
+            &scanned(&[("home", -80), ("cafe", -50), ("home", -40)])
+        ),
+        [("home", "1"), ("cafe", "3"), ("office", "2")]
+    );
+}
+
+/// Connects to the first of `networks` (SSID, password) that comes up, using
+/// `static_ip` if given, and returns the SSID it connected to. With more than
+/// one network, a scan decides the order, see `order_networks`. If the link
+/// doesn't come up with the static address, retries with DHCP.
+///
+/// Each network gets up to `WIFI_CONNECT_ATTEMPTS` attempts with a growing
+/// pause in between, so a briefly unavailable AP doesn't fail the boot.
+/// `on_attempt` is called with the SSID and attempt number before each one.
 pub fn wifi(
-    ssid: &str,
-    pass: &str,
+    networks: &[(&str, &str)],
+    static_ip: Option<StaticIp>,
     modem: impl peripheral::Peripheral<P = esp_idf_svc::hal::modem::Modem> + 'static,
     sysloop: EspSystemEventLoop,
-) -> anyhow::Result<Box<EspWifi<'static>>> {
-    let mut auth_method = AuthMethod::WPA2Personal;
-    if ssid.is_empty() {
+    mut on_attempt: impl FnMut(&str, u32),
+) -> anyhow::Result<(Box<EspWifi<'static>>, String)> {
+    if networks.is_empty() {
         anyhow::bail!("Missing WiFi name")
     }
-    if pass.is_empty() {
-        auth_method = AuthMethod::None;
-        info!("Wifi password is empty");
-    }
     let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), None)?;
+    if let Some(static_ip) = &static_ip {
+        info!("Using static IP {}/{}", static_ip.ip, static_ip.prefix);
+        esp_wifi.swap_netif_sta(static_ip.netif()?)?;
+    }
 
     let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
+    wifi.set_configuration(&esp_idf_svc::wifi::Configuration::Client(Default::default()))?;
+    wifi.start()?;
+
+    let networks = if networks.len() > 1 {
+        match wifi.scan() {
+            Ok(aps) => {
+                let scanned = aps
+                    .iter()
+                    .map(|ap| (ap.ssid.to_string(), ap.signal_strength))
+                    .collect::<Vec<_>>();
+                order_networks(networks, &scanned)
+            }
+            Err(e) => {
+                log::warn!("Wifi scan failed: {:?}, trying networks in order", e);
+                networks.to_vec()
+            }
+        }
+    } else {
+        networks.to_vec()
+    };
 
-    wifi.set_configuration(&esp_idf_svc::wifi::Configuration::Client(
+    let mut connect_any =
+        |wifi: &mut BlockingWifi<&mut EspWifi<'static>>| -> anyhow::Result<String> {
+            let mut last_err = None;
+            for &(ssid, pass) in &networks {
+                match connect_to(wifi, ssid, pass, &mut on_attempt) {
+                    Ok(()) => return Ok(ssid.to_string()),
+                    Err(e) => {
+                        log::warn!("Could not connect to {}: {:?}", ssid, e);
+                        let _ = wifi.disconnect();
+                        last_err = Some(e);
+                    }
+                }
+            }
+            Err(last_err.unwrap_or_else(|| anyhow::anyhow!("Missing WiFi name")))
+        };
+
+    let ssid = match connect_any(&mut wifi) {
+        Ok(ssid) => ssid,
+        Err(e) => {
+            if static_ip.is_none() {
+                return Err(e);
+            }
+            log::warn!(
+                "Static IP failed to come up ({:?}), falling back to DHCP",
+                e
+            );
+            let _ = wifi.disconnect();
+            wifi.stop()?;
+            wifi.wifi_mut()
+                .swap_netif_sta(EspNetif::new(NetifStack::Sta)?)?;
+            wifi.start()?;
+            connect_any(&mut wifi)?
+        }
+    };
+
+    let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
+
+    info!("Wifi connected to {}, IP info: {:?}", ssid, ip_info);
+
+    Ok((Box::new(esp_wifi), ssid))
+}
+
+fn client_configuration(
+    ssid: &str,
+    pass: &str,
+) -> anyhow::Result<esp_idf_svc::wifi::Configuration> {
+    let auth_method = if pass.is_empty() {
+        info!("Wifi password for {} is empty", ssid);
+        AuthMethod::None
+    } else {
+        AuthMethod::WPA2Personal
+    };
+    Ok(esp_idf_svc::wifi::Configuration::Client(
         esp_idf_svc::wifi::ClientConfiguration {
             ssid: ssid
                 .try_into()
-                .expect("Could not parse the given SSID into WiFi config"),
+                .map_err(|_| anyhow::anyhow!("WiFi name too long: {}", ssid))?,
             password: pass
                 .try_into()
-                .expect("Could not parse the given password into WiFi config"),
+                .map_err(|_| anyhow::anyhow!("WiFi password too long for {}", ssid))?,
             auth_method,
             ..Default::default()
         },
-    ))?;
+    ))
+}
 
+/// Makes one attempt to connect to `ssid` on the (not yet started) `esp_wifi`
+/// and disconnects again, for checking credentials before they are saved.
+/// Nothing is stored; the WiFi is stopped afterwards.
+pub fn test_wifi(
+    esp_wifi: &mut EspWifi<'static>,
+    sysloop: EspSystemEventLoop,
+    ssid: &str,
+    pass: &str,
+) -> anyhow::Result<()> {
+    let mut wifi = BlockingWifi::wrap(esp_wifi, sysloop)?;
+    wifi.set_configuration(&client_configuration(ssid, pass)?)?;
     wifi.start()?;
 
-    info!("Connecting wifi...");
+    info!("Testing wifi {}...", ssid);
+    let r = wifi.connect().and_then(|_| wifi.wait_netif_up());
+    let _ = wifi.disconnect();
+    wifi.stop()?;
+    Ok(r?)
+}
 
-    wifi.connect()?;
+/// Configures `ssid` and makes up to `WIFI_CONNECT_ATTEMPTS` attempts to
+/// connect to it.
+fn connect_to(
+    wifi: &mut BlockingWifi<&mut EspWifi<'static>>,
+    ssid: &str,
+    pass: &str,
+    on_attempt: &mut impl FnMut(&str, u32),
+) -> anyhow::Result<()> {
+    wifi.set_configuration(&client_configuration(ssid, pass)?)?;
 
-    info!("Waiting for DHCP lease...");
+    info!("Connecting wifi {}...", ssid);
 
-    wifi.wait_netif_up()?;
+    let mut attempt = 1;
+    loop {
+        on_attempt(ssid, attempt);
+        let r = (|| -> anyhow::Result<()> {
+            wifi.connect()?;
+            info!("Waiting for network interface...");
+            wifi.wait_netif_up()?;
+            Ok(())
+        })();
+        match r {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < WIFI_CONNECT_ATTEMPTS => {
+                let backoff = connect_backoff(attempt);
+                log::warn!(
+                    "Wifi connect attempt {}/{} failed: {:?}, retrying in {:?}",
+                    attempt,
+                    WIFI_CONNECT_ATTEMPTS,
+                    e,
+                    backoff
+                );
+                let _ = wifi.disconnect();
+                std::thread::sleep(backoff);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-    let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
+/// RSSI of the connected AP in dBm, `None` while not connected.
+pub fn rssi() -> Option<i8> {
+    let mut ap_info = esp_idf_svc::sys::wifi_ap_record_t::default();
+    let e = unsafe { esp_idf_svc::sys::esp_wifi_sta_get_ap_info(&mut ap_info) };
+    (e == esp_idf_svc::sys::ESP_OK).then_some(ap_info.rssi)
+}
+
+/// mDNS hostname for the device, `echokit-` and the last three bytes of its
+/// MAC in hex, so several devices on one network get distinct names.
+pub fn mdns_hostname(mac: &[u8; 6]) -> String {
+    format!("echokit-{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5])
+}
+
+#[test]
+fn test_mdns_hostname() {
+    assert_eq!(
+        mdns_hostname(&[0x24, 0x0a, 0xc4, 0x1b, 0x2c, 0xf0]),
+        "echokit-1b2cf0"
+    );
+}
+
+/// Starts answering mDNS queries for `{hostname}.local` on the station
+/// interface. Keep the handle alive for as long as the name should resolve.
+pub fn start_mdns(hostname: &str, instance_name: &str) -> anyhow::Result<EspMdns> {
+    let mut mdns = EspMdns::take()?;
+    mdns.set_hostname(hostname)?;
+    mdns.set_instance_name(instance_name)?;
+    info!("mDNS started as {}.local", hostname);
+    Ok(mdns)
+}
+
+/// Scans for access points without connecting, returning their SSIDs.
+pub fn scan(
+    modem: impl peripheral::Peripheral<P = esp_idf_svc::hal::modem::Modem> + 'static,
+    sysloop: EspSystemEventLoop,
+) -> anyhow::Result<Vec<String>> {
+    let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), None)?;
+    let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
+    wifi.set_configuration(&esp_idf_svc::wifi::Configuration::Client(Default::default()))?;
+    wifi.start()?;
+
+    let aps = wifi.scan()?;
+    info!("Found {} access points", aps.len());
+    Ok(aps.into_iter().map(|ap| ap.ssid.to_string()).collect())
+}
+
+/// How often `start_reconnect_watch` checks the station link.
+const RECONNECT_WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Starts a thread that reconnects the station when the link drops after
+/// `wifi` succeeded, backing off between attempts like `wifi` does. The
+/// websocket is reconnected by `app::main_work` once the link is back, so an
+/// AP outage doesn't need a reboot.
+pub fn start_reconnect_watch() -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .name("wifi_watch".to_string())
+        .stack_size(4 * 1024)
+        .spawn(|| {
+            let mut attempt = 0;
+            loop {
+                std::thread::sleep(if attempt == 0 {
+                    RECONNECT_WATCH_INTERVAL
+                } else {
+                    connect_backoff(attempt)
+                });
+
+                let mut ap_info = esp_idf_svc::sys::wifi_ap_record_t::default();
+                let e = unsafe { esp_idf_svc::sys::esp_wifi_sta_get_ap_info(&mut ap_info) };
+                if e == esp_idf_svc::sys::ESP_OK {
+                    if attempt > 0 {
+                        info!("Wifi reconnected after {} attempts", attempt);
+                    }
+                    attempt = 0;
+                    continue;
+                }
 
-    info!("Wifi DHCP info: {:?}", ip_info);
+                attempt += 1;
+                log::warn!("Wifi disconnected, reconnect attempt {}", attempt);
+                let e = unsafe { esp_idf_svc::sys::esp_wifi_connect() };
+                if e != esp_idf_svc::sys::ESP_OK {
+                    log::warn!("esp_wifi_connect failed: {}", e);
+                }
+            }
+        })?;
 
-    Ok(Box::new(esp_wifi))
+    Ok(())
 }
 
-#[allow(unused)]
+/// GETs `url`. `https` servers are verified against the certificate bundle.
 pub fn http_get(url: &str) -> anyhow::Result<EspHttpConnection> {
-    let configuration = esp_idf_svc::http::client::Configuration::default();
+    let configuration = esp_idf_svc::http::client::Configuration {
+        crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+        ..Default::default()
+    };
     let mut conn = EspHttpConnection::new(&configuration)?;
     conn.initiate_request(Method::Get, url, &[])?;
 
@@ -65,12 +429,18 @@ pub fn http_get(url: &str) -> anyhow::Result<EspHttpConnection> {
     Ok(conn)
 }
 
-#[allow(unused)]
-pub fn http_post(url: &str, data: &[u8]) -> anyhow::Result<EspHttpConnection> {
-    let configuration = esp_idf_svc::http::client::Configuration::default();
+fn send_post(
+    conn: &mut EspHttpConnection,
+    url: &str,
+    content_type: &str,
+    data: &[u8],
+) -> anyhow::Result<()> {
     let len = data.len().to_string();
-    let mut conn = EspHttpConnection::new(&configuration)?;
-    conn.initiate_request(Method::Post, url, &[("Content-Length", &len)])?;
+    conn.initiate_request(
+        Method::Post,
+        url,
+        &[("Content-Type", content_type), ("Content-Length", &len)],
+    )?;
 
     let mut offset = 0;
 
@@ -81,5 +451,286 @@ pub fn http_post(url: &str, data: &[u8]) -> anyhow::Result<EspHttpConnection> {
 
     conn.initiate_response()?;
 
-    Ok(conn)
+    Ok(())
+}
+
+/// Identifies the device in the reports it POSTs to the server's HTTP API;
+/// each report flattens it into its own fields.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceIdent {
+    /// Same id the device uses in the websocket URL (its WiFi MAC).
+    pub device_id: String,
+    /// `CARGO_PKG_VERSION` of the firmware.
+    #[serde(rename = "firmware")]
+    pub firmware_version: &'static str,
+}
+
+impl DeviceIdent {
+    pub fn new(device_id: String) -> Self {
+        Self {
+            device_id,
+            firmware_version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+}
+
+/// `report` as JSON without the `DeviceIdent` fields, which
+/// `test_device_ident_schema` covers, so report tests only check their own.
+#[cfg(test)]
+pub fn report_fields(report: &impl serde::Serialize) -> serde_json::Value {
+    let mut json = serde_json::to_value(report).unwrap();
+    let ident = serde_json::to_value(DeviceIdent::new(String::new())).unwrap();
+    for key in ident.as_object().unwrap().keys() {
+        assert!(json.as_object_mut().unwrap().remove(key).is_some());
+    }
+    json
+}
+
+#[test]
+fn test_device_ident_schema() {
+    let ident = DeviceIdent::new("a1b2c3d4e5f6".to_string());
+    assert_eq!(
+        serde_json::to_value(&ident).unwrap(),
+        serde_json::json!({
+            "device_id": "a1b2c3d4e5f6",
+            "firmware": env!("CARGO_PKG_VERSION"),
+        })
+    );
+}
+
+/// One keep-alive HTTP connection for periodic requests to the same server,
+/// so each request doesn't allocate a new client. The connection is dropped
+/// after any error and recreated by the next request, and closed with the
+/// session.
+#[derive(Default)]
+pub struct HttpSession {
+    conn: Option<EspHttpConnection>,
+}
+
+impl HttpSession {
+    /// POSTs `data` to `url` and returns the response status. The response
+    /// body is read and discarded so the connection can be reused.
+    ///
+    /// If the request fails on a reused connection, e.g. because the server
+    /// closed it while idle, it is retried once on a fresh one.
+    pub fn post(&mut self, url: &str, content_type: &str, data: &[u8]) -> anyhow::Result<u16> {
+        let reused = self.conn.is_some();
+        let mut r = self.try_post(url, content_type, data);
+        if r.is_err() {
+            self.close();
+            if reused {
+                log::warn!("POST to {} failed on a reused connection, retrying", url);
+                r = self.try_post(url, content_type, data);
+                if r.is_err() {
+                    self.close();
+                }
+            }
+        }
+        r
+    }
+
+    /// `post` with `body` serialized as JSON.
+    pub fn post_json<T: serde::Serialize>(&mut self, url: &str, body: &T) -> anyhow::Result<u16> {
+        let data = serde_json::to_vec(body)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize request for {}: {}", url, e))?;
+        self.post(url, "application/json", &data)
+    }
+
+    fn try_post(&mut self, url: &str, content_type: &str, data: &[u8]) -> anyhow::Result<u16> {
+        if self.conn.is_none() {
+            let configuration = esp_idf_svc::http::client::Configuration::default();
+            self.conn = Some(EspHttpConnection::new(&configuration)?);
+        }
+        let conn = self.conn.as_mut().unwrap();
+
+        send_post(conn, url, content_type, data)?;
+        let status = conn.status();
+
+        let mut buf = [0u8; 128];
+        while conn.read(&mut buf)? > 0 {}
+
+        Ok(status)
+    }
+
+    pub fn close(&mut self) {
+        if self.conn.take().is_some() {
+            log::info!("HTTP session closed");
+        }
+    }
+}
+
+/// Starts the thread `name` that POSTs what `body` returns as JSON to `url`,
+/// sleeping `interval()` before each round, over one `HttpSession` kept open
+/// between posts. `body` returns `None` to skip a round and is passed the
+/// result of its previous post (`None` if that round was skipped), so it can
+/// decide what to resend. Results are only logged here.
+pub fn spawn_periodic_post<T: serde::Serialize>(
+    name: &'static str,
+    stack_size: usize,
+    mut interval: impl FnMut() -> std::time::Duration + Send + 'static,
+    url: String,
+    mut body: impl FnMut(Option<anyhow::Result<u16>>) -> Option<T> + Send + 'static,
+) -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .stack_size(stack_size)
+        .spawn(move || {
+            let mut session = HttpSession::default();
+            let mut last = None;
+            loop {
+                std::thread::sleep(interval());
+
+                let Some(data) = body(last.take()) else {
+                    continue;
+                };
+                let r = session.post_json(&url, &data);
+                match &r {
+                    Ok(status) if (200..300).contains(status) => {
+                        log::debug!("{} sent", name);
+                    }
+                    Ok(status) => {
+                        log::warn!("{} rejected with status {}", name, status);
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to send {}: {:?}", name, e);
+                    }
+                }
+                last = Some(r);
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Returns the HTTP origin of a websocket server URL,
+/// e.g. `wss://example.com:8080/ws/` -> `https://example.com:8080`. A URL
+/// without a scheme, like `192.168.1.10:10086` or `//host/ws`, is taken as
+/// `http`.
+pub fn convert_ws_to_http(url: &str) -> Option<String> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .unwrap_or(("http", url.strip_prefix("//").unwrap_or(url)));
+    let scheme = match scheme {
+        "ws" => "http",
+        "wss" => "https",
+        "http" | "https" => scheme,
+        _ => return None,
+    };
+
+    let authority = authority(rest);
+    if authority.is_empty() {
+        return None;
+    }
+
+    Some(format!("{}://{}", scheme, authority))
+}
+
+/// The `host[:port]` part of a URL with the scheme already stripped.
+fn authority(rest: &str) -> &str {
+    rest.split(['/', '?', '#']).next().unwrap_or_default()
+}
+
+/// Checks that `url` is usable as the server URL: a `ws`, `wss`, `http` or
+/// `https` scheme, a host and, if given, a valid port. The error says what to
+/// fix, so it can be shown to the user as is.
+pub fn validate_server_url(url: &str) -> anyhow::Result<()> {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        anyhow::bail!(
+            "Server URL {:?} needs a scheme, e.g. ws://192.168.1.2:8080/ws",
+            url
+        );
+    };
+    if !matches!(scheme, "ws" | "wss" | "http" | "https") {
+        anyhow::bail!(
+            "Server URL scheme {:?} is not supported, use ws, wss, http or https",
+            scheme
+        );
+    }
+
+    let authority = authority(rest);
+    let (host, port) = match authority.rsplit_once(':') {
+        // an IPv6 address like `[::1]` without a port
+        Some((_, port)) if port.ends_with(']') => (authority, None),
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    if host.is_empty() {
+        anyhow::bail!("Server URL {:?} has no host", url);
+    }
+    if host.chars().any(char::is_whitespace) {
+        anyhow::bail!("Server URL host {:?} contains spaces", host);
+    }
+    if let Some(port) = port {
+        if !matches!(port.parse::<u16>(), Ok(1..)) {
+            anyhow::bail!("Server URL port {:?} is not a valid port number", port);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_validate_server_url() {
+    for url in [
+        "ws://192.168.1.2:8080/ws/",
+        "wss://example.com/ws?x=1",
+        "http://example.com",
+        "https://example.com:443/",
+        "ws://[::1]:8080/ws",
+        "ws://[::1]/ws",
+    ] {
+        assert!(validate_server_url(url).is_ok(), "{url}");
+    }
+
+    for url in [
+        "",
+        "ws:/host/ws",
+        "192.168.1.2:8080/ws",
+        "https://",
+        "ws:///ws",
+        "ftp://example.com",
+        "ws://:8080/ws",
+        "ws://host:/ws",
+        "ws://host:80a/ws",
+        "ws://host:0/ws",
+        "ws://host:65536/ws",
+        "ws://my host/ws",
+    ] {
+        assert!(validate_server_url(url).is_err(), "{url}");
+    }
+}
+
+#[test]
+fn test_convert_ws_to_http() {
+    assert_eq!(
+        convert_ws_to_http("ws://192.168.1.2:8080/ws/").as_deref(),
+        Some("http://192.168.1.2:8080")
+    );
+    assert_eq!(
+        convert_ws_to_http("wss://example.com/ws?x=1").as_deref(),
+        Some("https://example.com")
+    );
+    assert_eq!(
+        convert_ws_to_http("https://example.com").as_deref(),
+        Some("https://example.com")
+    );
+    assert_eq!(
+        convert_ws_to_http("example.com/ws").as_deref(),
+        Some("http://example.com")
+    );
+    assert_eq!(
+        convert_ws_to_http("192.168.1.10:10086").as_deref(),
+        Some("http://192.168.1.10:10086")
+    );
+    assert_eq!(
+        convert_ws_to_http("192.168.1.10:10086/").as_deref(),
+        Some("http://192.168.1.10:10086")
+    );
+    assert_eq!(
+        convert_ws_to_http("//host/ws").as_deref(),
+        Some("http://host")
+    );
+    assert_eq!(convert_ws_to_http(""), None);
+    assert_eq!(convert_ws_to_http("//"), None);
+    assert_eq!(convert_ws_to_http("ws:///ws"), None);
+    assert_eq!(convert_ws_to_http("ftp://example.com"), None);
 }
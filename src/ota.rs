@@ -0,0 +1,193 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use esp_idf_svc::ota::EspOta;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Set once `report_firmware_version` got through this boot.
+static VERSION_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Parses a hex SHA-256 digest (64 hex digits, either case).
+pub fn parse_sha256(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut digest = [0u8; 32];
+    for (i, b) in digest.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[test]
+fn test_parse_sha256() {
+    let hex = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    let digest = parse_sha256(hex).unwrap();
+    assert_eq!(digest[0], 0xe3);
+    assert_eq!(digest[31], 0x55);
+    assert_eq!(parse_sha256(&hex.to_uppercase()), Some(digest));
+
+    assert_eq!(parse_sha256(""), None);
+    assert_eq!(parse_sha256(&hex[..62]), None);
+    assert_eq!(parse_sha256(&format!("{}00", hex)), None);
+    assert_eq!(parse_sha256(&hex.replace('e', "g")), None);
+}
+
+/// Only firmware served over `https` is accepted, so the image comes from a
+/// server whose certificate checked out. The SHA-256 sent along with the URL
+/// travels over the same websocket and can't vouch for the image by itself.
+fn check_firmware_url(url: &str) -> anyhow::Result<()> {
+    match url.split_once("://") {
+        Some((scheme, _)) if scheme.eq_ignore_ascii_case("https") => Ok(()),
+        _ => anyhow::bail!("Firmware URL must use https: {}", url),
+    }
+}
+
+#[test]
+fn test_check_firmware_url() {
+    assert!(check_firmware_url("https://example.com/echokit.bin").is_ok());
+    assert!(check_firmware_url("HTTPS://example.com/echokit.bin").is_ok());
+    assert!(check_firmware_url("http://example.com/echokit.bin").is_err());
+    assert!(check_firmware_url("example.com/echokit.bin").is_err());
+    assert!(check_firmware_url("").is_err());
+}
+
+/// Downloads the app image at `url`, which must be `https`, into the next OTA
+/// slot and selects it for the next boot. `progress` is called with the bytes
+/// written so far and the total size if the server sent a `Content-Length`.
+///
+/// `sha256` only catches a corrupted or truncated download; the image is
+/// trusted because of the server's certificate. On any failure the update is
+/// aborted and the running firmware stays selected. The caller reboots after a
+/// successful update.
+pub fn update_from_url(
+    url: &str,
+    sha256: &str,
+    mut progress: impl FnMut(usize, Option<usize>),
+) -> anyhow::Result<()> {
+    check_firmware_url(url)?;
+    let expected =
+        parse_sha256(sha256).ok_or_else(|| anyhow::anyhow!("Invalid SHA-256: {:?}", sha256))?;
+
+    let mut conn = crate::network::http_get(url)?;
+    let status = conn.status();
+    if !(200..300).contains(&status) {
+        anyhow::bail!("Firmware download failed with status {}", status);
+    }
+    let total = conn
+        .header("Content-Length")
+        .and_then(|len| len.parse::<usize>().ok());
+    log::info!("Downloading firmware from {} ({:?} bytes)", url, total);
+
+    let mut ota = EspOta::new()?;
+    let mut update = ota.initiate_update()?;
+
+    let mut hasher = Sha256::new();
+    let mut written = 0;
+    let mut buf = vec![0u8; 4096];
+    let r = (|| -> anyhow::Result<()> {
+        loop {
+            let n = conn
+                .read(&mut buf)
+                .map_err(|e| anyhow::anyhow!("Firmware download failed: {:?}", e))?;
+            if n == 0 {
+                break;
+            }
+            update.write(&buf[..n])?;
+            hasher.update(&buf[..n]);
+            written += n;
+            progress(written, total);
+        }
+
+        if let Some(total) = total {
+            if written != total {
+                anyhow::bail!("Firmware truncated: {} of {} bytes", written, total);
+            }
+        }
+
+        let digest: [u8; 32] = hasher.finalize().into();
+        if digest != expected {
+            anyhow::bail!("Firmware SHA-256 mismatch");
+        }
+        Ok(())
+    })();
+
+    match r {
+        Ok(()) => {
+            update.complete()?;
+            log::info!("Firmware update written, {} bytes", written);
+            Ok(())
+        }
+        Err(e) => {
+            if let Err(abort_err) = update.abort() {
+                log::error!("Failed to abort OTA update: {:?}", abort_err);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Sent (JSON) to `{server}/api/devices/firmware`, where `{server}` is the
+/// HTTP origin of the websocket server URL, so the server knows which firmware
+/// each device runs and can offer an update.
+#[derive(Debug, Serialize)]
+pub struct FirmwareReport {
+    #[serde(flatten)]
+    pub ident: crate::network::DeviceIdent,
+    /// WiFi MAC as `aa:bb:cc:dd:ee:ff`.
+    pub mac: String,
+}
+
+impl FirmwareReport {
+    pub fn new(device_id: &str, mac: &[u8; 6]) -> Self {
+        Self {
+            ident: crate::network::DeviceIdent::new(device_id.to_string()),
+            mac: mac
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+        }
+    }
+}
+
+/// POSTs a `FirmwareReport` to the server at `server_url`. Only the first
+/// successful report of a boot is sent; later calls return `Ok` right away.
+pub fn report_firmware_version(
+    server_url: &str,
+    device_id: &str,
+    mac: &[u8; 6],
+) -> anyhow::Result<()> {
+    if VERSION_REPORTED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let base = crate::network::convert_ws_to_http(server_url)
+        .ok_or_else(|| anyhow::anyhow!("Invalid server URL for version report: {}", server_url))?;
+    let url = format!("{}/api/devices/firmware", base);
+
+    let report = FirmwareReport::new(device_id, mac);
+    let status = crate::network::HttpSession::default().post_json(&url, &report)?;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("Version report rejected with status {}", status);
+    }
+
+    VERSION_REPORTED.store(true, Ordering::Relaxed);
+    log::info!(
+        "Reported firmware {} to the server",
+        report.ident.firmware_version
+    );
+    Ok(())
+}
+
+#[test]
+fn test_firmware_report_schema() {
+    let report = FirmwareReport::new("a1b2c3d4e5f6", &[0xa1, 0xb2, 0xc3, 0xd4, 0xe5, 0xf6]);
+    assert_eq!(report.ident.device_id, "a1b2c3d4e5f6");
+    assert_eq!(
+        crate::network::report_fields(&report),
+        serde_json::json!({ "mac": "a1:b2:c3:d4:e5:f6" })
+    );
+}
@@ -10,6 +10,7 @@ pub enum GpioMode {
     Pwm = 1 << 5,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GpioPin {
     E0 = 0,
     E1 = 1,
@@ -98,3 +99,82 @@ pub fn read_gpio_level(
     .map_err(|e| anyhow::anyhow!("I2C transaction error: {:?}", e))?;
     Ok(read[0])
 }
+
+/// Set the PWM frequency in Hz, shared by all pins in `GpioMode::Pwm`
+///
+/// The 16-bit frequency is written little-endian starting at `ADDRESS_PWM_FREQUENCY`.
+pub fn set_pwm_frequency(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    dev_i2c_address: u8,
+    frequency: u16,
+) -> anyhow::Result<()> {
+    let [lo, hi] = frequency.to_le_bytes();
+    i2c.transaction(
+        dev_i2c_address,
+        &mut [Operation::Write(&[ADDRESS_PWM_FREQUENCY, lo, hi])],
+        esp_idf_svc::hal::delay::TickType::new_millis(1000).0,
+    )
+    .map_err(|e| anyhow::anyhow!("I2C write error: {:?}", e))?;
+    Ok(())
+}
+
+/// Set the PWM duty of a pin in `GpioMode::Pwm`
+///
+/// duty: 0 (always low) to 255 (always high)
+pub fn set_pwm_duty(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    dev_i2c_address: u8,
+    pin: GpioPin,
+    duty: u8,
+) -> anyhow::Result<()> {
+    i2c.transaction(
+        dev_i2c_address,
+        &mut [Operation::Write(&[ADDRESS_PWM_DUTY + pin as u8, duty])],
+        esp_idf_svc::hal::delay::TickType::new_millis(1000).0,
+    )
+    .map_err(|e| anyhow::anyhow!("I2C write error: {:?}", e))?;
+    Ok(())
+}
+
+/// Read a 16-bit value starting at `address`. The expander sends it
+/// little-endian: low byte first, then high byte.
+fn read_u16(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    dev_i2c_address: u8,
+    address: u8,
+) -> anyhow::Result<u16> {
+    let mut read = [0; 2];
+    i2c.transaction(
+        dev_i2c_address,
+        &mut [Operation::Write(&[address]), Operation::Read(&mut read)],
+        esp_idf_svc::hal::delay::TickType::new_millis(1000).0,
+    )
+    .map_err(|e| anyhow::anyhow!("I2C transaction error: {:?}", e))?;
+    Ok(u16::from_le_bytes(read))
+}
+
+/// Read the raw ADC value of a pin, switching it to `GpioMode::Adc` first
+///
+/// Each pin has a 16-bit register at `ADDRESS_ANALOG_VALUES + pin * 2`.
+pub fn read_analog_raw(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    dev_i2c_address: u8,
+    pin: GpioPin,
+) -> anyhow::Result<u16> {
+    let address = ADDRESS_ANALOG_VALUES + pin as u8 * 2;
+    set_gpio_mode(i2c, dev_i2c_address, pin, GpioMode::Adc)?;
+    read_u16(i2c, dev_i2c_address, address)
+}
+
+/// Read the voltage of a pin in millivolts, switching it to `GpioMode::Adc` first
+///
+/// Each pin has a 16-bit register at `ADDRESS_VOLTAGE_VALUES + pin * 2`.
+pub fn read_voltage_mv(
+    i2c: &mut esp_idf_svc::hal::i2c::I2cDriver<'static>,
+    dev_i2c_address: u8,
+    pin: GpioPin,
+) -> anyhow::Result<u16> {
+    let address = ADDRESS_VOLTAGE_VALUES + pin as u8 * 2;
+    set_gpio_mode(i2c, dev_i2c_address, pin, GpioMode::Adc)?;
+    read_u16(i2c, dev_i2c_address, address)
+}
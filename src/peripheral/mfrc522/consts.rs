@@ -10,27 +10,54 @@ pub struct Uid {
 }
 
 impl Uid {
-    pub fn get_number(&self) -> u128 {
+    /// Returns the UID as a little-endian number, or `PCDErrorCode::Invalid`
+    /// if `size` is not one of the valid UID lengths (4, 7 or 10 bytes).
+    pub fn get_number(&self) -> Result<u128, PCDErrorCode> {
         match self.size {
-            4 => u32::from_le_bytes(self.uid_bytes[..4].try_into().unwrap_or([0, 0, 0, 0])) as u128,
+            4 => Ok(
+                u32::from_le_bytes(self.uid_bytes[..4].try_into().unwrap_or([0, 0, 0, 0])) as u128,
+            ),
             7 => {
                 let mut bytes = [0; 8];
                 bytes[..7].copy_from_slice(&self.uid_bytes[..7]);
-                u64::from_le_bytes(bytes) as u128
+                Ok(u64::from_le_bytes(bytes) as u128)
             }
             10 => {
                 let mut bytes = [0; 16];
                 bytes[..10].copy_from_slice(&self.uid_bytes[..10]);
-                u128::from_le_bytes(bytes)
+                Ok(u128::from_le_bytes(bytes))
             }
             _ => {
-                log::error!("Wrong bytes count!");
-                unreachable!() // i dont think that this case is reachable
+                // a malformed select response can leave size inconsistent
+                log::error!("Wrong UID bytes count: {}", self.size);
+                Err(PCDErrorCode::Invalid)
             }
         }
     }
 }
 
+#[test]
+fn test_uid_get_number() {
+    let mut uid = Uid {
+        size: 4,
+        uid_bytes: [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A],
+        sak: 0,
+    };
+    assert_eq!(uid.get_number(), Ok(0x04030201));
+
+    uid.size = 7;
+    assert_eq!(uid.get_number(), Ok(0x07060504030201));
+
+    uid.size = 10;
+    assert_eq!(uid.get_number(), Ok(0x0A090807060504030201));
+
+    // sizes a malformed select response could leave behind
+    for size in [0, 5, 11, 255] {
+        uid.size = size;
+        assert_eq!(uid.get_number(), Err(PCDErrorCode::Invalid));
+    }
+}
+
 pub struct PCDRegister;
 pub struct PCDCommand;
 pub struct PICCCommand;
@@ -86,3 +86,116 @@ impl<'d> MfrcDriver for I2CDriver<'d> {
         Ok(())
     }
 }
+
+/// Address byte for an SPI write: register in bits 6..1, MSB clear.
+#[cfg(feature = "mfrc522_spi")]
+fn spi_write_addr(reg: u8) -> u8 {
+    (reg << 1) & 0x7E
+}
+
+/// Address byte for an SPI read: register in bits 6..1, MSB set.
+#[cfg(feature = "mfrc522_spi")]
+fn spi_read_addr(reg: u8) -> u8 {
+    0x80 | spi_write_addr(reg)
+}
+
+#[cfg(feature = "mfrc522_spi")]
+#[test]
+fn test_spi_addr() {
+    use super::consts::PCDRegister;
+
+    assert_eq!(spi_write_addr(PCDRegister::CommandReg), 0x02);
+    assert_eq!(spi_read_addr(PCDRegister::CommandReg), 0x82);
+    assert_eq!(spi_write_addr(PCDRegister::FIFODataReg), 0x12);
+    assert_eq!(spi_read_addr(0x3F), 0xFE);
+}
+
+/// MFRC522 on an SPI bus (mode 0, up to 10MHz). The device driver owns CS.
+///
+/// SPI transfers have no timeout, so the `timeout` arguments are ignored.
+#[cfg(feature = "mfrc522_spi")]
+pub struct SpiDriver<'d> {
+    spi: &'d mut esp_idf_svc::hal::spi::SpiDeviceDriver<
+        'static,
+        esp_idf_svc::hal::spi::SpiDriver<'static>,
+    >,
+}
+
+#[cfg(feature = "mfrc522_spi")]
+impl<'d> SpiDriver<'d> {
+    pub fn new(
+        spi: &'d mut esp_idf_svc::hal::spi::SpiDeviceDriver<
+            'static,
+            esp_idf_svc::hal::spi::SpiDriver<'static>,
+        >,
+    ) -> Self {
+        Self { spi }
+    }
+}
+
+#[cfg(feature = "mfrc522_spi")]
+impl<'d> MfrcDriver for SpiDriver<'d> {
+    fn write_reg(&mut self, reg: u8, val: u8, _timeout: TickType_t) -> Result<(), PCDErrorCode> {
+        self.spi
+            .write(&[spi_write_addr(reg), val])
+            .map_err(PCDErrorCode::from_spi_error)?;
+
+        Ok(())
+    }
+
+    fn write_reg_buff(
+        &mut self,
+        reg: u8,
+        count: usize,
+        values: &[u8],
+        _timeout: TickType_t,
+    ) -> Result<(), PCDErrorCode> {
+        let mut tx = Vec::with_capacity(count + 1);
+        tx.push(spi_write_addr(reg));
+        tx.extend_from_slice(&values[..count]);
+        self.spi.write(&tx).map_err(PCDErrorCode::from_spi_error)?;
+
+        Ok(())
+    }
+
+    fn read_reg(&mut self, reg: u8, _timeout: TickType_t) -> Result<u8, PCDErrorCode> {
+        let mut rx = [0; 2];
+        self.spi
+            .transfer(&mut rx, &[spi_read_addr(reg), 0])
+            .map_err(PCDErrorCode::from_spi_error)?;
+
+        Ok(rx[1])
+    }
+
+    fn read_reg_buff(
+        &mut self,
+        reg: u8,
+        count: usize,
+        output_buff: &mut [u8],
+        rx_align: u8,
+        _timeout: TickType_t,
+    ) -> Result<(), PCDErrorCode> {
+        if count == 0 {
+            return Ok(());
+        }
+
+        // the address is clocked out once per byte read, the reply to each address
+        // arrives on the next byte, and a trailing 0 ends the read
+        let mut tx = vec![spi_read_addr(reg); count + 1];
+        tx[count] = 0;
+        let mut rx = vec![0; count + 1];
+        self.spi
+            .transfer(&mut rx, &tx)
+            .map_err(PCDErrorCode::from_spi_error)?;
+
+        let first_out_byte = output_buff[0];
+        output_buff[..count].copy_from_slice(&rx[1..]);
+
+        if rx_align > 0 {
+            let mask = 0xFF << rx_align;
+            output_buff[0] = (first_out_byte & !mask) | (output_buff[0] & mask);
+        }
+
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ pub mod consts;
 // pub mod debug;
 pub mod drivers;
 pub mod mifare;
+pub mod ndef_uri;
 pub mod pcd;
 pub mod picc;
 
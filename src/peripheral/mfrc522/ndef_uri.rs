@@ -0,0 +1,180 @@
+//! Minimal NDEF URI records and the TLV framing used on Type 2 tags
+//! (MIFARE Ultralight / NTAG), enough to burn a server URL to a tag.
+
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF_MESSAGE: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+/// URI identifier codes from the NFC Forum URI RTD, indexed by code.
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+/// Encodes `uri` as a single short well-known URI record wrapped in an NDEF
+/// Message TLV and followed by a Terminator TLV, ready to be written from page 4.
+///
+/// Returns `None` if the record doesn't fit the one-byte TLV length.
+pub fn encode_uri_tlv(uri: &str) -> Option<Vec<u8>> {
+    let (code, rest) = URI_PREFIXES
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, prefix)| uri.starts_with(*prefix))
+        .max_by_key(|(_, prefix)| prefix.len())
+        .map(|(code, prefix)| (code as u8, &uri[prefix.len()..]))
+        .unwrap_or((0, uri));
+
+    let payload_len = 1 + rest.len();
+    // record header is 4 bytes, TLV lengths of 0xFF and up need the 3-byte form
+    if 4 + payload_len >= 0xFF {
+        return None;
+    }
+
+    let mut tlv = Vec::with_capacity(2 + 4 + payload_len + 1);
+    tlv.push(TLV_NDEF_MESSAGE);
+    tlv.push((4 + payload_len) as u8);
+    // MB | ME | SR, TNF = well-known
+    tlv.push(0xD1);
+    tlv.push(1);
+    tlv.push(payload_len as u8);
+    tlv.push(b'U');
+    tlv.push(code);
+    tlv.extend_from_slice(rest.as_bytes());
+    tlv.push(TLV_TERMINATOR);
+    Some(tlv)
+}
+
+/// Finds the NDEF message in a Type 2 tag data area (starting at page 4),
+/// skipping NULL, Lock Control and Memory Control TLVs.
+pub fn find_ndef_message(data: &[u8]) -> Option<&[u8]> {
+    let mut i = 0;
+    loop {
+        match *data.get(i)? {
+            TLV_NULL => i += 1,
+            TLV_TERMINATOR => return None,
+            t => {
+                let (len, header) = match *data.get(i + 1)? {
+                    0xFF => {
+                        let len = u16::from_be_bytes([*data.get(i + 2)?, *data.get(i + 3)?]);
+                        (len as usize, 4)
+                    }
+                    len => (len as usize, 2),
+                };
+                if t == TLV_NDEF_MESSAGE {
+                    return data.get(i + header..i + header + len);
+                }
+                i += header + len;
+            }
+        }
+    }
+}
+
+/// Decodes the first record of `message` if it is a short well-known URI record.
+pub fn decode_uri_record(message: &[u8]) -> Option<String> {
+    let header = *message.first()?;
+    let short = header & 0x10 != 0;
+    let has_id = header & 0x08 != 0;
+    if header & 0x07 != 0x01 || !short {
+        return None;
+    }
+
+    let type_len = *message.get(1)? as usize;
+    let payload_len = *message.get(2)? as usize;
+    let mut i = 3;
+    let id_len = if has_id {
+        i += 1;
+        *message.get(3)? as usize
+    } else {
+        0
+    };
+
+    if message.get(i..i + type_len)? != b"U" {
+        return None;
+    }
+    i += type_len + id_len;
+
+    let payload = message.get(i..i + payload_len)?;
+    let (code, rest) = payload.split_first()?;
+    let prefix = URI_PREFIXES
+        .get(*code as usize)
+        .copied()
+        .unwrap_or_default();
+    Some(format!("{}{}", prefix, std::str::from_utf8(rest).ok()?))
+}
+
+#[test]
+fn test_ndef_uri_round_trip() {
+    for uri in [
+        "ws://192.168.1.2:8080/ws/",
+        "https://echokit.dev/ws",
+        "http://www.example.com",
+        "",
+    ] {
+        let tlv = encode_uri_tlv(uri).unwrap();
+        assert_eq!(*tlv.last().unwrap(), TLV_TERMINATOR);
+        let message = find_ndef_message(&tlv).unwrap();
+        assert_eq!(decode_uri_record(message).as_deref(), Some(uri));
+    }
+
+    let tlv = encode_uri_tlv("https://echokit.dev").unwrap();
+    assert_eq!(
+        tlv,
+        [
+            &[0x03, 0x10, 0xD1, 0x01, 0x0C, b'U', 0x04][..],
+            b"echokit.dev",
+            &[0xFE]
+        ]
+        .concat()
+    );
+
+    assert!(encode_uri_tlv(&"a".repeat(250)).is_none());
+}
+
+#[test]
+fn test_find_ndef_message_skips_tlvs() {
+    // NULL, Lock Control TLV, then the NDEF message, as factory-formatted NTAGs have
+    let data = [
+        0x00, 0x01, 0x03, 0xA0, 0x0C, 0x34, 0x03, 0x02, 0xAA, 0xBB, 0xFE,
+    ];
+    assert_eq!(find_ndef_message(&data), Some(&[0xAA, 0xBB][..]));
+
+    assert_eq!(find_ndef_message(&[0xFE, 0x03, 0x01, 0xAA]), None);
+    // truncated message
+    assert_eq!(find_ndef_message(&[0x03, 0x05, 0xAA]), None);
+    assert_eq!(find_ndef_message(&[]), None);
+}
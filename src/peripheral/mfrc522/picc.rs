@@ -22,6 +22,21 @@ where
         Ok(())
     }
 
+    /// Like `picc_is_new_card_present`, but also answered by a card that was
+    /// halted, i.e. one already read and still on the reader.
+    pub fn picc_is_card_present(&mut self, timeout: TickType_t) -> Result<(), PCDErrorCode> {
+        let mut buffer_atqa = [0; 2];
+        let mut buffer_size = 2;
+
+        self.write_reg(PCDRegister::TxModeReg, 0x00, timeout)?;
+        self.write_reg(PCDRegister::RxModeReg, 0x00, timeout)?;
+        self.write_reg(PCDRegister::ModWidthReg, 0x26, timeout)?;
+
+        self.picc_wakeup_a(&mut buffer_atqa, &mut buffer_size, timeout)?;
+
+        Ok(())
+    }
+
     pub fn picc_halta(&mut self, timeout: TickType_t) -> Result<(), PCDErrorCode> {
         let mut buff = [0; 4];
         buff[0] = PICCCommand::PICC_CMD_HLTA;
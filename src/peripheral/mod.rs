@@ -1,4 +1,4 @@
 #[cfg(feature = "exio")]
 pub mod exio;
-#[cfg(feature = "mfrc522")]
+#[cfg(any(feature = "mfrc522", feature = "mfrc522_spi"))]
 pub mod mfrc522;
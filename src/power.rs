@@ -0,0 +1,83 @@
+//! Opt-in power saving while Idle, see `app::AppConfig::power_save_after`.
+//!
+//! The CPU is clocked down and may light sleep between events. `main_work`
+//! pauses the audio worker first (`AudioEvent::Pause`), since running I2S
+//! channels hold a PM lock that keeps the chip awake. WiFi stays associated in
+//! modem sleep, so server messages still arrive.
+//!
+//! Wake sources: K0 (GPIO wakeup from light sleep), server messages and timers,
+//! e.g. the idle clock refresh. The wake word is not one, it needs the mic.
+
+use esp_idf_svc::sys::*;
+
+/// Normal clock, as set by `CONFIG_ESP_DEFAULT_CPU_FREQ_MHZ`.
+const FULL_CPU_MHZ: i32 = 240;
+/// Clock while saving power, between light sleeps.
+const SAVE_CPU_MHZ: i32 = 80;
+/// Lowest clock dynamic frequency scaling may pick (the XTAL).
+const SAVE_MIN_CPU_MHZ: i32 = 40;
+
+/// K0, the button that wakes the chip from light sleep.
+const WAKE_GPIO: gpio_num_t = gpio_num_t_GPIO_NUM_0;
+
+/// Power save in effect, from `enter` until `leave`.
+pub struct PowerSave {
+    since: std::time::Instant,
+    battery_mv: Option<u32>,
+}
+
+fn configure(max_freq_mhz: i32, min_freq_mhz: i32, light_sleep: bool) -> Result<(), EspError> {
+    let config = esp_pm_config_t {
+        max_freq_mhz,
+        min_freq_mhz,
+        light_sleep_enable: light_sleep,
+    };
+    esp!(unsafe { esp_pm_configure(&config as *const esp_pm_config_t as *const _) })
+}
+
+/// Clocks the CPU down and allows light sleep. Failures (e.g. a build
+/// without `CONFIG_PM_ENABLE`) are logged, the device then just keeps running.
+pub fn enter() -> PowerSave {
+    let battery_mv = crate::telemetry::battery_mv();
+    log::info!("Entering power save, battery {:?}mV", battery_mv);
+
+    if let Err(e) =
+        esp!(unsafe { gpio_wakeup_enable(WAKE_GPIO, gpio_int_type_t_GPIO_INTR_LOW_LEVEL) })
+            .and_then(|_| esp!(unsafe { esp_sleep_enable_gpio_wakeup() }))
+    {
+        log::warn!("Failed to enable K0 wakeup: {:?}", e);
+    }
+    if let Err(e) = configure(SAVE_CPU_MHZ, SAVE_MIN_CPU_MHZ, true) {
+        log::warn!("Failed to enter power save: {:?}", e);
+    }
+
+    PowerSave {
+        since: std::time::Instant::now(),
+        battery_mv,
+    }
+}
+
+impl PowerSave {
+    /// Restores the full clock. Logs how long power save lasted and, on boards
+    /// that measure it, the battery drop, to compare the drain with normal use.
+    pub fn leave(self) {
+        if let Err(e) = configure(FULL_CPU_MHZ, FULL_CPU_MHZ, false) {
+            log::warn!("Failed to leave power save: {:?}", e);
+        }
+        // K0 is left on the level interrupt `gpio_wakeup_enable` set: main's
+        // button task sets the edge it needs on each wait, and may already be
+        // waiting for the release
+        unsafe { gpio_wakeup_disable(WAKE_GPIO) };
+
+        let battery_mv = crate::telemetry::battery_mv();
+        match (self.battery_mv, battery_mv) {
+            (Some(before), Some(after)) => log::info!(
+                "Left power save after {:?}, battery {}mV -> {}mV",
+                self.since.elapsed(),
+                before,
+                after
+            ),
+            _ => log::info!("Left power save after {:?}", self.since.elapsed()),
+        }
+    }
+}
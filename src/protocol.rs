@@ -15,10 +15,50 @@ pub enum ServerEvent {
     AudioChunki16 { data: Vec<i16>, vowel: u8 },
     EndAudio,
     StartVideo,
+    // one frame while a video is playing, an encoded PNG, WebP or GIF image,
+    // see `ui::draw_video_frame`
+    VideoChunk { data: Vec<u8> },
     EndVideo,
     EndResponse,
 
     EndVad,
+
+    // level uses the same 1..=5 scale as the local volume buttons,
+    // out-of-range values are clamped
+    SetVolume { level: u8 },
+
+    // avatar to show, e.g. "happy", "thinking" or "listening"; unknown names
+    // restore the default avatar
+    Emotion { name: String },
+
+    // firmware image to flash, only accepted from an https url; sha256 is the
+    // hex digest of the whole image, checked against a corrupted download
+    OtaUpdate { url: String, sha256: String },
+
+    // a notification outside the conversation, e.g. "Update available"
+    ShowText { area: TextArea, text: String },
+}
+
+/// Where `ServerEvent::ShowText` puts its text.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextArea {
+    /// The one-line state bar, e.g. "Idle" or "Listening...".
+    State,
+    /// The main area where ASR and response text go.
+    Content,
+}
+
+/// Decodes a binary websocket frame into a `ServerEvent`.
+///
+/// The server may encode with `rmp_serde::to_vec_named` (struct variant fields
+/// as a map keyed by name, the recommended default since it survives field
+/// reordering) or with `rmp_serde::to_vec` (fields as a positional array).
+/// rmp-serde accepts either form for struct bodies, so both encodings decode
+/// to the same event and the device does not need to know which one the
+/// server picked.
+pub fn decode_server_event(data: &[u8]) -> Result<ServerEvent, rmp_serde::decode::Error> {
+    rmp_serde::from_slice(data)
 }
 
 #[test]
@@ -41,13 +81,169 @@ fn test_rmp_command() {
     }
 }
 
+#[test]
+fn test_decode_server_event_both_encodings() {
+    let events = [
+        ServerEvent::HelloEnd,
+        ServerEvent::ASR {
+            text: "hi".to_string(),
+        },
+        ServerEvent::AudioChunkWithVowel {
+            data: vec![1, 2, 3],
+            vowel: 4,
+        },
+        ServerEvent::AudioChunki16 {
+            data: vec![-1, 0, 1],
+            vowel: 2,
+        },
+        ServerEvent::SetVolume { level: 3 },
+        ServerEvent::ShowText {
+            area: TextArea::Content,
+            text: "Update available".to_string(),
+        },
+    ];
+    for event in events {
+        let compact = rmp_serde::to_vec(&event).unwrap();
+        let named = rmp_serde::to_vec_named(&event).unwrap();
+        for data in [compact, named] {
+            let decoded = decode_server_event(&data).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", event));
+        }
+    }
+}
+
+/// Decodes a text websocket frame into a `ServerEvent`.
+///
+/// Text frames are expected to hold JSON in serde's default externally tagged
+/// form, e.g. `{"ASR":{"text":"hi"}}` or `"EndAudio"`, which is handy for
+/// control events and text-based test servers. A frame that isn't valid JSON
+/// is tried as msgpack before giving up.
+pub fn decode_server_text_event(text: &str) -> anyhow::Result<ServerEvent> {
+    serde_json::from_str(text).or_else(|json_err| {
+        decode_server_event(text.as_bytes()).map_err(|rmp_err| {
+            anyhow::anyhow!(
+                "Failed to deserialize text data as JSON ({}) or msgpack ({})",
+                json_err,
+                rmp_err
+            )
+        })
+    })
+}
+
+#[test]
+fn test_decode_server_text_event() {
+    let event = decode_server_text_event(r#"{"ASR":{"text":"hi"}}"#).unwrap();
+    assert!(matches!(event, ServerEvent::ASR { text } if text == "hi"));
+
+    let event = decode_server_text_event(r#""EndAudio""#).unwrap();
+    assert!(matches!(event, ServerEvent::EndAudio));
+
+    let event = decode_server_text_event(r#"{"SetVolume":{"level":3}}"#).unwrap();
+    assert!(matches!(event, ServerEvent::SetVolume { level: 3 }));
+
+    let event = decode_server_text_event(r#"{"ShowText":{"area":"state","text":"hi"}}"#).unwrap();
+    assert!(matches!(event, ServerEvent::ShowText { area: TextArea::State, text } if text == "hi"));
+
+    assert!(decode_server_text_event("hello").is_err());
+    assert!(decode_server_text_event(r#"{"Unknown":{}}"#).is_err());
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "event")]
 pub enum ClientCommand {
+    /// Sent first on every connection, so the server can tailor its
+    /// responses to what the device can do.
+    Hello {
+        /// Same as `network::DeviceIdent::firmware_version`.
+        firmware: String,
+        /// Width and height of the screen in pixels.
+        display: [u32; 2],
+        /// Sample rate of the submitted audio in Hz.
+        sample_rate: u32,
+        /// Optional Cargo features compiled in, e.g. `voice_interrupt`.
+        features: Vec<String>,
+    },
     StartRecord,
     StartChat,
     Submit,
-    Text { input: String },
+    Text {
+        input: String,
+    },
+    /// Sent after `EndResponse` so the server can tune its TTS rate.
+    Metrics {
+        /// From the end of speech (`EndVad` or a push-to-talk submit) to the
+        /// first audio chunk, `None` if no audio arrived.
+        first_audio_ms: Option<u64>,
+        total_audio_bytes: u64,
+        /// Last measured download time relative to playback time, see
+        /// `app::DownloadMetrics::speed`.
+        speed: f64,
+    },
+    /// Sent on a barge-in before the socket is replaced, so the server can
+    /// stop generating the response in progress.
+    Cancel,
+    /// A button press the device is configured to forward, named like
+    /// `app::Event` (`k1`, `vol_up`, ...). Local handling still happens.
+    Button {
+        name: String,
+    },
+}
+
+#[test]
+fn test_client_command_metrics() {
+    let cmd = ClientCommand::Metrics {
+        first_audio_ms: Some(850),
+        total_audio_bytes: 64000,
+        speed: 0.25,
+    };
+    assert_eq!(
+        serde_json::to_value(&cmd).unwrap(),
+        serde_json::json!({
+            "event": "Metrics",
+            "first_audio_ms": 850,
+            "total_audio_bytes": 64000,
+            "speed": 0.25,
+        })
+    );
+}
+
+#[test]
+fn test_client_command_hello() {
+    let cmd = ClientCommand::Hello {
+        firmware: "0.3.2".to_string(),
+        display: [320, 240],
+        sample_rate: 16000,
+        features: vec!["voice_interrupt".to_string()],
+    };
+    assert_eq!(
+        serde_json::to_value(&cmd).unwrap(),
+        serde_json::json!({
+            "event": "Hello",
+            "firmware": "0.3.2",
+            "display": [320, 240],
+            "sample_rate": 16000,
+            "features": ["voice_interrupt"],
+        })
+    );
+}
+
+#[test]
+fn test_client_command_cancel() {
+    assert_eq!(
+        serde_json::to_value(&ClientCommand::Cancel).unwrap(),
+        serde_json::json!({ "event": "Cancel" })
+    );
+}
+
+#[test]
+fn test_client_command_button() {
+    let cmd = ClientCommand::Button {
+        name: "k1".to_string(),
+    };
+    assert_eq!(
+        serde_json::to_value(&cmd).unwrap(),
+        serde_json::json!({ "event": "Button", "name": "k1" })
+    );
 }
 
 #[test]
@@ -65,3 +261,25 @@ fn test_rmp_client_command() {
         _ => panic!("Unexpected command: {:?}", cmd2),
     }
 }
+
+/// Serializes PCM samples for the wire. Audio is always little-endian i16,
+/// independent of the host byte order.
+pub fn encode_pcm_le(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// Inverse of `encode_pcm_le`. A trailing odd byte is ignored.
+pub fn decode_pcm_le(data: &[u8]) -> Vec<i16> {
+    data.chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+#[test]
+fn test_pcm_le_round_trip() {
+    let samples = [0i16, 1, -1, 0x1234, i16::MIN, i16::MAX];
+    let data = encode_pcm_le(&samples);
+    assert_eq!(&data[6..8], &[0x34, 0x12]);
+    assert_eq!(decode_pcm_le(&data), samples);
+    assert_eq!(decode_pcm_le(&data[..3]), vec![0]);
+}
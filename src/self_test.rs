@@ -0,0 +1,86 @@
+//! Boot self-test, started by holding K0 and VOL_DOWN at power-on. `main` runs
+//! each check in turn and shows the report until a button press.
+
+/// Outcome of one check: a short detail on success, the error on failure.
+pub struct Check {
+    pub name: &'static str,
+    pub result: anyhow::Result<String>,
+}
+
+impl Check {
+    pub fn new(name: &'static str, result: anyhow::Result<String>) -> Self {
+        Self { name, result }
+    }
+}
+
+/// One line per check, `OK` or `FAIL` first so the column lines up.
+pub fn format_report(checks: &[Check]) -> String {
+    checks
+        .iter()
+        .map(|check| match &check.result {
+            Ok(detail) if detail.is_empty() => format!("OK   {}", check.name),
+            Ok(detail) => format!("OK   {}: {}", check.name, detail),
+            Err(e) => format!("FAIL {}: {}", check.name, e),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Passes if any mic audio was captured. The level is reported so a dead
+/// (all-zero) mic shows up as a failure too.
+pub fn mic_check(samples: &[i16]) -> anyhow::Result<String> {
+    if samples.is_empty() {
+        anyhow::bail!("no audio");
+    }
+    let level = crate::audio::mean_amplitude(samples);
+    if level == 0 {
+        anyhow::bail!("silent");
+    }
+    Ok(format!(
+        "{:.1}s, level {}",
+        samples.len() as f32 / 16000.0,
+        level
+    ))
+}
+
+/// Passes if `ssid` is in range, or if any AP is when no SSID is configured.
+pub fn wifi_check(ssid: &str, found: &[String]) -> anyhow::Result<String> {
+    if ssid.is_empty() {
+        if found.is_empty() {
+            anyhow::bail!("no APs found");
+        }
+        return Ok(format!("{} APs", found.len()));
+    }
+    if found.iter().any(|s| s == ssid) {
+        Ok(format!("{} found", ssid))
+    } else {
+        anyhow::bail!("{} not found, {} APs", ssid, found.len())
+    }
+}
+
+#[test]
+fn test_format_report() {
+    let checks = [
+        Check::new("Display", Ok(String::new())),
+        Check::new("WiFi", Ok("3 APs".to_string())),
+        Check::new("Speaker", Err(anyhow::anyhow!("timeout"))),
+    ];
+    assert_eq!(
+        format_report(&checks),
+        "OK   Display\nOK   WiFi: 3 APs\nFAIL Speaker: timeout"
+    );
+    assert_eq!(format_report(&[]), "");
+}
+
+#[test]
+fn test_checks() {
+    assert!(mic_check(&[]).is_err());
+    assert!(mic_check(&[0; 1600]).is_err());
+    assert_eq!(mic_check(&[100; 16000]).unwrap(), "1.0s, level 100");
+
+    let found = ["home".to_string(), "guest".to_string()];
+    assert_eq!(wifi_check("", &found).unwrap(), "2 APs");
+    assert!(wifi_check("", &[]).is_err());
+    assert_eq!(wifi_check("guest", &found).unwrap(), "guest found");
+    assert!(wifi_check("office", &found).is_err());
+}
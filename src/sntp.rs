@@ -0,0 +1,171 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use esp_idf_svc::sntp::{EspSntp, SntpConf};
+
+/// Anything before this (2024-01-01) means the clock was never set; the RTC
+/// starts at the epoch after a reset.
+const MIN_VALID_UNIX_SECS: u64 = 1_704_067_200;
+
+/// How long `wait_for_sync` waits for the first sync by default.
+pub const SYNC_MAX_WAIT: Duration = Duration::from_secs(15);
+
+/// How many servers `sync_time_with_servers` uses, see
+/// `CONFIG_LWIP_SNTP_MAX_SERVERS` in `sdkconfig.defaults`.
+pub const MAX_SERVERS: usize = 3;
+
+/// Starts SNTP in the background with up to `MAX_SERVERS` custom servers, for
+/// networks that can't reach the public pool. Extra servers are ignored and an
+/// empty list keeps the default pool servers. The system clock is set once the
+/// first sync completes; keep the handle alive for periodic resyncs.
+pub fn sync_time_with_servers(servers: &[&str]) -> anyhow::Result<EspSntp<'static>> {
+    let mut conf = SntpConf::default();
+    fill_servers(servers, &mut conf.servers);
+    let sntp = EspSntp::new(&conf)?;
+    log::info!("SNTP started with {:?}", conf.servers);
+    Ok(sntp)
+}
+
+/// Puts the first `MAX_SERVERS` of `servers` into the config slots, repeating
+/// them if there are more slots, so no slot is left with a default server.
+fn fill_servers<'a>(servers: &[&'a str], slots: &mut [&'a str]) {
+    let servers = &servers[..servers.len().min(MAX_SERVERS)];
+    if servers.is_empty() {
+        return;
+    }
+    for (i, slot) in slots.iter_mut().enumerate() {
+        *slot = servers[i % servers.len()];
+    }
+}
+
+#[test]
+fn test_fill_servers() {
+    let defaults = ["0.pool.ntp.org", "1.pool.ntp.org", "2.pool.ntp.org"];
+
+    let mut slots = defaults;
+    fill_servers(&[], &mut slots);
+    assert_eq!(slots, defaults);
+
+    fill_servers(&["ntp.lan"], &mut slots);
+    assert_eq!(slots, ["ntp.lan"; 3]);
+
+    fill_servers(&["a", "b"], &mut slots);
+    assert_eq!(slots, ["a", "b", "a"]);
+
+    fill_servers(&["a", "b", "c", "d"], &mut slots);
+    assert_eq!(slots, ["a", "b", "c"]);
+
+    let mut slots = ["x"];
+    fill_servers(&["a", "b"], &mut slots);
+    assert_eq!(slots, ["a"]);
+}
+
+/// Whether the system clock has been set, by SNTP or otherwise.
+pub fn is_synced(now: SystemTime) -> bool {
+    now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() >= MIN_VALID_UNIX_SECS
+}
+
+/// Waits up to `max_wait` for the clock to be set after
+/// `sync_time_with_servers`, returning how long it took. On timeout SNTP keeps
+/// retrying in the background; the caller should carry on with an unsynced
+/// clock rather than fail.
+pub fn wait_for_sync(max_wait: Duration) -> anyhow::Result<Duration> {
+    let start = Instant::now();
+    while !is_synced(SystemTime::now()) {
+        if start.elapsed() >= max_wait {
+            anyhow::bail!("Time not synced after {:?}", max_wait);
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+
+    let took = start.elapsed();
+    log::info!("Time synced in {:?}", took);
+    Ok(took)
+}
+
+/// Formats `now` as `HH:MM` at `utc_offset_minutes` from UTC, or `--:--` while
+/// the clock hasn't been synced.
+pub fn format_clock(now: SystemTime, utc_offset_minutes: i32) -> String {
+    if !is_synced(now) {
+        return "--:--".to_string();
+    }
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    let minutes = (secs / 60) as i64 + utc_offset_minutes as i64;
+    let minute_of_day = minutes.rem_euclid(24 * 60);
+    format!("{:02}:{:02}", minute_of_day / 60, minute_of_day % 60)
+}
+
+/// Whether `now` falls in the daily window from `start_hour` to `end_hour`
+/// (local, 0..=23, end exclusive), which may wrap past midnight. Always false
+/// while the clock hasn't been synced.
+pub fn in_hours(now: SystemTime, utc_offset_minutes: i32, start_hour: u8, end_hour: u8) -> bool {
+    if !is_synced(now) {
+        return false;
+    }
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let minutes = (secs / 60) as i64 + utc_offset_minutes as i64;
+    let hour = (minutes.rem_euclid(24 * 60) / 60) as u8;
+
+    if start_hour <= end_hour {
+        (start_hour..end_hour).contains(&hour)
+    } else {
+        hour >= start_hour || hour < end_hour
+    }
+}
+
+/// Time until the next full minute, for refreshing a displayed clock.
+pub fn until_next_minute(now: SystemTime) -> Duration {
+    let into_minute = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        % 60_000;
+    Duration::from_millis((60_000 - into_minute) as u64)
+}
+
+#[test]
+fn test_format_clock() {
+    let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+
+    assert_eq!(format_clock(at(0), 480), "--:--");
+    assert_eq!(format_clock(at(12345), 0), "--:--");
+    assert!(!is_synced(at(MIN_VALID_UNIX_SECS - 1)));
+    assert!(is_synced(at(MIN_VALID_UNIX_SECS)));
+
+    // 2025-01-01 00:00:00 UTC
+    let t = 1_735_689_600;
+    assert_eq!(format_clock(at(t), 0), "00:00");
+    assert_eq!(format_clock(at(t), 480), "08:00");
+    assert_eq!(format_clock(at(t + 59), 0), "00:00");
+    assert_eq!(format_clock(at(t + 15 * 3600 + 30 * 60), 480), "23:30");
+    assert_eq!(format_clock(at(t + 16 * 3600 + 5 * 60), 480), "00:05");
+    assert_eq!(format_clock(at(t), -300), "19:00");
+    assert_eq!(format_clock(at(t), 330), "05:30");
+}
+
+#[test]
+fn test_until_next_minute() {
+    let at = |ms: u64| UNIX_EPOCH + Duration::from_millis(ms);
+    assert_eq!(until_next_minute(at(0)), Duration::from_secs(60));
+    assert_eq!(until_next_minute(at(59_000)), Duration::from_secs(1));
+    assert_eq!(until_next_minute(at(60_500)), Duration::from_millis(59_500));
+}
+
+#[test]
+fn test_in_hours() {
+    // 2025-01-01 00:00:00 UTC
+    let at = |hour: u64| UNIX_EPOCH + Duration::from_secs(1_735_689_600 + hour * 3600);
+
+    assert!(in_hours(at(22), 0, 22, 7));
+    assert!(in_hours(at(3), 0, 22, 7));
+    assert!(!in_hours(at(7), 0, 22, 7));
+    assert!(!in_hours(at(12), 0, 22, 7));
+
+    assert!(in_hours(at(13), 0, 13, 14));
+    assert!(!in_hours(at(14), 0, 13, 14));
+    assert!(!in_hours(at(13), 0, 13, 13));
+
+    // 14:00 UTC is 22:00 at UTC+8
+    assert!(in_hours(at(14), 480, 22, 7));
+    assert!(!in_hours(UNIX_EPOCH, 0, 0, 23));
+}
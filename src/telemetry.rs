@@ -0,0 +1,236 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use serde::Serialize;
+
+/// What `main_work` is doing, as last published with `set_state`.
+static STATE: Mutex<&'static str> = Mutex::new(IDLE);
+
+/// Recent conversation events, oldest first, see `log_turn`.
+static TURN_LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Entries kept in `TURN_LOG`; older ones are dropped.
+const TURN_LOG_CAPACITY: usize = 64;
+
+pub const IDLE: &str = "idle";
+pub const LISTENING: &str = "listening";
+pub const WAITING: &str = "waiting";
+pub const SPEAKING: &str = "speaking";
+
+/// One sample as POSTed (JSON) to `{server}/api/devices/telemetry`, where
+/// `{server}` is the HTTP origin of the websocket server URL.
+#[derive(Debug, Serialize)]
+pub struct TelemetryReport {
+    #[serde(flatten)]
+    pub ident: crate::network::DeviceIdent,
+    pub uptime_secs: u64,
+    pub free_internal_heap: usize,
+    pub free_spiram_heap: usize,
+    /// RSSI of the connected AP in dBm, `null` if it couldn't be read.
+    pub rssi: Option<i8>,
+    /// Battery voltage in millivolts, `null` on boards that can't measure it.
+    pub battery_mv: Option<u32>,
+    /// One of `IDLE`, `LISTENING`, `WAITING` or `SPEAKING`.
+    pub state: &'static str,
+    /// Short I2S reads since boot; the chunk is dropped each time.
+    pub i2s_read_mismatches: u32,
+}
+
+pub fn set_state(state: &'static str) {
+    if let Ok(mut s) = STATE.lock() {
+        *s = state;
+    }
+}
+
+fn state() -> &'static str {
+    STATE.lock().map(|s| *s).unwrap_or(IDLE)
+}
+
+/// Appends `entry` to the in-RAM turn log, stamped with the uptime, for field
+/// debugging without a serial console. `main_work` logs state changes, ASR
+/// transcript lengths (not the text), playback speeds and errors; see
+/// `turn_log` for reading it back.
+pub fn log_turn(entry: impl std::fmt::Display) {
+    let uptime_ms = unsafe { esp_idf_svc::sys::esp_timer_get_time() / 1000 };
+    let entry = format!("{}.{:03} {}", uptime_ms / 1000, uptime_ms % 1000, entry);
+    if let Ok(mut log) = TURN_LOG.lock() {
+        push_bounded(&mut log, entry, TURN_LOG_CAPACITY);
+    }
+}
+
+/// The turn log, oldest entry first.
+pub fn turn_log() -> Vec<String> {
+    TURN_LOG
+        .lock()
+        .map(|log| log.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn push_bounded(log: &mut VecDeque<String>, entry: String, capacity: usize) {
+    while log.len() >= capacity {
+        log.pop_front();
+    }
+    log.push_back(entry);
+}
+
+pub fn battery_mv() -> Option<u32> {
+    #[cfg(feature = "exio")]
+    return crate::boards::battery_mv();
+    #[cfg(not(feature = "exio"))]
+    None
+}
+
+/// Starts the thread that sends a sample every `interval`.
+///
+/// Samples are skipped while a conversation turn is in progress (any state but
+/// `IDLE`), so the HTTP request doesn't contend with the websocket. Failed
+/// samples are not retried; the next one replaces them. The HTTP connection is
+/// kept open between samples.
+pub fn start(
+    device_id: String,
+    server_url: &str,
+    interval: std::time::Duration,
+) -> anyhow::Result<()> {
+    let base = crate::network::convert_ws_to_http(server_url)
+        .ok_or_else(|| anyhow::anyhow!("Invalid server URL for telemetry: {}", server_url))?;
+    let url = format!("{}/api/devices/telemetry", base);
+    let ident = crate::network::DeviceIdent::new(device_id);
+
+    crate::network::spawn_periodic_post(
+        "telemetry",
+        8 * 1024,
+        move || interval,
+        url,
+        move |_| {
+            let state = state();
+            if state != IDLE {
+                log::debug!("Skipping telemetry while {}", state);
+                return None;
+            }
+
+            Some(unsafe {
+                use esp_idf_svc::sys::{
+                    esp_timer_get_time, heap_caps_get_free_size, MALLOC_CAP_INTERNAL,
+                    MALLOC_CAP_SPIRAM,
+                };
+
+                TelemetryReport {
+                    ident: ident.clone(),
+                    uptime_secs: (esp_timer_get_time() / 1_000_000) as u64,
+                    free_internal_heap: heap_caps_get_free_size(MALLOC_CAP_INTERNAL),
+                    free_spiram_heap: heap_caps_get_free_size(MALLOC_CAP_SPIRAM),
+                    rssi: crate::network::rssi(),
+                    battery_mv: battery_mv(),
+                    state,
+                    i2s_read_mismatches: crate::audio::read_mismatches(),
+                }
+            })
+        },
+    )
+}
+
+/// One liveness ping as POSTed (JSON) to `{server}/api/devices/heartbeat`.
+/// Unlike `TelemetryReport` it is kept minimal, so it can be sent often.
+#[derive(Debug, Serialize)]
+pub struct Heartbeat {
+    pub device_id: String,
+    pub uptime_secs: u64,
+}
+
+/// Battery readings below this are taken as running on battery; on USB the
+/// charger holds the cell near full.
+const ON_BATTERY_BELOW_MV: u32 = 4100;
+
+/// Heartbeats are this many times further apart while on battery.
+const HEARTBEAT_BATTERY_BACKOFF: u32 = 4;
+
+/// Time until the next heartbeat: `interval`, or backed off while on battery.
+/// Boards that can't measure the battery always use `interval`.
+fn heartbeat_interval(
+    interval: std::time::Duration,
+    battery_mv: Option<u32>,
+) -> std::time::Duration {
+    match battery_mv {
+        Some(mv) if mv < ON_BATTERY_BELOW_MV => interval * HEARTBEAT_BATTERY_BACKOFF,
+        _ => interval,
+    }
+}
+
+/// Starts the thread that sends a `Heartbeat` every `interval`, in any state,
+/// so the server sees an idle device is alive without websocket traffic.
+/// Failed pings are only logged. The HTTP connection is kept open between pings.
+pub fn start_heartbeat(
+    device_id: String,
+    server_url: &str,
+    interval: std::time::Duration,
+) -> anyhow::Result<()> {
+    let base = crate::network::convert_ws_to_http(server_url)
+        .ok_or_else(|| anyhow::anyhow!("Invalid server URL for heartbeat: {}", server_url))?;
+    let url = format!("{}/api/devices/heartbeat", base);
+
+    crate::network::spawn_periodic_post(
+        "heartbeat",
+        8 * 1024,
+        move || heartbeat_interval(interval, battery_mv()),
+        url,
+        move |_| {
+            Some(Heartbeat {
+                device_id: device_id.clone(),
+                uptime_secs: unsafe { esp_idf_svc::sys::esp_timer_get_time() / 1_000_000 } as u64,
+            })
+        },
+    )
+}
+
+#[test]
+fn test_telemetry_report_schema() {
+    let report = TelemetryReport {
+        ident: crate::network::DeviceIdent::new("a1b2c3d4e5f6".to_string()),
+        uptime_secs: 3600,
+        free_internal_heap: 65536,
+        free_spiram_heap: 4 * 1024 * 1024,
+        rssi: Some(-58),
+        battery_mv: Some(3900),
+        state: IDLE,
+        i2s_read_mismatches: 3,
+    };
+
+    assert_eq!(
+        crate::network::report_fields(&report),
+        serde_json::json!({
+            "uptime_secs": 3600,
+            "free_internal_heap": 65536,
+            "free_spiram_heap": 4194304,
+            "rssi": -58,
+            "battery_mv": 3900,
+            "state": "idle",
+            "i2s_read_mismatches": 3,
+        })
+    );
+}
+
+#[test]
+fn test_heartbeat() {
+    let json = serde_json::to_value(&Heartbeat {
+        device_id: "a1b2c3d4e5f6".to_string(),
+        uptime_secs: 42,
+    })
+    .unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({ "device_id": "a1b2c3d4e5f6", "uptime_secs": 42 })
+    );
+
+    let minute = std::time::Duration::from_secs(60);
+    assert_eq!(heartbeat_interval(minute, None), minute);
+    assert_eq!(heartbeat_interval(minute, Some(4150)), minute);
+    assert_eq!(heartbeat_interval(minute, Some(3800)), minute * 4);
+}
+
+#[test]
+fn test_push_bounded() {
+    let mut log = VecDeque::new();
+    for i in 0..5 {
+        push_bounded(&mut log, i.to_string(), 3);
+    }
+    assert_eq!(log, ["2", "3", "4"]);
+}
@@ -1,17 +1,57 @@
 use embedded_graphics::{
     image::GetPixel,
-    pixelcolor::Rgb565,
     prelude::*,
-    primitives::{PrimitiveStyle, Rectangle},
+    primitives::{Circle, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
     text::renderer::{CharacterStyle, TextRenderer},
 };
 use u8g2_fonts::U8g2TextStyle;
 
-pub type ColorFormat = Rgb565;
+/// Pixel format of the display, chosen by the board (`boards::ColorFormat`).
+/// Rgb565 on the boards so far; a panel with more colour depth can use e.g.
+/// Rgb888 instead, everything drawn here follows.
+pub type ColorFormat = crate::boards::ColorFormat;
+/// Storage type of `ColorFormat` in the framebuffer.
+pub type ColorRaw = <ColorFormat as PixelColor>::Raw;
+/// Bits per pixel of `ColorFormat`.
+const COLOR_BITS: usize = <ColorRaw as embedded_graphics::pixelcolor::raw::RawData>::BITS_PER_PIXEL;
+/// Bytes per pixel sent to the panel.
+pub const COLOR_WIDTH: usize = COLOR_BITS.div_ceil(8);
+
+// `init_lcd` only sets panels up for 16-bit (Rgb565) or 24-bit (Rgb888) pixels
+const _: () = assert!(
+    COLOR_BITS == 16 || COLOR_BITS == 24,
+    "boards::ColorFormat must be a 16- or 24-bit format"
+);
+
+/// Scales 8-bit channels (decoded images) down to the depth of `ColorFormat`.
+pub fn from_rgb8(r: u8, g: u8, b: u8) -> ColorFormat {
+    ColorFormat::new(
+        r / (u8::MAX / ColorFormat::MAX_R),
+        g / (u8::MAX / ColorFormat::MAX_G),
+        b / (u8::MAX / ColorFormat::MAX_B),
+    )
+}
+
+#[test]
+fn test_from_rgb8() {
+    assert_eq!(from_rgb8(255, 255, 255), ColorFormat::WHITE);
+    assert_eq!(from_rgb8(0, 0, 0), ColorFormat::BLACK);
+    assert_eq!(from_rgb8(255, 0, 0), ColorFormat::RED);
+}
 
 pub const DEFAULT_BACKGROUND: &[u8] = include_bytes!("../assets/echokit.gif");
 // pub const DEFAULT_BACKGROUND: &[u8] = include_bytes!("../assets/ht.gif");
 
+/// Upper bound on GIF playback rate. Frames whose delay is shorter than
+/// `1000 / GIF_MAX_FPS` ms are held for that long instead, so a GIF authored
+/// with tiny delays can't keep the display thread busy redrawing and flushing.
+pub static mut GIF_MAX_FPS: u32 = 15;
+
+fn gif_frame_interval(delay: std::time::Duration) -> std::time::Duration {
+    let max_fps = unsafe { GIF_MAX_FPS }.max(1);
+    delay.max(std::time::Duration::from_millis(1000 / max_fps as u64))
+}
+
 // TextRenderer + CharacterStyle
 #[derive(Debug, Clone)]
 pub struct MyTextStyle(pub U8g2TextStyle<ColorFormat>, pub i32);
@@ -95,17 +135,89 @@ pub trait DisplayTargetDrive:
     fn fill_color(&mut self, color: ColorFormat) -> anyhow::Result<()>;
     fn flush(&mut self) -> anyhow::Result<()>;
     fn fix_background(&mut self) -> anyhow::Result<()>;
+    /// Sends the whole screen, where `flush` may only send what changed.
+    fn force_flush(&mut self) -> anyhow::Result<()>;
+}
+
+/// Checks that `gif` has a valid header, fits in `area` (the display for a
+/// background, `avatar_size` for an avatar) and has a first frame that decodes,
+/// so a broken upload is rejected before it is saved.
+pub fn validate_gif(gif: &[u8], area: Size) -> anyhow::Result<()> {
+    use image::{AnimationDecoder, ImageDecoder};
+    let img_gif = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(gif))?;
+    let (width, height) = img_gif.dimensions();
+    check_gif_size(Size::new(width, height), area)?;
+    match img_gif.into_frames().next() {
+        Some(frame) => {
+            frame?;
+            Ok(())
+        }
+        None => Err(anyhow::anyhow!("GIF has no frames")),
+    }
+}
+
+/// Smaller GIFs are shown centered. Larger ones would be cropped (see
+/// `draw_gif_frame`), so they are refused.
+fn check_gif_size(size: Size, area: Size) -> anyhow::Result<()> {
+    if size.width == 0 || size.height == 0 {
+        return Err(anyhow::anyhow!(
+            "GIF is empty ({}x{})",
+            size.width,
+            size.height
+        ));
+    }
+    if size.width > area.width || size.height > area.height {
+        return Err(anyhow::anyhow!(
+            "GIF is {}x{}, larger than {}x{}",
+            size.width,
+            size.height,
+            area.width,
+            area.height
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_gif_size() {
+    let screen = Size::new(320, 240);
+    assert!(check_gif_size(screen, screen).is_ok());
+    assert!(check_gif_size(Size::new(240, 240), screen).is_ok());
+    assert!(check_gif_size(Size::new(0, 240), screen).is_err());
+    let e = check_gif_size(Size::new(320, 320), screen).unwrap_err();
+    assert_eq!(e.to_string(), "GIF is 320x320, larger than 320x240");
+
+    // avatars are checked against the avatar area, not the screen
+    let avatar = Size::new(avatar_size(screen), avatar_size(screen));
+    assert!(check_gif_size(Size::new(96, 96), avatar).is_ok());
+    assert!(check_gif_size(Size::new(240, 240), avatar).is_err());
 }
 
 pub fn display_gif<D: DisplayTargetDrive>(
     display_target: &mut D,
     gif: &[u8],
 ) -> anyhow::Result<()> {
+    display_gif_until(display_target, gif, || false)?;
+    Ok(())
+}
+
+/// How often `display_gif_until` checks `stop` while a frame is shown.
+const GIF_STOP_POLL: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// Plays `gif` once like `display_gif`, but checks `stop` while each frame is
+/// shown. Once it returns true, the next frame is shown as the background and
+/// playback ends. Returns whether it was stopped.
+pub fn display_gif_until<D: DisplayTargetDrive>(
+    display_target: &mut D,
+    gif: &[u8],
+    mut stop: impl FnMut() -> bool,
+) -> anyhow::Result<bool> {
     use image::AnimationDecoder;
     let img_gif = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(gif))?;
 
     let mut frames = img_gif.into_frames();
     let mut ff = frames.next();
+    let mut stopped = false;
 
     loop {
         if ff.is_none() {
@@ -113,44 +225,197 @@ pub fn display_gif<D: DisplayTargetDrive>(
         }
 
         let frame = ff.unwrap()?;
+        let delay = draw_gif_frame(display_target, frame)?;
 
-        let delay = frame.delay();
+        let now = std::time::Instant::now();
+        // once stopped, the frame just drawn is the last one
+        ff = if stopped { None } else { frames.next() };
+        if ff.is_none() {
+            display_target.fix_background()?;
+        }
 
-        let img = frame.into_buffer();
-        let pixels = img.enumerate_pixels().map(|(x, y, p)| {
-            let (x, y) = if p[3] == 0 {
-                (-1, -1)
-            } else {
-                (x as i32, y as i32)
-            };
+        display_target.flush()?;
+
+        while !stopped && now.elapsed() < delay {
+            stopped = stop();
+            let left = delay.saturating_sub(now.elapsed());
+            std::thread::sleep(left.min(GIF_STOP_POLL));
+        }
+    }
+
+    Ok(stopped)
+}
 
+/// Draws `frame` centered without flushing and returns how long to show it for.
+/// A frame larger than the display is cropped to its middle.
+fn draw_gif_frame<D: DisplayTargetDrive>(
+    display_target: &mut D,
+    frame: image::Frame,
+) -> anyhow::Result<std::time::Duration> {
+    let delay = frame.delay();
+
+    let img = frame.into_buffer();
+    let screen = display_target.bounding_box();
+    let offset = centered(screen.size, Size::new(img.width(), img.height()));
+    let pixels = img
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p[3] != 0)
+        .map(|(x, y, p)| {
             Pixel(
-                Point { x, y },
-                ColorFormat::new(
-                    p[0] / (u8::MAX / ColorFormat::MAX_R),
-                    p[1] / (u8::MAX / ColorFormat::MAX_G),
-                    p[2] / (u8::MAX / ColorFormat::MAX_B),
-                ),
+                offset + Point::new(x as i32, y as i32),
+                from_rgb8(p[0], p[1], p[2]),
             )
-        });
+        })
+        .filter(|Pixel(point, _)| screen.contains(*point));
 
-        display_target
-            .draw_iter(pixels)
-            .map_err(|_| anyhow::anyhow!("Failed to draw GIF frame"))?;
+    display_target
+        .draw_iter(pixels)
+        .map_err(|_| anyhow::anyhow!("Failed to draw GIF frame"))?;
 
-        let now = std::time::Instant::now();
-        ff = frames.next();
-        if ff.is_none() {
-            display_target.fix_background()?;
+    Ok(gif_frame_interval(std::time::Duration::from(delay)))
+}
+
+/// GIF data shared between a `GifLoop` and the decoders it restarts.
+struct GifData(std::rc::Rc<Vec<u8>>);
+
+impl AsRef<[u8]> for GifData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Plays a GIF over and over, one frame per `step`, so the caller's event loop
+/// decides when to draw and can stop at any frame instead of blocking like
+/// `display_gif`.
+pub struct GifLoop {
+    gif: std::rc::Rc<Vec<u8>>,
+    frames: image::Frames<'static>,
+    next_at: std::time::Instant,
+}
+
+impl GifLoop {
+    pub fn new(gif: Vec<u8>) -> anyhow::Result<Self> {
+        let gif = std::rc::Rc::new(gif);
+        let frames = Self::decode(&gif)?;
+        Ok(Self {
+            gif,
+            frames,
+            next_at: std::time::Instant::now(),
+        })
+    }
+
+    fn decode(gif: &std::rc::Rc<Vec<u8>>) -> anyhow::Result<image::Frames<'static>> {
+        use image::AnimationDecoder;
+        let cursor = std::io::Cursor::new(GifData(gif.clone()));
+        Ok(image::codecs::gif::GifDecoder::new(cursor)?.into_frames())
+    }
+
+    /// Time until the next frame is due, zero if it already is.
+    pub fn until_next(&self, now: std::time::Instant) -> std::time::Duration {
+        self.next_at.saturating_duration_since(now)
+    }
+
+    /// Draws the next frame, without flushing, if it is due. Returns whether
+    /// one was drawn. Starts over from the first frame after the last one.
+    pub fn step<D: DisplayTargetDrive>(
+        &mut self,
+        display_target: &mut D,
+        now: std::time::Instant,
+    ) -> anyhow::Result<bool> {
+        if now < self.next_at {
+            return Ok(false);
         }
 
-        display_target.flush()?;
+        let frame = match self.frames.next() {
+            Some(frame) => frame?,
+            None => {
+                self.frames = Self::decode(&self.gif)?;
+                self.frames
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("GIF has no frames"))??
+            }
+        };
+        let delay = draw_gif_frame(display_target, frame)?;
+        self.next_at = now + delay;
+        Ok(true)
+    }
+}
+
+/// Largest encoded `ServerEvent::VideoChunk` frame accepted.
+pub const MAX_VIDEO_FRAME_BYTES: usize = 64 * 1024;
+
+/// Where an image of `size` goes to be centered on `screen`. Negative for an
+/// image larger than the screen, which then loses as much on either side.
+fn centered(screen: Size, size: Size) -> Point {
+    Point::new(
+        (screen.width as i32 - size.width as i32) / 2,
+        (screen.height as i32 - size.height as i32) / 2,
+    )
+}
 
-        let delay = std::time::Duration::from(delay);
+#[test]
+fn test_centered() {
+    let screen = Size::new(320, 240);
+    assert_eq!(centered(screen, screen), Point::zero());
+    assert_eq!(centered(screen, Size::new(160, 120)), Point::new(80, 60));
+    assert_eq!(centered(screen, Size::new(1, 1)), Point::new(159, 119));
+    assert_eq!(centered(screen, Size::new(400, 240)), Point::new(-40, 0));
+}
 
-        std::thread::sleep(std::time::Instant::now() - (now + delay));
+/// Blacks out the screen for video, without flushing. Like everything drawn
+/// without `fix_background`, it is gone after the next flush.
+pub fn draw_video_blank<D: DisplayTargetDrive>(display_target: &mut D) -> anyhow::Result<()> {
+    display_target
+        .bounding_box()
+        .into_styled(PrimitiveStyle::with_fill(ColorFormat::BLACK))
+        .draw(display_target)
+        .map_err(|_| anyhow::anyhow!("Failed to clear the screen for video"))
+}
+
+/// Decodes a video frame (PNG, WebP, or the first frame of a GIF) and draws it
+/// centered on black, without flushing. Frames over `MAX_VIDEO_FRAME_BYTES` or larger
+/// than the screen are rejected before they are decoded, so a bad frame can't
+/// take the memory the framebuffers need.
+pub fn draw_video_frame<D: DisplayTargetDrive>(
+    display_target: &mut D,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    if data.len() > MAX_VIDEO_FRAME_BYTES {
+        anyhow::bail!(
+            "Video frame of {} bytes is over the {} byte limit",
+            data.len(),
+            MAX_VIDEO_FRAME_BYTES
+        );
     }
 
+    let screen = display_target.bounding_box().size;
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(screen.width);
+    limits.max_image_height = Some(screen.height);
+    // the decoded RGBA frame plus some room for the decoder
+    limits.max_alloc = Some(2 * 4 * screen.width as u64 * screen.height as u64);
+
+    let mut reader = image::ImageReader::new(std::io::Cursor::new(data)).with_guessed_format()?;
+    reader.limits(limits);
+    let img = reader.decode()?.to_rgba8();
+
+    draw_video_blank(display_target)?;
+
+    let offset = centered(screen, Size::new(img.width(), img.height()));
+    let pixels = img
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p[3] != 0)
+        .map(|(x, y, p)| {
+            Pixel(
+                offset + Point::new(x as i32, y as i32),
+                from_rgb8(p[0], p[1], p[2]),
+            )
+        });
+
+    display_target
+        .draw_iter(pixels)
+        .map_err(|_| anyhow::anyhow!("Failed to draw video frame"))?;
+
     Ok(())
 }
 
@@ -164,16 +429,9 @@ pub fn display_png<D: DisplayTargetDrive>(
 
     let img = img_reader.decode().unwrap().to_rgb8();
 
-    let p = img.enumerate_pixels().map(|(x, y, p)| {
-        Pixel(
-            Point::new(x as i32, y as i32),
-            ColorFormat::new(
-                p[0] / (u8::MAX / ColorFormat::MAX_R),
-                p[1] / (u8::MAX / ColorFormat::MAX_G),
-                p[2] / (u8::MAX / ColorFormat::MAX_B),
-            ),
-        )
-    });
+    let p = img
+        .enumerate_pixels()
+        .map(|(x, y, p)| Pixel(Point::new(x as i32, y as i32), from_rgb8(p[0], p[1], p[2])));
 
     display_target
         .draw_iter(p)
@@ -268,6 +526,291 @@ pub fn get_background_pixels<T: GetPixel<Color = ColorFormat>>(
         .collect()
 }
 
+/// Accessibility high-contrast mode for the chat UI, loaded from NVS at boot.
+///
+/// When enabled the state and content areas are filled with solid black
+/// instead of being alpha-blended over the background GIF, all chat text is
+/// pure white, and the state line uses `wqy16` instead of `wqy12`. The content
+/// text already uses `wqy16`, the largest gb2312 font we ship, so its size is
+/// unchanged.
+pub static mut HIGH_CONTRAST: bool = false;
+
+fn high_contrast() -> bool {
+    unsafe { HIGH_CONTRAST }
+}
+
+/// Height of the state bar at the top of the chat screen, sized for
+/// `state_text_style`.
+pub const STATE_BAR_HEIGHT: u32 = 32;
+
+/// Side of the square chat avatar: 2/5 of the shorter screen side, 96 px on
+/// the 240 px high panels.
+pub fn avatar_size(screen: Size) -> u32 {
+    screen.width.min(screen.height) * 2 / 5
+}
+
+#[test]
+fn test_avatar_size() {
+    // the areas around it are checked per board, see `boards::ui::test_chat_layout`
+    for screen in [Size::new(240, 240), Size::new(320, 240)] {
+        assert_eq!(avatar_size(screen), 96);
+    }
+}
+
+pub fn state_text_style() -> U8g2TextStyle<ColorFormat> {
+    if high_contrast() {
+        U8g2TextStyle::new(
+            u8g2_fonts::fonts::u8g2_font_wqy16_t_gb2312,
+            ColorFormat::WHITE,
+        )
+    } else {
+        U8g2TextStyle::new(
+            u8g2_fonts::fonts::u8g2_font_wqy12_t_gb2312a,
+            ColorFormat::CSS_LIGHT_CYAN,
+        )
+    }
+}
+
+pub fn content_text_style() -> MyTextStyle {
+    let color = if high_contrast() {
+        ColorFormat::WHITE
+    } else {
+        ColorFormat::CSS_WHEAT
+    };
+
+    MyTextStyle(
+        U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_wqy16_t_gb2312, color),
+        3,
+    )
+}
+
+pub fn state_background_pixels<T: GetPixel<Color = ColorFormat>>(
+    display: &T,
+    area: Rectangle,
+) -> Vec<Pixel<ColorFormat>> {
+    let (color, alpha) = if high_contrast() {
+        (ColorFormat::BLACK, 1.0)
+    } else {
+        (ColorFormat::CSS_DARK_BLUE, 0.5)
+    };
+
+    let style = PrimitiveStyleBuilder::new()
+        .stroke_color(color)
+        .stroke_width(1)
+        .fill_color(color)
+        .build();
+    get_background_pixels(display, area, style, alpha)
+}
+
+pub fn content_background_pixels<T: GetPixel<Color = ColorFormat>>(
+    display: &T,
+    area: Rectangle,
+) -> Vec<Pixel<ColorFormat>> {
+    let alpha = if high_contrast() { 1.0 } else { 0.5 };
+
+    let style = PrimitiveStyleBuilder::new()
+        .stroke_color(ColorFormat::CSS_BLACK)
+        .stroke_width(5)
+        .fill_color(ColorFormat::CSS_BLACK)
+        .build();
+    get_background_pixels(display, area, style, alpha)
+}
+
+/// Shown while voice interrupt is enabled: an 8px filled dot at the right end
+/// of the state band, vertically centered and 6px from the edge, outside the
+/// width used by centered state messages.
+pub fn draw_interrupt_indicator<D: DrawTarget<Color = ColorFormat>>(
+    target: &mut D,
+    state_area: Rectangle,
+) -> Result<(), D::Error> {
+    const DIAMETER: u32 = 8;
+    const MARGIN: i32 = 6;
+
+    let color = if high_contrast() {
+        ColorFormat::WHITE
+    } else {
+        ColorFormat::CSS_ORANGE
+    };
+    let top_left = Point::new(
+        state_area.top_left.x + state_area.size.width as i32 - DIAMETER as i32 - MARGIN,
+        state_area.center().y - DIAMETER as i32 / 2,
+    );
+    Circle::new(top_left, DIAMETER)
+        .into_styled(PrimitiveStyle::with_fill(color))
+        .draw(target)
+}
+
+/// Connection step shown next to the state text during boot, see
+/// `draw_status_icon`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusIcon {
+    Connecting,
+    Connected,
+    Error,
+}
+
+/// Draws `icon` at the left of the state area, right of the battery indicator:
+/// an open ring for connecting, a check for connected and a cross for errors.
+pub fn draw_status_icon<D: DrawTarget<Color = ColorFormat>>(
+    target: &mut D,
+    state_area: Rectangle,
+    icon: StatusIcon,
+) -> Result<(), D::Error> {
+    use embedded_graphics::primitives::{Arc, Line};
+
+    const SIZE: i32 = 10;
+    // battery indicator and its margins
+    const LEFT: i32 = 6 + 22 + 6;
+
+    let color = if high_contrast() {
+        ColorFormat::WHITE
+    } else {
+        match icon {
+            StatusIcon::Connecting => ColorFormat::CSS_LIGHT_SKY_BLUE,
+            StatusIcon::Connected => ColorFormat::CSS_LIGHT_GREEN,
+            StatusIcon::Error => ColorFormat::CSS_RED,
+        }
+    };
+    let style = PrimitiveStyle::with_stroke(color, 2);
+    let top_left = Point::new(
+        state_area.top_left.x + LEFT,
+        state_area.center().y - SIZE / 2,
+    );
+    let at = |x, y| top_left + Point::new(x, y);
+
+    match icon {
+        StatusIcon::Connecting => Arc::new(top_left, SIZE as u32, 0.0f32.deg(), 270.0f32.deg())
+            .into_styled(style)
+            .draw(target),
+        StatusIcon::Connected => {
+            Line::new(at(0, SIZE / 2), at(SIZE / 3, SIZE))
+                .into_styled(style)
+                .draw(target)?;
+            Line::new(at(SIZE / 3, SIZE), at(SIZE, 0))
+                .into_styled(style)
+                .draw(target)
+        }
+        StatusIcon::Error => {
+            Line::new(at(0, 0), at(SIZE, SIZE))
+                .into_styled(style)
+                .draw(target)?;
+            Line::new(at(0, SIZE), at(SIZE, 0))
+                .into_styled(style)
+                .draw(target)
+        }
+    }
+}
+
+/// Signal bars shown by `draw_wifi_indicator`: 0 while not connected, 1 below
+/// -75 dBm, then one more at -75, -65 and -55 dBm.
+pub fn wifi_bars(rssi: Option<i8>) -> u8 {
+    match rssi {
+        None => 0,
+        Some(rssi) if rssi >= -55 => 4,
+        Some(rssi) if rssi >= -65 => 3,
+        Some(rssi) if rssi >= -75 => 2,
+        Some(_) => 1,
+    }
+}
+
+#[test]
+fn test_wifi_bars() {
+    assert_eq!(wifi_bars(None), 0);
+    assert_eq!(wifi_bars(Some(-40)), 4);
+    assert_eq!(wifi_bars(Some(-55)), 4);
+    assert_eq!(wifi_bars(Some(-56)), 3);
+    assert_eq!(wifi_bars(Some(-70)), 2);
+    assert_eq!(wifi_bars(Some(-80)), 1);
+    assert_eq!(wifi_bars(Some(-100)), 1);
+}
+
+/// Draws `bars` rising signal bars left of the interrupt indicator, with
+/// outlines for the missing ones; all outlines in red when not connected.
+pub fn draw_wifi_indicator<D: DrawTarget<Color = ColorFormat>>(
+    target: &mut D,
+    state_area: Rectangle,
+    bars: u8,
+) -> Result<(), D::Error> {
+    const BAR_WIDTH: u32 = 3;
+    const HEIGHT: u32 = 10;
+    // right of the bars: interrupt indicator and its margins
+    const RIGHT: i32 = 6 + 8 + 6;
+
+    let color = if high_contrast() {
+        ColorFormat::WHITE
+    } else if bars == 0 {
+        ColorFormat::CSS_RED
+    } else {
+        ColorFormat::CSS_LIGHT_GREEN
+    };
+    let left = state_area.top_left.x + state_area.size.width as i32 - RIGHT - 4 * 4;
+    let bottom = state_area.center().y + HEIGHT as i32 / 2;
+
+    for i in 0..4 {
+        let height = 4 + 2 * i as u32;
+        let bar = Rectangle::new(
+            Point::new(left + i * 4, bottom - height as i32),
+            Size::new(BAR_WIDTH, height),
+        );
+        let style = if i < bars as i32 {
+            PrimitiveStyle::with_fill(color)
+        } else {
+            PrimitiveStyle::with_stroke(color, 1)
+        };
+        bar.into_styled(style).draw(target)?;
+    }
+    Ok(())
+}
+
+/// Battery levels shown by `draw_battery_indicator`: 0 (empty) to 4 bars.
+pub fn battery_bucket(percent: u8) -> u8 {
+    (percent.min(100) + 12) / 25
+}
+
+/// Draws a battery with `bucket` bars at the left of the state area.
+pub fn draw_battery_indicator<D: DrawTarget<Color = ColorFormat>>(
+    target: &mut D,
+    state_area: Rectangle,
+    bucket: u8,
+) -> Result<(), D::Error> {
+    const WIDTH: u32 = 20;
+    const HEIGHT: u32 = 10;
+    const MARGIN: i32 = 6;
+
+    let color = if high_contrast() {
+        ColorFormat::WHITE
+    } else if bucket == 0 {
+        ColorFormat::CSS_RED
+    } else {
+        ColorFormat::CSS_LIGHT_GREEN
+    };
+    let top_left = Point::new(
+        state_area.top_left.x + MARGIN,
+        state_area.center().y - HEIGHT as i32 / 2,
+    );
+
+    Rectangle::new(top_left, Size::new(WIDTH, HEIGHT))
+        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+        .draw(target)?;
+    // terminal
+    Rectangle::new(
+        top_left + Point::new(WIDTH as i32, HEIGHT as i32 / 2 - 2),
+        Size::new(2, 4),
+    )
+    .into_styled(PrimitiveStyle::with_fill(color))
+    .draw(target)?;
+
+    for i in 0..bucket.min(4) as i32 {
+        Rectangle::new(
+            top_left + Point::new(2 + i * 4, 2),
+            Size::new(3, HEIGHT - 4),
+        )
+        .into_styled(PrimitiveStyle::with_fill(color))
+        .draw(target)?;
+    }
+    Ok(())
+}
+
 pub struct ImageArea {
     pub image_data: Vec<Pixel<ColorFormat>>,
 }
@@ -295,11 +838,7 @@ impl ImageArea {
             }
             pixels.push(Pixel(
                 Point::new(area.top_left.x + x as i32, area.top_left.y + y as i32),
-                ColorFormat::new(
-                    p[0] / (u8::MAX / ColorFormat::MAX_R),
-                    p[1] / (u8::MAX / ColorFormat::MAX_G),
-                    p[2] / (u8::MAX / ColorFormat::MAX_B),
-                ),
+                from_rgb8(p[0], p[1], p[2]),
             ));
         }
 
@@ -348,6 +887,23 @@ impl ImageArea {
     }
 }
 
+/// Opaque pixels of `img` placed at `area`, clipped to its size.
+fn rgba_to_pixels(area: Rectangle, img: &image::RgbaImage) -> Vec<Pixel<ColorFormat>> {
+    let mut pixels = Vec::with_capacity((area.size.width * area.size.height) as usize);
+
+    for (x, y, p) in img.enumerate_pixels() {
+        if x >= area.size.width || y >= area.size.height || p[3] == 0 {
+            continue;
+        }
+        pixels.push(Pixel(
+            Point::new(area.top_left.x + x as i32, area.top_left.y + y as i32),
+            from_rgb8(p[0], p[1], p[2]),
+        ));
+    }
+
+    pixels
+}
+
 pub struct DynamicImage<const N: usize> {
     pub display_index: usize,
     pub image_data: Vec<Vec<Pixel<ColorFormat>>>,
@@ -370,24 +926,7 @@ impl<const N: usize> DynamicImage<N> {
         for ff in frames.take(N) {
             let frame = ff?;
 
-            let img = frame.into_buffer();
-            let mut pixels = Vec::with_capacity((area.size.width * area.size.height) as usize);
-
-            for (x, y, p) in img.enumerate_pixels() {
-                if x >= area.size.width || y >= area.size.height || p[3] == 0 {
-                    continue;
-                }
-                pixels.push(Pixel(
-                    Point::new(area.top_left.x + x as i32, area.top_left.y + y as i32),
-                    ColorFormat::new(
-                        p[0] / (u8::MAX / ColorFormat::MAX_R),
-                        p[1] / (u8::MAX / ColorFormat::MAX_G),
-                        p[2] / (u8::MAX / ColorFormat::MAX_B),
-                    ),
-                ));
-            }
-
-            image_data.push(pixels);
+            image_data.push(rgba_to_pixels(area, &frame.into_buffer()));
         }
 
         Ok(Self {
@@ -396,6 +935,15 @@ impl<const N: usize> DynamicImage<N> {
         })
     }
 
+    /// A still image with a single frame, e.g. one of the emotion avatars.
+    pub fn new_from_png(area: Rectangle, png_data: &[u8]) -> anyhow::Result<Self> {
+        let img = image::load_from_memory_with_format(png_data, image::ImageFormat::Png)?;
+        Ok(Self {
+            display_index: 0,
+            image_data: vec![rgba_to_pixels(area, &img.to_rgba8())],
+        })
+    }
+
     pub fn set_index(&mut self, index: usize) {
         let new_idx = index % N;
         if new_idx == self.display_index {
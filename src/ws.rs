@@ -9,6 +9,15 @@ use crate::{app::Event, protocol::ServerEvent};
 use futures_util::{SinkExt, StreamExt, TryFutureExt};
 use tokio_websockets::Message;
 
+/// A ping that isn't answered (by a pong or any other frame) within this long
+/// means the link is dead.
+const PONG_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Frames with a larger payload are dropped without being parsed, see
+/// `DeviceConfig::ws_max_payload_kb`.
+pub static MAX_PAYLOAD_LEN: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(256 * 1024);
+
 enum SubmitItem {
     JSON(crate::protocol::ClientCommand),
     AudioChunk(Vec<u8>),
@@ -21,19 +30,38 @@ async fn ws_manager(
     >,
     mut rx: tokio::sync::mpsc::Receiver<SubmitItem>,
     tx: tokio::sync::mpsc::Sender<ServerEvent>,
+    ping_interval: Option<std::time::Duration>,
 ) -> anyhow::Result<()> {
     use crate::codec::opus;
     enum SelectItem {
         Recv(Option<Result<Message, tokio_websockets::error::Error>>),
         Send(Option<SubmitItem>),
+        Keepalive,
     }
 
     let mut opus_decoder = opus::Decoder::new(16000, opus::Channels::Mono)?;
     let mut opus_buffer = vec![0i16; 16000 * 12 / 100]; // 120ms buffer
 
+    let mut last_recv = tokio::time::Instant::now();
+    let mut ping_sent_at: Option<tokio::time::Instant> = None;
+
     loop {
+        // ping after `ping_interval` without hearing from the server, then give
+        // it `PONG_TIMEOUT` to answer
+        let keepalive_at = match (ping_interval, ping_sent_at) {
+            (None, _) => None,
+            (Some(_), Some(sent)) => Some(sent + PONG_TIMEOUT),
+            (Some(interval), None) => Some(last_recv + interval),
+        };
+
         let recv_fut = ws.next();
         let send_fut = rx.recv();
+        let keepalive_fut = async move {
+            match keepalive_at {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
         let item = tokio::select! {
             recv = recv_fut => {
                 SelectItem::Recv(recv)
@@ -41,83 +69,96 @@ async fn ws_manager(
             send = send_fut => {
                 SelectItem::Send(send)
             },
+            _ = keepalive_fut => {
+                SelectItem::Keepalive
+            },
         };
 
         match item {
             SelectItem::Recv(Some(Ok(msg))) => {
-                if msg.is_binary() {
+                last_recv = tokio::time::Instant::now();
+                ping_sent_at = None;
+
+                // a bad frame costs only itself, the session goes on
+                let max_len = MAX_PAYLOAD_LEN.load(std::sync::atomic::Ordering::Relaxed);
+                let len = msg.as_payload().len();
+                if len > max_len {
+                    log::warn!(
+                        "Dropping a {} byte WebSocket frame, over the {} byte limit",
+                        len,
+                        max_len
+                    );
+                    continue;
+                }
+
+                let evt = if msg.is_binary() {
                     let payload = msg.into_payload();
-                    let evt = rmp_serde::from_slice::<ServerEvent>(&payload)
-                        .map_err(|e| anyhow::anyhow!("Failed to deserialize binary data: {}", e));
-                    match evt {
-                        Err(e) => {
-                            log::warn!("{}", e);
-                            continue;
-                        }
-                        Ok(ServerEvent::AudioChunk { data }) => {
-                            match opus_decoder.decode(&data, &mut opus_buffer, false) {
-                                Ok(decoded_samples) => {
-                                    let data = opus_buffer[..decoded_samples]
-                                        .iter()
-                                        .cloned()
-                                        .collect::<Vec<i16>>();
-                                    let server_event =
-                                        ServerEvent::AudioChunki16 { data, vowel: 0 };
-                                    tx.send(server_event).await.map_err(|_| {
-                                        anyhow::anyhow!(
-                                            "Failed to send opus audio chunk to channel",
-                                        )
-                                    })?;
-                                }
-                                Err(e) => {
-                                    log::warn!("Failed to decode opus audio chunk: {}", e);
-                                    continue;
-                                }
+                    crate::protocol::decode_server_event(&payload)
+                        .map_err(|e| anyhow::anyhow!("Failed to deserialize binary data: {}", e))
+                } else if let Some(text) = msg.as_text() {
+                    crate::protocol::decode_server_text_event(text)
+                } else {
+                    log::debug!("Ignoring non-data WebSocket message");
+                    continue;
+                };
+
+                match evt {
+                    Err(e) => {
+                        log::warn!("{}", e);
+                        continue;
+                    }
+                    Ok(ServerEvent::AudioChunk { data }) => {
+                        match opus_decoder.decode(&data, &mut opus_buffer, false) {
+                            Ok(decoded_samples) => {
+                                let data = opus_buffer[..decoded_samples]
+                                    .iter()
+                                    .cloned()
+                                    .collect::<Vec<i16>>();
+                                let server_event = ServerEvent::AudioChunki16 { data, vowel: 0 };
+                                tx.send(server_event).await.map_err(|_| {
+                                    anyhow::anyhow!("Failed to send opus audio chunk to channel",)
+                                })?;
                             }
-                        }
-                        Ok(ServerEvent::AudioChunkWithVowel { data, vowel }) => {
-                            match opus_decoder.decode(&data, &mut opus_buffer, false) {
-                                Ok(decoded_samples) => {
-                                    let data = opus_buffer[..decoded_samples]
-                                        .iter()
-                                        .cloned()
-                                        .collect::<Vec<i16>>();
-                                    let server_event = ServerEvent::AudioChunki16 { data, vowel };
-                                    tx.send(server_event).await.map_err(|_| {
-                                        anyhow::anyhow!(
-                                            "Failed to send opus audio chunk to channel",
-                                        )
-                                    })?;
-                                }
-                                Err(e) => {
-                                    log::warn!("Failed to decode opus audio chunk: {}", e);
-                                    continue;
-                                }
+                            Err(e) => {
+                                log::warn!("Failed to decode opus audio chunk: {}", e);
+                                continue;
                             }
                         }
-                        Ok(ServerEvent::StartAudio { text }) => {
-                            log::info!("Received StartAudio event: {}", text);
-                            opus_decoder.reset_state().map_err(|e| {
-                                anyhow::anyhow!("Failed to reset opus decoder state: {}", e)
-                            })?;
-                            tx.send(ServerEvent::StartAudio { text })
-                                .await
-                                .map_err(|e| {
-                                    anyhow::anyhow!(
-                                        "Failed to send StartAudio event to channel: {}",
-                                        e
-                                    )
+                    }
+                    Ok(ServerEvent::AudioChunkWithVowel { data, vowel }) => {
+                        match opus_decoder.decode(&data, &mut opus_buffer, false) {
+                            Ok(decoded_samples) => {
+                                let data = opus_buffer[..decoded_samples]
+                                    .iter()
+                                    .cloned()
+                                    .collect::<Vec<i16>>();
+                                let server_event = ServerEvent::AudioChunki16 { data, vowel };
+                                tx.send(server_event).await.map_err(|_| {
+                                    anyhow::anyhow!("Failed to send opus audio chunk to channel",)
                                 })?;
+                            }
+                            Err(e) => {
+                                log::warn!("Failed to decode opus audio chunk: {}", e);
+                                continue;
+                            }
                         }
-                        Ok(evt) => {
-                            tx.send(evt).await.map_err(|e| {
-                                anyhow::anyhow!("Failed to send event to channel: {}", e)
+                    }
+                    Ok(ServerEvent::StartAudio { text }) => {
+                        log::info!("Received StartAudio event: {}", text);
+                        opus_decoder.reset_state().map_err(|e| {
+                            anyhow::anyhow!("Failed to reset opus decoder state: {}", e)
+                        })?;
+                        tx.send(ServerEvent::StartAudio { text })
+                            .await
+                            .map_err(|e| {
+                                anyhow::anyhow!("Failed to send StartAudio event to channel: {}", e)
                             })?;
-                        }
                     }
-                } else {
-                    log::error!("Unexpected non-binary WebSocket message received");
-                    continue;
+                    Ok(evt) => {
+                        tx.send(evt).await.map_err(|e| {
+                            anyhow::anyhow!("Failed to send event to channel: {}", e)
+                        })?;
+                    }
                 }
             }
             SelectItem::Recv(None) => {
@@ -159,12 +200,53 @@ async fn ws_manager(
                 log::info!("WebSocket send channel closed");
                 return Ok(());
             }
+            SelectItem::Keepalive => {
+                if ping_sent_at.is_some() {
+                    log::error!("No pong within {:?}, closing WebSocket", PONG_TIMEOUT);
+                    return Err(anyhow::anyhow!("WebSocket keepalive timed out"));
+                }
+                log::debug!("Sending WebSocket ping");
+                ws.send(Message::ping(bytes::Bytes::new()))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("WebSocket ping error: {}", e))?;
+                ping_sent_at = Some(tokio::time::Instant::now());
+            }
         }
     }
 }
 
+/// Optional features reported in `ClientCommand::Hello`.
+const REPORTED_FEATURES: [(&str, bool); 7] = [
+    ("voice_interrupt", cfg!(feature = "voice_interrupt")),
+    ("wakeword", cfg!(feature = "wakeword")),
+    ("passthrough", cfg!(feature = "passthrough")),
+    ("custom_ui", cfg!(feature = "custom_ui")),
+    ("mfrc522", cfg!(feature = "mfrc522")),
+    ("exio", cfg!(feature = "exio")),
+    ("extra_server", cfg!(feature = "extra_server")),
+];
+
+fn device_hello() -> crate::protocol::ClientCommand {
+    crate::protocol::ClientCommand::Hello {
+        firmware: env!("CARGO_PKG_VERSION").to_string(),
+        display: [
+            crate::boards::DISPLAY_WIDTH as u32,
+            crate::boards::DISPLAY_HEIGHT as u32,
+        ],
+        sample_rate: crate::audio::SAMPLE_RATE,
+        features: REPORTED_FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    }
+}
+
+/// Starts the task driving `ws`. The first thing it sends is the
+/// `ClientCommand::Hello`, ahead of anything queued on the returned sender.
 async fn connect_handler(
     ws: tokio_websockets::WebSocketStream<tokio_websockets::MaybeTlsStream<tokio::net::TcpStream>>,
+    ping_interval: Option<std::time::Duration>,
 ) -> (
     tokio::sync::mpsc::Sender<SubmitItem>,
     tokio::sync::mpsc::Receiver<ServerEvent>,
@@ -172,8 +254,11 @@ async fn connect_handler(
     let (tx_ws, rx) = tokio::sync::mpsc::channel::<SubmitItem>(32);
     let (tx, rx_ws) = tokio::sync::mpsc::channel::<ServerEvent>(32);
 
+    // the channel is empty, so this can't fail
+    let _ = tx_ws.try_send(SubmitItem::JSON(device_hello()));
+
     tokio::spawn(async move {
-        if let Err(e) = ws_manager(ws, rx, tx).await {
+        if let Err(e) = ws_manager(ws, rx, tx, ping_interval).await {
             log::error!("WebSocket manager error: {}", e);
         }
     });
@@ -181,43 +266,177 @@ async fn connect_handler(
     (tx_ws, rx_ws)
 }
 
+/// How long `Server::new` waits for the handshake by default.
+pub const DEFAULT_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The server didn't complete the handshake within the connect timeout.
+#[derive(Debug)]
+pub struct ConnectTimeout(pub std::time::Duration);
+
+impl std::fmt::Display for ConnectTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no response from the server within {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ConnectTimeout {}
+
+/// Why a connect failed, for the error screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectFailure {
+    /// `ConnectTimeout`: the host is unreachable or drops the traffic.
+    TimedOut,
+    /// The host answered but nothing listens on the port.
+    Refused,
+    Other,
+}
+
+impl ConnectFailure {
+    pub fn of(e: &anyhow::Error) -> Self {
+        if e.downcast_ref::<ConnectTimeout>().is_some() {
+            return Self::TimedOut;
+        }
+        let refused = e.chain().any(|cause| {
+            let io = cause
+                .downcast_ref::<std::io::Error>()
+                .or_else(|| match cause.downcast_ref::<tokio_websockets::Error>() {
+                    Some(tokio_websockets::Error::Io(io)) => Some(io),
+                    _ => None,
+                });
+            io.is_some_and(|io| io.kind() == std::io::ErrorKind::ConnectionRefused)
+        });
+        if refused {
+            Self::Refused
+        } else {
+            Self::Other
+        }
+    }
+}
+
+#[test]
+fn test_connect_failure() {
+    let timed_out = anyhow::Error::from(ConnectTimeout(DEFAULT_CONNECT_TIMEOUT));
+    assert_eq!(ConnectFailure::of(&timed_out), ConnectFailure::TimedOut);
+
+    let refused = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused))
+        .context("Failed to reconnect");
+    assert_eq!(ConnectFailure::of(&refused), ConnectFailure::Refused);
+
+    let other = anyhow::anyhow!("invalid URI");
+    assert_eq!(ConnectFailure::of(&other), ConnectFailure::Other);
+}
+
 pub struct Server {
     pub url: String,
     pub id: String,
+    pub role: String,
+    /// Keepalive ping interval while no frames arrive, `None` disables it.
+    pub ping_interval: Option<std::time::Duration>,
+    /// Limit on each connect and reconnect handshake.
+    pub connect_timeout: std::time::Duration,
     timeout: std::time::Duration,
     tx: tokio::sync::mpsc::Sender<SubmitItem>,
     rx: tokio::sync::mpsc::Receiver<ServerEvent>,
+    /// The current socket's end was already reported by `recv`, or the
+    /// device closed it itself.
+    closed: bool,
 }
 
 const EXTRA_PARAMETERS: &str = "opus=true&vowel=true&stream_asr=true";
 
+/// Builds `{url}/{id}?...`. A non-empty `role` selects the assistant persona on
+/// servers that host several, and is sent as a percent-encoded `role` query
+/// parameter on both the first connect and every reconnect.
+fn build_url(url: &str, id: &str, role: &str, reconnect: bool) -> String {
+    let mut u = if url.ends_with("/") {
+        format!("{}{}?", url, id)
+    } else {
+        format!("{}/{}?", url, id)
+    };
+    if reconnect {
+        u.push_str("reconnect=true&");
+    }
+    u.push_str(EXTRA_PARAMETERS);
+    if !role.is_empty() {
+        u.push_str("&role=");
+        for b in role.bytes() {
+            if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+                u.push(b as char);
+            } else {
+                u.push_str(&format!("%{:02X}", b));
+            }
+        }
+    }
+    u
+}
+
+#[test]
+fn test_build_url() {
+    assert_eq!(
+        build_url("ws://host/ws", "abc", "", false),
+        "ws://host/ws/abc?opus=true&vowel=true&stream_asr=true"
+    );
+    assert_eq!(
+        build_url("ws://host/ws/", "abc", "", true),
+        "ws://host/ws/abc?reconnect=true&opus=true&vowel=true&stream_asr=true"
+    );
+    assert_eq!(
+        build_url("ws://host/ws", "abc", "tutor en", false),
+        "ws://host/ws/abc?opus=true&vowel=true&stream_asr=true&role=tutor%20en"
+    );
+}
+
+/// `base * 2^attempt`, scaled by a factor in 0.8..=1.2 picked from `random`.
+fn backoff_delay(base: std::time::Duration, attempt: u32, random: u32) -> std::time::Duration {
+    let jitter = 0.8 + 0.4 * (random as f64 / u32::MAX as f64);
+    base.saturating_mul(1 << attempt.min(16)).mul_f64(jitter)
+}
+
+#[test]
+fn test_backoff_delay() {
+    let base = std::time::Duration::from_millis(300);
+    assert_eq!(backoff_delay(base, 0, u32::MAX / 2).as_millis(), 300);
+    assert_eq!(backoff_delay(base, 1, u32::MAX / 2).as_millis(), 600);
+    assert_eq!(backoff_delay(base, 2, u32::MAX / 2).as_millis(), 1200);
+    assert_eq!(backoff_delay(base, 0, 0).as_millis(), 240);
+    assert_eq!(backoff_delay(base, 0, u32::MAX).as_millis(), 360);
+}
+
 impl Server {
-    pub async fn new(id: String, url: String) -> anyhow::Result<Self> {
-        let u = if url.ends_with("/") {
-            format!("{}{}?{}", url, id, EXTRA_PARAMETERS)
-        } else {
-            format!("{}/{}?{}", url, id, EXTRA_PARAMETERS)
-        };
+    pub async fn new(
+        id: String,
+        url: String,
+        role: String,
+        ping_interval: Option<std::time::Duration>,
+        connect_timeout: std::time::Duration,
+    ) -> anyhow::Result<Self> {
+        let u = build_url(&url, &id, &role, false);
 
-        let (ws, _resp) = tokio_websockets::ClientBuilder::new()
+        let connect = tokio_websockets::ClientBuilder::new()
             .uri(&u)?
             .add_header(
                 http::HeaderName::from_static("sec-websocket-extensions"),
                 http::HeaderValue::from_static("permessage-deflate; client_max_window_bits"),
             )?
-            .connect()
-            .await?;
+            .connect();
+        let (ws, _resp) = tokio::time::timeout(connect_timeout, connect)
+            .await
+            .map_err(|_| ConnectTimeout(connect_timeout))??;
 
         let timeout = std::time::Duration::from_secs(30);
 
-        let (tx, rx) = connect_handler(ws).await;
+        let (tx, rx) = connect_handler(ws, ping_interval).await;
 
         Ok(Self {
             id,
             url,
+            role,
+            ping_interval,
+            connect_timeout,
             timeout,
             tx,
             rx,
+            closed: false,
         })
     }
 
@@ -227,35 +446,41 @@ impl Server {
     }
 
     pub async fn reconnect(&mut self) -> anyhow::Result<()> {
-        let u = if self.url.ends_with("/") {
-            format!(
-                "{}{}?reconnect=true&{}",
-                self.url, self.id, EXTRA_PARAMETERS
-            )
-        } else {
-            format!(
-                "{}/{}?reconnect=true&{}",
-                self.url, self.id, EXTRA_PARAMETERS
-            )
-        };
+        let u = build_url(&self.url, &self.id, &self.role, true);
 
-        let (ws, _resp) = tokio_websockets::ClientBuilder::new()
+        let connect = tokio_websockets::ClientBuilder::new()
             .uri(&u)?
             .add_header(
                 http::HeaderName::from_static("sec-websocket-extensions"),
                 http::HeaderValue::from_static("permessage-deflate; client_max_window_bits"),
             )?
-            .connect()
+            .connect();
+        let (ws, _resp) = tokio::time::timeout(self.connect_timeout, connect)
             .await
+            .map_err(|_| ConnectTimeout(self.connect_timeout))?
             .map_err(|e| anyhow::anyhow!("Failed to reconnect: {}", e))?;
 
-        let (tx, rx) = connect_handler(ws).await;
+        let (tx, rx) = connect_handler(ws, self.ping_interval).await;
         self.tx = tx;
         self.rx = rx;
+        self.closed = false;
         Ok(())
     }
 
     pub async fn reconnect_with_retry(&mut self, retries: usize) -> anyhow::Result<()> {
+        self.reconnect_with_backoff(retries, std::time::Duration::from_millis(300))
+            .await
+    }
+
+    /// Retries `reconnect`, sleeping `base`, `2 * base`, `4 * base`, ... between
+    /// attempts, each randomized by ±20% so devices dropped at the same moment
+    /// don't all come back at once.
+    pub async fn reconnect_with_backoff(
+        &mut self,
+        retries: usize,
+        base: std::time::Duration,
+    ) -> anyhow::Result<()> {
+        let start = std::time::Instant::now();
         for attempt in 0..retries {
             match self.reconnect().await {
                 Ok(_) => return Ok(()),
@@ -266,14 +491,27 @@ impl Server {
                         retries,
                         e
                     );
-                    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+                    if attempt + 1 < retries {
+                        let random = unsafe { esp_idf_svc::sys::esp_random() };
+                        tokio::time::sleep(backoff_delay(base, attempt as u32, random)).await;
+                    }
                 }
             }
         }
-        Err(anyhow::anyhow!("All reconnect attempts failed"))
+        Err(anyhow::anyhow!(
+            "All {} reconnect attempts failed after {:.1}s",
+            retries,
+            start.elapsed().as_secs_f32()
+        ))
+    }
+
+    /// Whether the websocket manager task is still running.
+    pub fn is_connected(&self) -> bool {
+        !self.tx.is_closed()
     }
 
     pub async fn close(&mut self) -> anyhow::Result<()> {
+        self.closed = true;
         let _ = self.send(SubmitItem::Close).await;
         Ok(())
     }
@@ -296,23 +534,38 @@ impl Server {
         self.send(SubmitItem::JSON(cmd)).await
     }
 
+    /// Queues `ClientCommand::Cancel` without waiting, so a full channel
+    /// never holds up the local interrupt. It goes out on the current socket
+    /// even if that is replaced right after.
+    pub fn cancel(&mut self) {
+        let cmd = SubmitItem::JSON(crate::protocol::ClientCommand::Cancel);
+        if let Err(e) = self.tx.try_send(cmd) {
+            log::warn!("Failed to send cancel: {}", e);
+        }
+    }
+
     pub async fn send_client_audio_chunk(&mut self, chunk: Vec<u8>) -> anyhow::Result<()> {
         self.send(SubmitItem::AudioChunk(chunk)).await
     }
 
     pub async fn send_client_audio_chunk_i16(&mut self, chunk: Vec<i16>) -> anyhow::Result<()> {
-        let audio_buffer_u8 =
-            unsafe { std::slice::from_raw_parts(chunk.as_ptr() as *const u8, chunk.len() * 2) };
-
-        self.send_client_audio_chunk(audio_buffer_u8.to_vec()).await
+        self.send_client_audio_chunk(crate::protocol::encode_pcm_le(&chunk))
+            .await
     }
 
+    /// The next server event. When the socket ends without `close` (the server
+    /// closed it, or it failed), `Event::SERVER_CLOSED` is returned once and
+    /// later calls wait until `reconnect`.
     pub async fn recv(&mut self) -> anyhow::Result<Event> {
-        let msg = self
-            .rx
-            .recv()
-            .await
-            .ok_or_else(|| anyhow::anyhow!("WS channel closed"))?;
-        Ok(Event::ServerEvent(msg))
+        if self.closed {
+            return std::future::pending().await;
+        }
+        match self.rx.recv().await {
+            Some(msg) => Ok(Event::ServerEvent(msg)),
+            None => {
+                self.closed = true;
+                Ok(Event::Event(Event::SERVER_CLOSED))
+            }
+        }
     }
 }